@@ -0,0 +1,130 @@
+use schema::hack::{self, Node, SyntaxTree};
+
+// 命令のインデント
+const INDENT: &str = "    ";
+
+// 整形後の行
+enum Line {
+    Blank,
+    Comment {
+        indent: &'static str,
+        comment: String,
+    },
+    Code {
+        code: String,
+        comment: Option<String>,
+    },
+}
+
+// 構文木を決まった形式の Hack アセンブラに整形する
+// - ラベルは行頭、命令は 4 文字インデントする
+// - コマンドは空白を含まない標準の表記にする
+// - コメントだけの行は次のコマンドと同じインデントにする
+// - 空行の連続は1行にまとめ、ファイル先頭・末尾の空行は取り除く
+// - 連続したコマンド行の中で行末のコメントの位置を揃える
+pub fn format(tree: &SyntaxTree) -> String {
+    // コメント行のインデントを決めるために後ろから見ていく
+    let mut lines: Vec<Line> = Vec::new();
+    let mut next_indent = "";
+    for line in tree.lines.iter().rev() {
+        let line = match &line.node {
+            Node::Blank => Line::Blank,
+            Node::Comment(comment) => Line::Comment {
+                indent: next_indent,
+                comment: comment.clone(),
+            },
+            Node::Command { command, comment } => {
+                next_indent = match command {
+                    hack::Command::L(_) => "",
+                    _ => INDENT,
+                };
+                Line::Code {
+                    code: format!("{next_indent}{command}"),
+                    comment: comment.clone(),
+                }
+            }
+        };
+        let previous_is_blank = matches!(lines.last(), None | Some(Line::Blank));
+        if !(matches!(line, Line::Blank) && previous_is_blank) {
+            lines.push(line);
+        }
+    }
+    if matches!(lines.last(), Some(Line::Blank)) {
+        lines.pop();
+    }
+    lines.reverse();
+
+    let mut output = Vec::new();
+    for group in lines.chunk_by(|a, b| matches!((a, b), (Line::Code { .. }, Line::Code { .. }))) {
+        // コメントを持つ最も長い行に揃える
+        let comment_column = group
+            .iter()
+            .filter_map(|line| match line {
+                Line::Code {
+                    code,
+                    comment: Some(_),
+                } => Some(code.chars().count()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        output.extend(group.iter().map(|line| match line {
+            Line::Blank => String::new(),
+            Line::Comment { indent, comment } => format!("{indent}//{comment}"),
+            Line::Code {
+                code,
+                comment: Some(comment),
+            } => format!("{code:comment_column$} //{comment}"),
+            Line::Code {
+                code,
+                comment: None,
+            } => code.clone(),
+        }));
+    }
+    output.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let source = [
+            "",
+            "// count down",
+            "@i // counter",
+            "M = 1",
+            "",
+            "",
+            "  (LOOP)",
+            "// loop body",
+            "   @LOOP     // back",
+            "D = D - 1 ; JGT // forever",
+            "",
+        ]
+        .join("\n");
+        let tree = hack::parse_syntax_tree(&source).unwrap();
+        let formatted = format(&tree);
+
+        assert_eq!(
+            formatted,
+            [
+                "    // count down",
+                "    @i // counter",
+                "    M=1",
+                "",
+                "(LOOP)",
+                "    // loop body",
+                "    @LOOP     // back",
+                "    D=D-1;JGT // forever",
+                "",
+            ]
+            .join("\n")
+        );
+        // 整形してもコマンド列は変わらず、もう一度整形しても変わらない
+        let formatted_tree = hack::parse_syntax_tree(&formatted).unwrap();
+        assert_eq!(formatted_tree.commands(), tree.commands());
+        assert_eq!(format(&formatted_tree), formatted);
+    }
+}
@@ -0,0 +1,60 @@
+use schema::hack;
+use std::path::Path;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // --check: ファイルを書き換えず、整形が必要なファイルがあれば失敗する
+    let check = args.iter().any(|arg| arg == "--check");
+    let input_paths: Vec<&Path> = args
+        .iter()
+        .filter(|arg| *arg != "--check")
+        .map(Path::new)
+        .collect();
+    if input_paths.is_empty() {
+        anyhow::bail!("usage: asmfmt [--check] <file.asm>...");
+    }
+
+    let mut unformatted = Vec::new();
+    for input_path in input_paths {
+        let source = std::fs::read_to_string(input_path)?;
+        let tree = hack::parse_syntax_tree(&source).map_err(|errors| {
+            let errors: Vec<String> = errors
+                .0
+                .iter()
+                .map(|error| format!("{}:{error}", input_path.display()))
+                .collect();
+            anyhow::anyhow!("{}", errors.join("\n"))
+        })?;
+        let formatted = asmfmt::format(&tree);
+
+        // 整形前後でコマンド列が変わっていないことを確認する
+        let formatted_commands = hack::parse_syntax_tree(&formatted)
+            .map(|formatted_tree| formatted_tree.commands())
+            .ok();
+        if formatted_commands != Some(tree.commands()) {
+            anyhow::bail!(
+                "{}: formatting changed the commands, the file was left unchanged",
+                input_path.display()
+            );
+        }
+
+        if formatted != source {
+            if check {
+                unformatted.push(input_path.display().to_string());
+            } else {
+                std::fs::write(input_path, formatted)?;
+            }
+        }
+    }
+    if !unformatted.is_empty() {
+        anyhow::bail!("not formatted:\n{}", unformatted.join("\n"));
+    }
+    Ok(())
+}
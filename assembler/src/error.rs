@@ -0,0 +1,124 @@
+use schema::hack;
+use std::fmt;
+
+// ソースコード上の位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line_number: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssembleError {
+    // 構文エラー
+    Parse(Vec<hack::ParseError>),
+    // シンボルテーブルに無いシンボル
+    UndefinedSymbol {
+        index: usize,
+        position: Option<Position>,
+        symbol: hack::Symbol,
+    },
+    // A命令の値が 15bit に収まらない
+    ValueOutOfRange {
+        index: usize,
+        position: Option<Position>,
+        command: hack::Command,
+        value: u16,
+    },
+    // プログラムが ROM に収まらない
+    RomOverflow {
+        rom_size: usize,
+        highest_variable_address: Option<u16>,
+    },
+    // 変数が SCREEN のメモリマップに重なる
+    RamOverflow {
+        rom_size: usize,
+        highest_variable_address: u16,
+    },
+}
+
+impl AssembleError {
+    // 原因となったコマンドの位置（コマンド列中の位置）
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Self::UndefinedSymbol { index, .. } | Self::ValueOutOfRange { index, .. } => {
+                Some(*index)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Self::UndefinedSymbol { position, .. } | Self::ValueOutOfRange { position, .. } => {
+                *position
+            }
+            _ => None,
+        }
+    }
+
+    // コマンド列中の位置からソースコード上の位置を設定する
+    pub(crate) fn with_position(mut self, source_commands: &[hack::SourceCommand]) -> Self {
+        let source_position = self.index().and_then(|index| {
+            source_commands.get(index).map(|source_command| Position {
+                line_number: source_command.line_number,
+                column: source_command.column,
+            })
+        });
+        match &mut self {
+            Self::UndefinedSymbol { position, .. } | Self::ValueOutOfRange { position, .. } => {
+                *position = source_position
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(position) = self.position() {
+            write!(f, "{}:{}: ", position.line_number, position.column)?;
+        }
+        let usage = |rom_size: &usize, highest_variable_address: Option<u16>| {
+            format!(
+                "ROM size: {rom_size} words, highest RAM address used by variables: {}",
+                highest_variable_address.map_or("none".to_string(), |address| address.to_string())
+            )
+        };
+        match self {
+            Self::Parse(errors) => {
+                let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            Self::UndefinedSymbol { symbol, .. } => {
+                write!(f, "{:?} was not found in SymbolTable.", symbol)
+            }
+            Self::ValueOutOfRange { command, value, .. } => write!(
+                f,
+                "{command} resolves to {value}, which does not fit in 15 bits (max {}).",
+                hack::A_COMMAND_MAX_VALUE
+            ),
+            Self::RomOverflow {
+                rom_size,
+                highest_variable_address,
+            } => write!(
+                f,
+                "program does not fit in ROM ({} words). {}",
+                crate::machine_code::ROM_SIZE,
+                usage(rom_size, *highest_variable_address)
+            ),
+            Self::RamOverflow {
+                rom_size,
+                highest_variable_address,
+            } => write!(
+                f,
+                "variables overflow into SCREEN ({}): RAM address {highest_variable_address} is allocated. {}",
+                crate::machine_code::SCREEN_ADDRESS,
+                usage(rom_size, Some(*highest_variable_address))
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
@@ -0,0 +1,287 @@
+use crate::machine_code;
+use std::path::Path;
+
+// 機械語の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    // 1行に1命令を 16文字の 0/1 で表す (.hack)
+    Text,
+    // 1命令を big-endian の 2byte で表すバイナリイメージ (.bin)
+    Binary,
+    // Intel HEX (.hex), byte アドレス・big-endian
+    IntelHex,
+    // Logisim の ROM イメージ ("v2.0 raw" 形式, .rom)
+    Logisim,
+}
+
+const LOGISIM_HEADER: &str = "v2.0 raw";
+// Intel HEX の1レコードあたりのデータ長 (byte)
+const INTEL_HEX_RECORD_LENGTH: usize = 16;
+
+impl FileFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hack" => Some(Self::Text),
+            "bin" => Some(Self::Binary),
+            "hex" => Some(Self::IntelHex),
+            "logisim" => Some(Self::Logisim),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "hack" => Some(Self::Text),
+            "bin" => Some(Self::Binary),
+            "hex" => Some(Self::IntelHex),
+            "rom" => Some(Self::Logisim),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Text => "hack",
+            Self::Binary => "bin",
+            Self::IntelHex => "hex",
+            Self::Logisim => "rom",
+        }
+    }
+
+    pub fn write(&self, words: &[u16]) -> Vec<u8> {
+        match self {
+            Self::Text => words
+                .iter()
+                .map(|word| format!("{word:016b}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into_bytes(),
+            Self::Binary => words.iter().flat_map(|word| word.to_be_bytes()).collect(),
+            Self::IntelHex => write_intel_hex(words).into_bytes(),
+            Self::Logisim => write_logisim(words).into_bytes(),
+        }
+    }
+
+    pub fn read(&self, bytes: &[u8]) -> anyhow::Result<Vec<u16>> {
+        match self {
+            Self::Text => machine_code::parse_words(std::str::from_utf8(bytes)?),
+            Self::Binary => {
+                if !bytes.len().is_multiple_of(2) {
+                    anyhow::bail!("binary image has odd length ({} bytes).", bytes.len());
+                }
+                Ok(bytes
+                    .chunks(2)
+                    .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                    .collect())
+            }
+            Self::IntelHex => read_intel_hex(std::str::from_utf8(bytes)?),
+            Self::Logisim => read_logisim(std::str::from_utf8(bytes)?),
+        }
+    }
+}
+
+// 拡張子（Logisim の場合はヘッダ）から形式を判定して機械語を読み込む
+pub fn read_file(path: &Path) -> anyhow::Result<Vec<u16>> {
+    let bytes = std::fs::read(path)?;
+    let format = if bytes.starts_with(LOGISIM_HEADER.as_bytes()) {
+        FileFormat::Logisim
+    } else {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(FileFormat::from_extension)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}: unknown machine code format (.hack, .bin, .hex or .rom)",
+                    path.display()
+                )
+            })?
+    };
+    format.read(&bytes)
+}
+
+fn write_intel_hex(words: &[u16]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut records: Vec<String> = vec![];
+    for (index, data) in bytes.chunks(INTEL_HEX_RECORD_LENGTH).enumerate() {
+        let byte_address = index * INTEL_HEX_RECORD_LENGTH;
+        // 64KB を超える場合は拡張リニアアドレスレコードで上位アドレスを指定する
+        if byte_address.is_multiple_of(0x10000) && byte_address > 0 {
+            records.push(intel_hex_record(
+                0,
+                0x04,
+                &((byte_address >> 16) as u16).to_be_bytes(),
+            ));
+        }
+        records.push(intel_hex_record((byte_address & 0xffff) as u16, 0x00, data));
+    }
+    records.push(intel_hex_record(0, 0x01, &[]));
+    records.join("\n")
+}
+
+fn intel_hex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    bytes.push(checksum);
+    format!(
+        ":{}",
+        bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<String>()
+    )
+}
+
+fn read_intel_hex(code: &str) -> anyhow::Result<Vec<u16>> {
+    let mut memory: Vec<u8> = vec![];
+    let mut base_address: usize = 0;
+    for (index, line) in code.lines().map(str::trim).enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| anyhow::anyhow!("line {}: {message}: {line:?}", index + 1);
+        let hex = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record must start with ':'"))?;
+        if !hex.len().is_multiple_of(2) || hex.len() < 10 {
+            return Err(error("invalid record length"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error("invalid hex digit"))?;
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+        let data_length = bytes[0] as usize;
+        if bytes.len() != data_length + 5 {
+            return Err(error("data length mismatch"));
+        }
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..4 + data_length];
+        match bytes[3] {
+            0x00 => {
+                let start = base_address + address;
+                // ROM (byte アドレス) に収まらないレコードで巨大なメモリを確保しない
+                if start + data_length > machine_code::ROM_SIZE * 2 {
+                    return Err(error("address out of ROM"));
+                }
+                if memory.len() < start + data_length {
+                    memory.resize(start + data_length, 0);
+                }
+                memory[start..start + data_length].copy_from_slice(data);
+            }
+            0x01 => break,
+            0x02 if data_length == 2 => {
+                base_address = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4
+            }
+            0x04 if data_length == 2 => {
+                base_address = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16
+            }
+            0x03 | 0x05 => {} // 開始アドレスは使わない
+            _ => return Err(error("unsupported record type")),
+        }
+    }
+    if !memory.len().is_multiple_of(2) {
+        memory.push(0);
+    }
+    Ok(memory
+        .chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+        .collect())
+}
+
+// 同じ値が連続する場合は 'N*value' で表す
+fn write_logisim(words: &[u16]) -> String {
+    let mut tokens: Vec<String> = vec![];
+    let mut index = 0;
+    while index < words.len() {
+        let run = words[index..]
+            .iter()
+            .take_while(|word| **word == words[index])
+            .count();
+        if run >= 4 {
+            tokens.push(format!("{run}*{:x}", words[index]));
+        } else {
+            tokens.extend((0..run).map(|_| format!("{:x}", words[index])));
+        }
+        index += run;
+    }
+    std::iter::once(LOGISIM_HEADER.to_string())
+        .chain(tokens.chunks(8).map(|line| line.join(" ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+fn read_logisim(code: &str) -> anyhow::Result<Vec<u16>> {
+    let mut lines = code.lines();
+    if lines.next().map(str::trim) != Some(LOGISIM_HEADER) {
+        anyhow::bail!("Logisim image must start with '{LOGISIM_HEADER}'.");
+    }
+    let mut words = vec![];
+    for token in lines
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+    {
+        let (count, value) = match token.split_once('*') {
+            Some((count, value)) => (count.parse::<usize>()?, value),
+            None => (1, token),
+        };
+        let value = u16::from_str_radix(value, 16)
+            .map_err(|e| anyhow::anyhow!("invalid Logisim word {token:?}: {e}"))?;
+        words.extend(std::iter::repeat_n(value, count));
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let words: Vec<u16> = [0x0010, 0xec10, 0, 0, 0, 0, 0, 0xe307]
+            .into_iter()
+            .chain((0..machine_code::ROM_SIZE as u32 - 8).map(|i| (i * 7) as u16))
+            .collect();
+        for format in [
+            FileFormat::Text,
+            FileFormat::Binary,
+            FileFormat::IntelHex,
+            FileFormat::Logisim,
+        ] {
+            assert_eq!(format.read(&format.write(&words)).unwrap(), words);
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let words = [0x0010, 0xec10, 0, 0, 0, 0];
+        assert_eq!(
+            FileFormat::Binary.write(&words)[..4],
+            [0x00, 0x10, 0xec, 0x10]
+        );
+        assert_eq!(
+            String::from_utf8(FileFormat::IntelHex.write(&words)).unwrap(),
+            ":0C0000000010EC100000000000000000E8\n:00000001FF"
+        );
+        assert_eq!(
+            String::from_utf8(FileFormat::Logisim.write(&words)).unwrap(),
+            "v2.0 raw\n10 ec10 4*0\n"
+        );
+        assert!(FileFormat::IntelHex
+            .read(b":0C0000000010EC100000000000000000E9")
+            .is_err());
+        // ROM の外を指す拡張リニアアドレス
+        assert!(FileFormat::IntelHex
+            .read(b":02000004FFFFFC\n:020000000010EE\n:00000001FF")
+            .is_err());
+    }
+}
@@ -0,0 +1,86 @@
+mod error;
+pub mod file_format;
+pub mod linker;
+pub mod lint;
+pub mod listing;
+pub mod machine_code;
+pub mod macro_expander;
+pub mod object;
+pub mod optimizer;
+pub mod symbol_table;
+
+pub use error::{AssembleError, Position};
+
+use schema::hack;
+use symbol_table::SymbolTable;
+
+// アセンブルの結果
+#[derive(Debug, Clone)]
+pub struct Assembled {
+    pub words: Vec<u16>,
+    pub symbol_table: SymbolTable,
+}
+
+// アセンブラ言語のソースコードを機械語に変換する
+pub fn assemble(source: &str) -> Result<Assembled, AssembleError> {
+    let source_commands = hack::parse_with_source(source.to_string())
+        .map_err(|errors| AssembleError::Parse(errors.0))?;
+    assemble_source_commands(&source_commands)
+}
+
+// 構文解析済みのコマンド列を機械語に変換する
+// エラーの位置は各コマンドの行・列で報告される
+pub fn assemble_source_commands(
+    source_commands: &[hack::SourceCommand],
+) -> Result<Assembled, AssembleError> {
+    let commands: Vec<hack::Command> = source_commands
+        .iter()
+        .map(|source_command| source_command.command.clone())
+        .collect();
+    let symbol_table = SymbolTable::new(&commands);
+    let words = machine_code::construct(&symbol_table, commands)
+        .map_err(|error| error.with_position(source_commands))?
+        .into_iter()
+        .map(machine_code::Instruction::into_word)
+        .collect();
+    Ok(Assembled {
+        words,
+        symbol_table,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_errors() {
+        let assembled = assemble("@i\nM=1\n(END)\n@END\n0;JMP").unwrap();
+        assert_eq!(assembled.words, vec![16, 0xefc8, 2, 0xea87]);
+        assert_eq!(
+            assembled.symbol_table.get(&hack::Symbol("END".to_string())),
+            Some(2)
+        );
+
+        match assemble("@1\nD=X\nM=") {
+            Err(AssembleError::Parse(errors)) => {
+                let positions: Vec<_> = errors
+                    .iter()
+                    .map(|error| (error.line_number, error.column))
+                    .collect();
+                assert_eq!(positions, vec![(2, 3), (3, 3)]);
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        let error = assemble(&"D=0\n".repeat(32769)).unwrap_err();
+        assert_eq!(
+            error,
+            AssembleError::RomOverflow {
+                rom_size: 32769,
+                highest_variable_address: None
+            }
+        );
+        assert_eq!(error.position(), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::machine_code::{ROM_SIZE, SCREEN_ADDRESS};
+use crate::object::ObjectFile;
+use crate::symbol_table::SymbolTable;
+use crate::Assembled;
+use schema::hack;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    // 複数のオブジェクトファイルで同じラベルが定義されている
+    DuplicateSymbol {
+        symbol: hack::Symbol,
+        first_module: String,
+        second_module: String,
+    },
+    // ジャンプ先として参照されたシンボルがどのオブジェクトファイルでも定義されていない
+    UndefinedSymbol {
+        symbol: hack::Symbol,
+        module: String,
+    },
+    // 結合したプログラムが ROM に収まらない
+    RomOverflow {
+        rom_size: usize,
+    },
+    // 変数が SCREEN のメモリマップに重なる
+    RamOverflow {
+        highest_variable_address: u16,
+    },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateSymbol {
+                symbol,
+                first_module,
+                second_module,
+            } => write!(
+                f,
+                "duplicate symbol '{}': defined in {first_module} and {second_module}",
+                symbol.get()
+            ),
+            Self::UndefinedSymbol { symbol, module } => write!(
+                f,
+                "undefined symbol '{}': referenced as a jump target in {module}",
+                symbol.get()
+            ),
+            Self::RomOverflow { rom_size } => write!(
+                f,
+                "program does not fit in ROM ({ROM_SIZE} words). ROM size: {rom_size} words"
+            ),
+            Self::RamOverflow {
+                highest_variable_address,
+            } => write!(
+                f,
+                "variables overflow into SCREEN ({SCREEN_ADDRESS}): RAM address {highest_variable_address} is allocated"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkErrors(pub Vec<LinkError>);
+
+impl fmt::Display for LinkErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", errors.join("\n"))
+    }
+}
+
+impl std::error::Error for LinkErrors {}
+
+// オブジェクトファイルを与えられた順に ROM へ配置して1つのプログラムにする
+// 変数は全オブジェクトファイルを通して登場順に RAM アドレスを割り当てる
+pub fn link(objects: &[ObjectFile]) -> Result<Assembled, LinkErrors> {
+    let rom_size: usize = objects.iter().map(|object| object.words.len()).sum();
+    if rom_size > ROM_SIZE {
+        return Err(LinkErrors(vec![LinkError::RomOverflow { rom_size }]));
+    }
+
+    let mut errors = Vec::new();
+
+    // 各オブジェクトファイルの先頭 ROM アドレスを決め、ラベルを全体のアドレスにする
+    let mut labels: HashMap<&hack::Symbol, (u16, &str)> = HashMap::new();
+    let mut base_address: u16 = 0;
+    for object in objects {
+        for (symbol, address) in &object.exports {
+            match labels.get(symbol) {
+                Some((_, first_module)) => errors.push(LinkError::DuplicateSymbol {
+                    symbol: symbol.clone(),
+                    first_module: first_module.to_string(),
+                    second_module: object.name.clone(),
+                }),
+                None => {
+                    labels.insert(symbol, (base_address + address, &object.name));
+                }
+            }
+        }
+        base_address += object.words.len() as u16;
+    }
+
+    for object in objects {
+        for import in object.imports.iter().filter(|import| import.jump) {
+            if !labels.contains_key(&import.symbol) {
+                errors.push(LinkError::UndefinedSymbol {
+                    symbol: import.symbol.clone(),
+                    module: object.name.clone(),
+                });
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(LinkErrors(errors));
+    }
+
+    let mut symbol_table = SymbolTable::with_labels(
+        labels
+            .iter()
+            .map(|(symbol, (address, _))| ((*symbol).clone(), *address)),
+    );
+    let mut words = Vec::with_capacity(rom_size);
+    for object in objects {
+        let mut object_words = object.words.clone();
+        for relocation in &object.relocations {
+            object_words[relocation.address as usize] = symbol_table.resolve(&relocation.symbol);
+        }
+        words.extend(object_words);
+    }
+
+    if let Some(address) = symbol_table
+        .highest_variable_address()
+        .filter(|address| *address >= SCREEN_ADDRESS)
+    {
+        return Err(LinkErrors(vec![LinkError::RamOverflow {
+            highest_variable_address: address,
+        }]));
+    }
+
+    Ok(Assembled {
+        words,
+        symbol_table,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(name: &str, source: &str) -> ObjectFile {
+        ObjectFile::assemble(name, &hack::parse(source.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_link() {
+        let main = "@i\nM=1\n(Main.loop)\n@Sys.halt\n0;JMP\n@Main.loop\n0;JMP";
+        let sys = "(Sys.halt)\n@j\nM=0\n@Sys.halt\n0;JMP\n@i\nD=M";
+
+        // オブジェクトファイルの書き出しと読み込み
+        let main_object = object("Main", main);
+        assert_eq!(
+            ObjectFile::from_text(&main_object.to_text()).unwrap(),
+            main_object
+        );
+
+        // ファイルを連結してアセンブルした結果と一致する
+        let linked = link(&[main_object, object("Sys", sys)]).unwrap();
+        let assembled = crate::assemble(&format!("{main}\n{sys}")).unwrap();
+        assert_eq!(linked.words, assembled.words);
+        assert_eq!(
+            linked.symbol_table.get(&hack::Symbol::new("Sys.halt")),
+            Some(6)
+        );
+        assert_eq!(linked.symbol_table.get(&hack::Symbol::new("j")), Some(17));
+
+        let errors =
+            link(&[object("A", "(X)\n@Y\n0;JMP"), object("B", "(X)\n@X\n0;JMP")]).unwrap_err();
+        assert_eq!(
+            errors,
+            LinkErrors(vec![
+                LinkError::DuplicateSymbol {
+                    symbol: hack::Symbol::new("X"),
+                    first_module: "A".to_string(),
+                    second_module: "B".to_string(),
+                },
+                LinkError::UndefinedSymbol {
+                    symbol: hack::Symbol::new("Y"),
+                    module: "A".to_string(),
+                },
+            ])
+        );
+    }
+}
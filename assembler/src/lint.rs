@@ -0,0 +1,273 @@
+use crate::symbol_table::{SymbolKind, SymbolTable, PRE_DEFINED_SYMBOLS};
+use schema::hack;
+use std::collections::HashMap;
+use std::fmt;
+
+// 警告（index はコマンド列中の位置）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub index: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    // 同じラベルが2回以上定義されている（後の定義で上書きされる）
+    DuplicateLabel {
+        label: hack::Symbol,
+        first_index: usize,
+    },
+    // 定義済みシンボルと同名のラベルが定義されている
+    ShadowedPreDefinedSymbol(hack::Symbol),
+    // ジャンプ先として使われているシンボルがラベルとして定義されておらず、変数として割り当てられる
+    UndefinedLabel {
+        symbol: hack::Symbol,
+        suggestion: Option<hack::Symbol>,
+    },
+    // 無条件ジャンプの後にあり、ラベルが無いため到達できない命令
+    UnreachableCode {
+        count: usize,
+    },
+    // A レジスタへの書き込みとジャンプを同時に行う C 命令
+    WriteAAndJump,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateLabel { label, .. } => write!(
+                f,
+                "label '{}' is defined more than once; the last definition wins",
+                label.get()
+            ),
+            Self::ShadowedPreDefinedSymbol(label) => write!(
+                f,
+                "label '{}' shadows the predefined symbol",
+                label.get()
+            ),
+            Self::UndefinedLabel { symbol, suggestion } => {
+                write!(
+                    f,
+                    "jump target '{}' is not defined as a label and will be allocated as a variable",
+                    symbol.get()
+                )?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean '{}'?)", suggestion.get())?;
+                }
+                Ok(())
+            }
+            Self::UnreachableCode { count } => write!(
+                f,
+                "{count} instruction(s) after an unconditional jump can never be reached"
+            ),
+            Self::WriteAAndJump => write!(
+                f,
+                "instruction writes A and jumps; the jump uses the old A value and the target is lost"
+            ),
+        }
+    }
+}
+
+pub fn lint(commands: &[hack::Command]) -> Vec<Warning> {
+    let mut warnings = [
+        check_labels(commands),
+        check_undefined_labels(commands),
+        check_unreachable_code(commands),
+        check_write_a_and_jump(commands),
+    ]
+    .concat();
+    warnings.sort_by_key(|warning| warning.index);
+    warnings
+}
+
+fn check_labels(commands: &[hack::Command]) -> Vec<Warning> {
+    let mut defined: HashMap<&hack::Symbol, usize> = HashMap::new();
+    let mut warnings = vec![];
+    for (index, command) in commands.iter().enumerate() {
+        let hack::Command::L(label) = command else {
+            continue;
+        };
+        if PRE_DEFINED_SYMBOLS
+            .iter()
+            .any(|(symbol, _)| *symbol == label.get())
+        {
+            warnings.push(Warning {
+                index,
+                kind: WarningKind::ShadowedPreDefinedSymbol(label.clone()),
+            });
+        }
+        if let Some(first_index) = defined.get(label) {
+            warnings.push(Warning {
+                index,
+                kind: WarningKind::DuplicateLabel {
+                    label: label.clone(),
+                    first_index: *first_index,
+                },
+            });
+        } else {
+            defined.insert(label, index);
+        }
+    }
+    warnings
+}
+
+fn check_undefined_labels(commands: &[hack::Command]) -> Vec<Warning> {
+    let symbol_table = SymbolTable::new(commands);
+    let labels = symbol_table.symbols(SymbolKind::Label);
+    commands
+        .windows(2)
+        .enumerate()
+        .filter_map(|(index, window)| match window {
+            [hack::Command::A(hack::ACommand::Symbol(symbol)), hack::Command::C(hack::CCommand {
+                jump: Some(_),
+                ..
+            })] if symbol_table.kind(symbol) == Some(SymbolKind::Variable) => Some(Warning {
+                index,
+                kind: WarningKind::UndefinedLabel {
+                    symbol: symbol.clone(),
+                    suggestion: labels
+                        .iter()
+                        .map(|(label, _)| (edit_distance(label.get(), symbol.get()), *label))
+                        .filter(|(distance, _)| *distance <= 2)
+                        .min()
+                        .map(|(_, label)| label.clone()),
+                },
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_unreachable_code(commands: &[hack::Command]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut iter = commands.iter().enumerate().peekable();
+    while let Some((_, command)) = iter.next() {
+        if !matches!(
+            command,
+            hack::Command::C(hack::CCommand {
+                jump: Some(hack::JumpMnemonic::JMP),
+                ..
+            })
+        ) {
+            continue;
+        }
+        // 次のラベルまでの命令には到達できない
+        let mut unreachable = vec![];
+        while let Some((index, _)) =
+            iter.next_if(|(_, command)| !matches!(command, hack::Command::L(_)))
+        {
+            unreachable.push(index);
+        }
+        if let Some(first_index) = unreachable.first() {
+            warnings.push(Warning {
+                index: *first_index,
+                kind: WarningKind::UnreachableCode {
+                    count: unreachable.len(),
+                },
+            });
+        }
+    }
+    warnings
+}
+
+fn check_write_a_and_jump(commands: &[hack::Command]) -> Vec<Warning> {
+    commands
+        .iter()
+        .enumerate()
+        .filter_map(|(index, command)| match command {
+            hack::Command::C(hack::CCommand {
+                dest:
+                    Some(
+                        hack::DestMnemonic::A
+                        | hack::DestMnemonic::AM
+                        | hack::DestMnemonic::AD
+                        | hack::DestMnemonic::AMD,
+                    ),
+                jump: Some(jump),
+                ..
+            }) if *jump != hack::JumpMnemonic::Null => Some(Warning {
+                index,
+                kind: WarningKind::WriteAAndJump,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// レーベンシュタイン距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let commands = hack::parse(
+            [
+                "(LOOP)",   // 0
+                "@LOPO",    // 1
+                "D;JGT",    // 2
+                "@LOOP",    // 3
+                "0;JMP",    // 4
+                "D=M",      // 5 到達不能
+                "M=D",      // 6 到達不能
+                "(LOOP)",   // 7 二重定義
+                "(SCREEN)", // 8 定義済みシンボル
+                "@counter", // 9
+                "A=M;JNE",  // 10 ジャンプ先は変数 counter, A への書き込み
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let warnings: Vec<(usize, WarningKind)> = lint(&commands)
+            .into_iter()
+            .map(|warning| (warning.index, warning.kind))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    1,
+                    WarningKind::UndefinedLabel {
+                        symbol: hack::Symbol::new("LOPO"),
+                        suggestion: Some(hack::Symbol::new("LOOP")),
+                    }
+                ),
+                (5, WarningKind::UnreachableCode { count: 2 }),
+                (
+                    7,
+                    WarningKind::DuplicateLabel {
+                        label: hack::Symbol::new("LOOP"),
+                        first_index: 0
+                    }
+                ),
+                (
+                    8,
+                    WarningKind::ShadowedPreDefinedSymbol(hack::Symbol::new("SCREEN"))
+                ),
+                (
+                    9,
+                    WarningKind::UndefinedLabel {
+                        symbol: hack::Symbol::new("counter"),
+                        suggestion: None,
+                    }
+                ),
+                (10, WarningKind::WriteAAndJump),
+            ]
+        );
+    }
+}
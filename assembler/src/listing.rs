@@ -0,0 +1,89 @@
+use crate::symbol_table::{SymbolKind, SymbolTable};
+use schema::hack;
+
+// 各行に ROMアドレス, 機械語, 元のソースコードの行を並べたリストを生成する
+pub fn generate_listing(source_commands: &[hack::SourceCommand], words: &[u16]) -> String {
+    let mut words = words.iter();
+    let mut rom_address: u16 = 0;
+    source_commands
+        .iter()
+        .map(|source_command| {
+            let source = format!(
+                "{:>5}: {}",
+                source_command.line_number, source_command.source
+            );
+            match source_command.command {
+                hack::Command::L(_) => format!("{rom_address:>5}  {:16}  {source}", ""),
+                _ => {
+                    let line = format!(
+                        "{rom_address:>5}  {}  {source}",
+                        words
+                            .next()
+                            .map(|word| format!("{word:016b}"))
+                            .unwrap_or_default()
+                    );
+                    rom_address += 1;
+                    line
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// ラベルのROMアドレス, 変数及び使用された定義済みシンボルのRAMアドレスの一覧を生成する
+pub fn generate_symbol_map(symbol_table: &SymbolTable) -> String {
+    let section = |title: &str, memory: &str, symbols: Vec<(&hack::Symbol, u16)>| {
+        std::iter::once(format!("[{title}]"))
+            .chain(
+                symbols
+                    .into_iter()
+                    .map(|(symbol, address)| format!("{memory} {address:>5}  {}", symbol.get())),
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    [
+        section("labels", "ROM", symbol_table.symbols(SymbolKind::Label)),
+        section(
+            "variables",
+            "RAM",
+            symbol_table.symbols(SymbolKind::Variable),
+        ),
+        section(
+            "predefined symbols",
+            "RAM",
+            symbol_table.used_pre_defined_symbols(),
+        ),
+    ]
+    .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_and_symbol_map() {
+        let source = "// count down\n@i\nM=1 // init\n(LOOP)\n  @LOOP\n  0;JMP";
+        let source_commands = hack::parse_with_source(source.to_string()).unwrap();
+        let assembled = crate::assemble_source_commands(&source_commands).unwrap();
+        let symbol_table = &assembled.symbol_table;
+
+        assert_eq!(
+            generate_listing(&source_commands, &assembled.words),
+            [
+                "    0  0000000000010000      2: @i",
+                "    1  1110111111001000      3: M=1 // init",
+                "    2                        4: (LOOP)",
+                "    2  0000000000000010      5: @LOOP",
+                "    3  1110101010000111      6: 0;JMP",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            generate_symbol_map(symbol_table),
+            "[labels]\nROM     2  LOOP\n\n[variables]\nRAM    16  i\n\n[predefined symbols]"
+        );
+    }
+}
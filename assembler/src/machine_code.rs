@@ -1,10 +1,16 @@
+use crate::error::AssembleError;
 use crate::symbol_table::SymbolTable;
 use schema::hack::*;
 
+// ROM の容量と SCREEN の先頭 (変数はこれより前に割り当てられなければならない)
+pub use schema::hack::{ROM_SIZE, SCREEN_ADDRESS};
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
     A(u16),
     C {
+        // 拡張 ALU のシフト命令 (101a cccc ccdd djjj) であるか
+        shift: bool,
         a: bool,
         comp: [bool; 6],
         dest: [bool; 3],
@@ -55,9 +61,41 @@ fn a_comp_bit(mnemonic: CompMnemonic) -> (bool, [bool; 6]) {
         CompMnemonic::MMinusD => (true, [false, false, false, true, true, true]),
         CompMnemonic::DAndM => (true, [false, false, false, false, false, false]),
         CompMnemonic::DOrM => (true, [false, true, false, true, false, true]),
+        // シフト命令: c1 が左シフト、c2 が D を選ぶ
+        #[cfg(feature = "shift")]
+        CompMnemonic::DShiftLeft => (false, [true, true, false, false, false, false]),
+        #[cfg(feature = "shift")]
+        CompMnemonic::AShiftLeft => (false, [true, false, false, false, false, false]),
+        #[cfg(feature = "shift")]
+        CompMnemonic::MShiftLeft => (true, [true, false, false, false, false, false]),
+        #[cfg(feature = "shift")]
+        CompMnemonic::DShiftRight => (false, [false, true, false, false, false, false]),
+        #[cfg(feature = "shift")]
+        CompMnemonic::AShiftRight => (false, [false, false, false, false, false, false]),
+        #[cfg(feature = "shift")]
+        CompMnemonic::MShiftRight => (true, [false, false, false, false, false, false]),
     }
 }
 
+// 拡張 ALU のシフト命令か
+fn is_shift(mnemonic: &CompMnemonic) -> bool {
+    #[cfg(feature = "shift")]
+    return matches!(
+        mnemonic,
+        CompMnemonic::DShiftLeft
+            | CompMnemonic::AShiftLeft
+            | CompMnemonic::MShiftLeft
+            | CompMnemonic::DShiftRight
+            | CompMnemonic::AShiftRight
+            | CompMnemonic::MShiftRight
+    );
+    #[cfg(not(feature = "shift"))]
+    return {
+        let _ = mnemonic;
+        false
+    };
+}
+
 fn jump_bit(mnemonic: JumpMnemonic) -> [bool; 3] {
     match mnemonic {
         JumpMnemonic::Null => [false, false, false],
@@ -71,6 +109,97 @@ fn jump_bit(mnemonic: JumpMnemonic) -> [bool; 3] {
     }
 }
 
+const DEST_MNEMONICS: [DestMnemonic; 8] = [
+    DestMnemonic::Null,
+    DestMnemonic::M,
+    DestMnemonic::D,
+    DestMnemonic::MD,
+    DestMnemonic::A,
+    DestMnemonic::AM,
+    DestMnemonic::AD,
+    DestMnemonic::AMD,
+];
+
+const COMP_MNEMONICS: &[CompMnemonic] = &[
+    CompMnemonic::Zero,
+    CompMnemonic::One,
+    CompMnemonic::MinusOne,
+    CompMnemonic::D,
+    CompMnemonic::A,
+    CompMnemonic::NegateD,
+    CompMnemonic::NegateA,
+    CompMnemonic::MinusD,
+    CompMnemonic::MinusA,
+    CompMnemonic::DPlusOne,
+    CompMnemonic::APlusOne,
+    CompMnemonic::DMinusOne,
+    CompMnemonic::AMinusOne,
+    CompMnemonic::DPlusA,
+    CompMnemonic::DMinusA,
+    CompMnemonic::AMinusD,
+    CompMnemonic::DAndA,
+    CompMnemonic::DOrA,
+    CompMnemonic::M,
+    CompMnemonic::NegateM,
+    CompMnemonic::MinusM,
+    CompMnemonic::MPlusOne,
+    CompMnemonic::MMinusOne,
+    CompMnemonic::DPlusM,
+    CompMnemonic::DMinusM,
+    CompMnemonic::MMinusD,
+    CompMnemonic::DAndM,
+    CompMnemonic::DOrM,
+    #[cfg(feature = "shift")]
+    CompMnemonic::DShiftLeft,
+    #[cfg(feature = "shift")]
+    CompMnemonic::AShiftLeft,
+    #[cfg(feature = "shift")]
+    CompMnemonic::MShiftLeft,
+    #[cfg(feature = "shift")]
+    CompMnemonic::DShiftRight,
+    #[cfg(feature = "shift")]
+    CompMnemonic::AShiftRight,
+    #[cfg(feature = "shift")]
+    CompMnemonic::MShiftRight,
+];
+
+const JUMP_MNEMONICS: [JumpMnemonic; 8] = [
+    JumpMnemonic::Null,
+    JumpMnemonic::JGT,
+    JumpMnemonic::JEQ,
+    JumpMnemonic::JGE,
+    JumpMnemonic::JLT,
+    JumpMnemonic::JNE,
+    JumpMnemonic::JLE,
+    JumpMnemonic::JMP,
+];
+
+// dest_bit の逆変換 (000 は dest 無し)
+fn dest_mnemonic(bits: [bool; 3]) -> Option<DestMnemonic> {
+    DEST_MNEMONICS
+        .into_iter()
+        .skip(1)
+        .find(|mnemonic| dest_bit(mnemonic.clone()) == bits)
+}
+
+// a_comp_bit の逆変換 (対応するニーモニックが無いビット列は None)
+fn comp_mnemonic(shift: bool, a: bool, bits: [bool; 6]) -> Option<CompMnemonic> {
+    COMP_MNEMONICS
+        .iter()
+        .find(|mnemonic| {
+            is_shift(mnemonic) == shift && a_comp_bit((*mnemonic).clone()) == (a, bits)
+        })
+        .cloned()
+}
+
+// jump_bit の逆変換 (000 は jump 無し)
+fn jump_mnemonic(bits: [bool; 3]) -> Option<JumpMnemonic> {
+    JUMP_MNEMONICS
+        .into_iter()
+        .skip(1)
+        .find(|mnemonic| jump_bit(mnemonic.clone()) == bits)
+}
+
 fn bit_to_char(bit: bool) -> char {
     if bit {
         '1'
@@ -84,44 +213,128 @@ fn bits_to_string(bits: impl Iterator<Item = bool>) -> String {
 }
 
 impl Instruction {
-    fn from_a_command(a_command: ACommand, symbol_table: &SymbolTable) -> anyhow::Result<Self> {
-        let address = match a_command {
-            ACommand::Address(address) => address,
-            ACommand::Symbol(symbol) => symbol_table
-                .get(&symbol)
-                .ok_or_else(|| anyhow::anyhow!("{:?} was not found in SymbolTable.", symbol))?,
+    fn from_a_command(
+        index: usize,
+        a_command: ACommand,
+        symbol_table: &SymbolTable,
+    ) -> Result<Self, AssembleError> {
+        let address = match &a_command {
+            ACommand::Address(address) => *address,
+            ACommand::Symbol(symbol) => {
+                symbol_table
+                    .get(symbol)
+                    .ok_or_else(|| AssembleError::UndefinedSymbol {
+                        index,
+                        position: None,
+                        symbol: symbol.clone(),
+                    })?
+            }
         };
+        // A命令で指定できる値は 15bit まで
+        if address > A_COMMAND_MAX_VALUE {
+            return Err(AssembleError::ValueOutOfRange {
+                index,
+                position: None,
+                command: Command::A(a_command),
+                value: address,
+            });
+        }
         Ok(Self::A(address))
     }
-    fn from_c_command(c_command: CCommand) -> Self {
+    pub(crate) fn from_c_command(c_command: CCommand) -> Self {
         let dest_bit = c_command
             .dest
             .map(dest_bit)
             .unwrap_or([false, false, false]);
 
+        let shift = is_shift(&c_command.comp);
         let (a_bit, comp_bit) = a_comp_bit(c_command.comp);
         let jump_bit = c_command
             .jump
             .map(jump_bit)
             .unwrap_or([false, false, false]);
         Self::C {
+            shift,
             a: a_bit,
             comp: comp_bit,
             dest: dest_bit,
             jump: jump_bit,
         }
     }
+    // 16bit の機械語命令から変換する
+    pub fn from_word(word: u16) -> Self {
+        if word & 0x8000 == 0 {
+            return Self::A(word);
+        }
+        let bit = |n: u16| word & (1 << n) != 0;
+        Self::C {
+            // シフト命令は 101 で始まる（機能が無効であれば通常の C 命令として扱う）
+            shift: cfg!(feature = "shift") && word & 0xe000 == 0xa000,
+            a: bit(12),
+            comp: [bit(11), bit(10), bit(9), bit(8), bit(7), bit(6)],
+            dest: [bit(5), bit(4), bit(3)],
+            jump: [bit(2), bit(1), bit(0)],
+        }
+    }
+    // アセンブラコマンドに逆変換する（シンボルは復元されない）
+    pub fn try_into_command(self) -> anyhow::Result<Command> {
+        Ok(match self {
+            Self::A(address) => Command::A(ACommand::Address(address)),
+            Self::C {
+                shift,
+                a,
+                comp,
+                dest,
+                jump,
+            } => Command::C(CCommand {
+                dest: dest_mnemonic(dest),
+                comp: comp_mnemonic(shift, a, comp).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "invalid comp encoding: a={} c={}",
+                        bit_to_char(a),
+                        bits_to_string(comp.into_iter())
+                    )
+                })?,
+                jump: jump_mnemonic(jump),
+            }),
+        })
+    }
+    // 16bit の機械語命令に変換する
+    pub fn into_word(self) -> u16 {
+        let bits_to_word = |bits: &[bool]| {
+            bits.iter()
+                .fold(0u16, |word, bit| (word << 1) | u16::from(*bit))
+        };
+        match self {
+            Self::A(address) => address,
+            Self::C {
+                shift,
+                a,
+                comp,
+                dest,
+                jump,
+            } => {
+                (if shift { 0b101 } else { 0b111 }) << 13
+                    | u16::from(a) << 12
+                    | bits_to_word(&comp) << 6
+                    | bits_to_word(&dest) << 3
+                    | bits_to_word(&jump)
+            }
+        }
+    }
     pub fn into_bit_string(self) -> String {
         match self {
             Self::A(address) => format!("0{:015b}", address),
             Self::C {
+                shift,
                 a,
                 comp,
                 dest,
                 jump,
             } => {
                 format!(
-                    "111{}{}{}{}",
+                    "{}{}{}{}{}",
+                    if shift { "101" } else { "111" },
                     bit_to_char(a),
                     bits_to_string(comp.into_iter()),
                     bits_to_string(dest.into_iter()),
@@ -135,15 +348,63 @@ impl Instruction {
 pub fn construct(
     symbol_table: &SymbolTable,
     commands: Vec<Command>,
-) -> anyhow::Result<Vec<Instruction>> {
+) -> Result<Vec<Instruction>, AssembleError> {
+    check_memory_usage(symbol_table, &commands)?;
     commands
         .into_iter()
-        .filter_map(|command| match command {
-            Command::A(a_command) => Some(Instruction::from_a_command(a_command, symbol_table)),
+        .enumerate()
+        .filter_map(|(index, command)| match command {
+            Command::A(a_command) => {
+                Some(Instruction::from_a_command(index, a_command, symbol_table))
+            }
             Command::C(c_command) => Some(Ok(Instruction::from_c_command(c_command))),
             Command::L(_) => None,
         })
-        .collect::<anyhow::Result<Vec<_>>>()
+        .collect()
+}
+
+// プログラムが ROM に収まること、変数が SCREEN のメモリマップと重ならないことを確認する
+fn check_memory_usage(
+    symbol_table: &SymbolTable,
+    commands: &[Command],
+) -> Result<(), AssembleError> {
+    let rom_size = commands
+        .iter()
+        .filter(|command| !matches!(command, Command::L(_)))
+        .count();
+    let highest_variable_address = symbol_table.highest_variable_address();
+    if rom_size > ROM_SIZE {
+        return Err(AssembleError::RomOverflow {
+            rom_size,
+            highest_variable_address,
+        });
+    }
+    if let Some(address) = highest_variable_address.filter(|address| *address >= SCREEN_ADDRESS) {
+        return Err(AssembleError::RamOverflow {
+            rom_size,
+            highest_variable_address: address,
+        });
+    }
+    Ok(())
+}
+
+// .hack ファイルの各行（16文字の 0/1 文字列）を機械語命令として読み込む
+pub fn parse_words(code: &str) -> anyhow::Result<Vec<u16>> {
+    code.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| {
+            if line.len() != 16 || !line.chars().all(|c| c == '0' || c == '1') {
+                anyhow::bail!(
+                    "line {}: {:?} is not a 16-bit binary word.",
+                    index + 1,
+                    line
+                );
+            }
+            Ok(u16::from_str_radix(line, 2)?)
+        })
+        .collect()
 }
 
 pub fn generate(instructions: Vec<Instruction>) -> String {
@@ -153,3 +414,114 @@ pub fn generate(instructions: Vec<Instruction>) -> String {
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_round_trip() {
+        let symbol_table = SymbolTable::new(&[]);
+        let instructions = construct(
+            &symbol_table,
+            vec![
+                Command::A(ACommand::Address(12345)),
+                Command::C(CCommand {
+                    dest: Some(DestMnemonic::AM),
+                    comp: CompMnemonic::DPlusM,
+                    jump: Some(JumpMnemonic::JLE),
+                }),
+            ],
+        )
+        .unwrap();
+        let code = generate(instructions.clone());
+        assert_eq!(code, "0011000000111001\n1111000010101110");
+        assert_eq!(
+            parse_words(&code).unwrap(),
+            instructions
+                .into_iter()
+                .map(Instruction::into_word)
+                .collect::<Vec<_>>()
+        );
+        assert!(parse_words("0101").is_err());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let c_command = Command::C(CCommand {
+            dest: None,
+            comp: CompMnemonic::Zero,
+            jump: None,
+        });
+
+        let commands = vec![c_command.clone(); ROM_SIZE + 1];
+        let error = construct(&SymbolTable::new(&commands), commands).unwrap_err();
+        assert!(error.to_string().contains("ROM size: 32769 words"));
+
+        let commands: Vec<Command> = (0..16384 - 16 + 1)
+            .map(|i| Command::A(ACommand::Symbol(Symbol(format!("var{i}")))))
+            .collect();
+        let error = construct(&SymbolTable::new(&commands), commands).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("highest RAM address used by variables: 16384"));
+
+        let commands = vec![Command::A(ACommand::Address(32768))];
+        assert!(construct(&SymbolTable::new(&commands), commands).is_err());
+    }
+
+    #[test]
+    fn test_decode_all_mnemonics() {
+        for comp in COMP_MNEMONICS {
+            for (dest, jump) in DEST_MNEMONICS.into_iter().zip(JUMP_MNEMONICS) {
+                let command = Command::C(CCommand {
+                    dest: Some(dest).filter(|dest| *dest != DestMnemonic::Null),
+                    comp: comp.clone(),
+                    jump: Some(jump).filter(|jump| *jump != JumpMnemonic::Null),
+                });
+                let word = Instruction::from_c_command(match command.clone() {
+                    Command::C(c_command) => c_command,
+                    _ => unreachable!(),
+                })
+                .into_word();
+                assert_eq!(
+                    Instruction::from_word(word).try_into_command().unwrap(),
+                    command
+                );
+            }
+        }
+        // a=1 c=101010 に対応するニーモニックは無い
+        assert!(Instruction::from_word(0b1111_1010_1000_0000)
+            .try_into_command()
+            .is_err());
+    }
+
+    #[cfg(feature = "shift")]
+    #[test]
+    fn test_shift_encoding() {
+        let words = parse_words(&generate(
+            construct(
+                &SymbolTable::new(&[]),
+                schema::hack::parse("D=D<<\nAM=M>>;JGT".to_string()).unwrap(),
+            )
+            .unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(words, vec![0b1010_1100_0001_0000, 0b1011_0000_0010_1001]);
+        // 101 で始まる命令はシフト命令、111 で始まる同じビット列は通常の命令
+        assert_eq!(
+            Instruction::from_word(0b1010_1100_0001_0000)
+                .try_into_command()
+                .unwrap()
+                .to_string(),
+            "D=D<<"
+        );
+        assert_eq!(
+            Instruction::from_word(0b1110_1100_0001_0000)
+                .try_into_command()
+                .unwrap()
+                .to_string(),
+            "D=A"
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//! 手書きのアセンブラ向けの拡張構文を展開して、通常の Hack アセンブラコマンドにする
+//!
+//! ```text
+//! .include "lib.asm"        // 他のファイルを取り込む（パスはこのファイルからの相対パス）
+//! .define STACK_BASE 256    // 以降の行の STACK_BASE を 256 に置き換える
+//! .macro LOAD_CONST value   // 引数付きマクロの定義
+//!     @value
+//!     D=A
+//! .endmacro
+//! LOAD_CONST STACK_BASE     // マクロ呼び出し
+//! ```
+use schema::hack;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// マクロ展開の深さの上限（再帰的なマクロ呼び出しの検出）
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+const SYMBOL_CHAR: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_.$:";
+
+// 展開後のコマンド（位置はマクロ呼び出しの場合は呼び出し箇所）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedCommand {
+    pub path: PathBuf,
+    pub source_command: hack::SourceCommand,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandError {
+    pub path: PathBuf,
+    pub error: hack::ParseError,
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandErrors(pub Vec<ExpandError>);
+
+impl fmt::Display for ExpandErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(ExpandError::to_string).collect();
+        write!(f, "{}", errors.join("\n"))
+    }
+}
+
+impl std::error::Error for ExpandErrors {}
+
+pub fn expand_file(path: &Path) -> Result<Vec<ExpandedCommand>, ExpandErrors> {
+    let mut expander = Expander::default();
+    match std::fs::read_to_string(path) {
+        Ok(source) => expander.expand_source(path, &source),
+        Err(e) => expander.errors.push(ExpandError {
+            path: path.to_path_buf(),
+            error: error_at(&Line::new(0, ""), format!("could not read file: {e}")),
+        }),
+    }
+    expander.finish()
+}
+
+// path は .include の相対パスの基準及びエラー表示に使われる
+pub fn expand(path: &Path, source: &str) -> Result<Vec<ExpandedCommand>, ExpandErrors> {
+    let mut expander = Expander::default();
+    expander.expand_source(path, source);
+    expander.finish()
+}
+
+// ソースコードの1行
+#[derive(Debug, Clone)]
+struct Line {
+    line_number: usize,
+    source: String,
+}
+
+impl Line {
+    fn new(line_number: usize, source: &str) -> Self {
+        Self {
+            line_number,
+            source: source.trim_end_matches('\r').to_string(),
+        }
+    }
+    // コメントと前後の空白を除いた内容
+    fn code(&self) -> &str {
+        self.source.split("//").next().unwrap_or_default().trim()
+    }
+    fn column(&self) -> usize {
+        self.source
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .count()
+            + 1
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+// '../' などを含む別表記のパスでも同じファイルと分かるようにする
+// (存在しないファイルなどで正規化できなければそのまま)
+fn canonical_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Debug, Default)]
+struct Expander {
+    defines: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    // 循環した .include の検出用
+    include_stack: Vec<PathBuf>,
+    commands: Vec<ExpandedCommand>,
+    errors: Vec<ExpandError>,
+}
+
+impl Expander {
+    fn finish(self) -> Result<Vec<ExpandedCommand>, ExpandErrors> {
+        if self.errors.is_empty() {
+            Ok(self.commands)
+        } else {
+            Err(ExpandErrors(self.errors))
+        }
+    }
+
+    fn error(&mut self, path: &Path, line: &Line, message: String) {
+        self.errors.push(ExpandError {
+            path: path.to_path_buf(),
+            error: error_at(line, message),
+        });
+    }
+
+    fn expand_source(&mut self, path: &Path, source: &str) {
+        self.include_stack.push(canonical_path(path));
+        let mut lines = source
+            .split('\n')
+            .enumerate()
+            .map(|(index, source)| Line::new(index + 1, source));
+        while let Some(line) = lines.next() {
+            let code = line.code().to_string();
+            let (head, rest) = split_first_word(&code);
+            match head {
+                "" => {}
+                ".define" => self.define(path, &line, rest),
+                ".include" => self.include(path, &line, rest),
+                ".macro" => {
+                    // .endmacro までをマクロの本体とする
+                    let mut body = vec![];
+                    let mut terminated = false;
+                    for body_line in lines.by_ref() {
+                        match split_first_word(body_line.code()).0 {
+                            ".endmacro" => {
+                                terminated = true;
+                                break;
+                            }
+                            ".macro" => {
+                                self.error(
+                                    path,
+                                    &body_line,
+                                    "nested .macro definition is not allowed".to_string(),
+                                );
+                            }
+                            _ => body.push(body_line),
+                        }
+                    }
+                    if !terminated {
+                        self.error(path, &line, ".macro without .endmacro".to_string());
+                    }
+                    self.define_macro(path, &line, rest, body);
+                }
+                ".endmacro" => self.error(path, &line, ".endmacro without .macro".to_string()),
+                directive if directive.starts_with('.') => {
+                    self.error(path, &line, format!("unknown directive '{directive}'"))
+                }
+                head if !self.macros.contains_key(head)
+                    && substitute(&line.source, &self.defines) == line.source =>
+                {
+                    // 展開の必要が無い行は、エラー位置を列単位で報告する
+                    match hack::parse_with_source(line.source.clone()) {
+                        Ok(source_commands) => self.push_commands(path, &line, source_commands),
+                        Err(errors) => {
+                            self.errors
+                                .extend(errors.0.into_iter().map(|error| ExpandError {
+                                    path: path.to_path_buf(),
+                                    error: hack::ParseError {
+                                        line_number: line.line_number,
+                                        ..error
+                                    },
+                                }))
+                        }
+                    }
+                }
+                _ => {
+                    if let Err(message) = self.expand_line(path, &line, &line.source, 0) {
+                        self.error(path, &line, message);
+                    }
+                }
+            }
+        }
+        self.include_stack.pop();
+    }
+
+    fn define(&mut self, path: &Path, line: &Line, rest: &str) {
+        let (name, value) = split_first_word(rest);
+        if !is_symbol(name) || value.is_empty() {
+            self.error(path, line, "usage: .define NAME value".to_string());
+            return;
+        }
+        let value = substitute(value, &self.defines);
+        self.defines.insert(name.to_string(), value);
+    }
+
+    fn include(&mut self, path: &Path, line: &Line, rest: &str) {
+        let Some(file_name) = rest
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+        else {
+            self.error(path, line, "usage: .include \"file.asm\"".to_string());
+            return;
+        };
+        let include_path = path
+            .parent()
+            .map_or_else(|| PathBuf::from(file_name), |dir| dir.join(file_name));
+        if self.include_stack.contains(&canonical_path(&include_path)) {
+            self.error(
+                path,
+                line,
+                format!("recursive include of {}", include_path.display()),
+            );
+            return;
+        }
+        match std::fs::read_to_string(&include_path) {
+            Ok(source) => self.expand_source(&include_path, &source),
+            Err(e) => self.error(
+                path,
+                line,
+                format!("could not read {}: {e}", include_path.display()),
+            ),
+        }
+    }
+
+    fn define_macro(&mut self, path: &Path, line: &Line, rest: &str, body: Vec<Line>) {
+        let (name, params) = split_first_word(rest);
+        let params = split_args(params);
+        if !is_symbol(name) || params.iter().any(|param| !is_symbol(param)) {
+            self.error(path, line, "usage: .macro NAME [param, ...]".to_string());
+            return;
+        }
+        self.macros.insert(name.to_string(), Macro { params, body });
+    }
+
+    fn push_commands(
+        &mut self,
+        path: &Path,
+        call_site: &Line,
+        source_commands: Vec<hack::SourceCommand>,
+    ) {
+        self.commands.extend(
+            source_commands
+                .into_iter()
+                .map(|source_command| ExpandedCommand {
+                    path: path.to_path_buf(),
+                    source_command: hack::SourceCommand {
+                        command: source_command.command,
+                        line_number: call_site.line_number,
+                        column: call_site.column(),
+                        source: call_site.source.trim().to_string(),
+                    },
+                }),
+        );
+    }
+
+    // 1行を展開して解析する（マクロ呼び出しの場合は本体を再帰的に展開する）
+    // 展開されたコマンドは全て呼び出し元の行 call_site の位置を持つ
+    fn expand_line(
+        &mut self,
+        path: &Path,
+        call_site: &Line,
+        source: &str,
+        depth: usize,
+    ) -> Result<(), String> {
+        let code = Line::new(call_site.line_number, source).code().to_string();
+        let (head, rest) = split_first_word(&code);
+        let Some(macro_definition) = self.macros.get(head).cloned() else {
+            let expanded = substitute(source, &self.defines);
+            let source_commands = hack::parse_with_source(expanded.clone()).map_err(|errors| {
+                errors
+                    .0
+                    .iter()
+                    .map(|error| error.message.clone())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+                    + &if depth > 0 || expanded != source {
+                        format!(" (expanded to '{}')", expanded.trim())
+                    } else {
+                        String::new()
+                    }
+            })?;
+            self.commands
+                .extend(
+                    source_commands
+                        .into_iter()
+                        .map(|source_command| ExpandedCommand {
+                            path: path.to_path_buf(),
+                            source_command: hack::SourceCommand {
+                                command: source_command.command,
+                                line_number: call_site.line_number,
+                                column: call_site.column(),
+                                source: call_site.source.trim().to_string(),
+                            },
+                        }),
+                );
+            return Ok(());
+        };
+
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(format!(
+                "macro '{head}' is expanded too deeply (recursive macro?)"
+            ));
+        }
+        let args = split_args(&substitute(rest, &self.defines));
+        if args.len() != macro_definition.params.len() {
+            return Err(format!(
+                "macro '{head}' takes {} argument(s) but {} were given",
+                macro_definition.params.len(),
+                args.len()
+            ));
+        }
+        let bindings: HashMap<String, String> =
+            macro_definition.params.iter().cloned().zip(args).collect();
+        for body_line in &macro_definition.body {
+            self.expand_line(
+                path,
+                call_site,
+                &substitute(&body_line.source, &bindings),
+                depth + 1,
+            )
+            .map_err(|message| format!("in expansion of macro '{head}': {message}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn error_at(line: &Line, message: String) -> hack::ParseError {
+    hack::ParseError {
+        line_number: line.line_number,
+        column: line.column(),
+        message,
+        source: line.source.clone(),
+    }
+}
+
+// 最初の単語とそれ以降に分割する
+fn split_first_word(code: &str) -> (&str, &str) {
+    let code = code.trim();
+    match code.find(char::is_whitespace) {
+        Some(pos) => (&code[..pos], code[pos..].trim()),
+        None => (code, ""),
+    }
+}
+
+// カンマ又は空白区切りの引数
+fn split_args(args: &str) -> Vec<String> {
+    args.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_symbol(word: &str) -> bool {
+    !word.is_empty()
+        && !word.starts_with(|c: char| c.is_ascii_digit())
+        && word.chars().all(|c| SYMBOL_CHAR.contains(c))
+}
+
+// シンボルとして現れる単語を置き換える（コメント部分は置き換えない）
+fn substitute(source: &str, replacements: &HashMap<String, String>) -> String {
+    if replacements.is_empty() {
+        return source.to_string();
+    }
+    let (code, comment) = match source.find("//") {
+        Some(pos) => source.split_at(pos),
+        None => (source, ""),
+    };
+    let mut res = String::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, res: &mut String| {
+        res.push_str(replacements.get(word.as_str()).unwrap_or(word));
+        word.clear();
+    };
+    for c in code.chars() {
+        if SYMBOL_CHAR.contains(c) {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut res);
+            res.push(c);
+        }
+    }
+    flush(&mut word, &mut res);
+    res + comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(expanded: &[ExpandedCommand]) -> Vec<String> {
+        expanded
+            .iter()
+            .map(|expanded| {
+                format!(
+                    "{} {}",
+                    expanded.source_command.line_number, expanded.source_command.command
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        let source = "
+.define BASE 256
+.macro LOAD_CONST value
+    @value
+    D=A
+.endmacro
+.macro PUSH_CONST value
+    LOAD_CONST value // ネストしたマクロ呼び出し
+    @SP
+    AM=M+1
+    A=A-1
+    M=D
+.endmacro
+PUSH_CONST BASE
+@BASE // 置き換えられる
+";
+        let expanded = expand(Path::new("test.asm"), source).unwrap();
+        assert_eq!(
+            commands(&expanded),
+            vec![
+                "14 @256",
+                "14 D=A",
+                "14 @SP",
+                "14 AM=M+1",
+                "14 A=A-1",
+                "14 M=D",
+                "15 @256",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_at_call_site() {
+        let source = ".macro BAD x\n    D=x\n.endmacro\n\n  BAD Q\n  BAD\n.unknown\n  M=X";
+        let errors = expand(Path::new("test.asm"), source).unwrap_err().0;
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.error.line_number, error.error.column))
+                .collect::<Vec<_>>(),
+            vec![(5, 3), (6, 3), (7, 1), (8, 5)]
+        );
+        assert!(errors[0]
+            .to_string()
+            .starts_with("test.asm:5:3: in expansion of macro 'BAD': Unexpected `Q`"));
+        assert!(errors[1]
+            .to_string()
+            .contains("macro 'BAD' takes 1 argument(s) but 0 were given"));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("macro_expander_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lib.asm"),
+            ".macro GOTO label\n    @label\n    0;JMP\n.endmacro\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.asm"),
+            ".include \"lib.asm\"\n(LOOP)\nGOTO LOOP\n",
+        )
+        .unwrap();
+
+        let expanded = expand_file(&dir.join("main.asm")).unwrap();
+        assert_eq!(commands(&expanded), vec!["2 (LOOP)", "3 @LOOP", "3 0;JMP"]);
+        assert!(expanded
+            .iter()
+            .all(|expanded| expanded.path == dir.join("main.asm")));
+
+        std::fs::write(dir.join("lib.asm"), ".include \"main.asm\"\n").unwrap();
+        assert!(expand_file(&dir.join("main.asm")).is_err());
+
+        // '../' を経由した自分自身の .include
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("a.asm"), ".include \"../sub/a.asm\"\n").unwrap();
+        let errors = expand_file(&dir.join("sub").join("a.asm")).unwrap_err();
+        assert!(errors.0[0].to_string().contains("recursive include"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,38 +1,135 @@
-use core::panic;
+use assembler::file_format::FileFormat;
+use assembler::object::ObjectFile;
+use assembler::{lint, listing, macro_expander};
 use schema::hack;
 use std::path::{Path, PathBuf};
 
-mod machine_code;
-mod symbol_table;
-
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
     let args: Vec<String> = std::env::args().collect();
     // 入力されるアセンブラ言語のパス
-    let input_path: &Path = Path::new(args.get(1).unwrap());
+    let input_path: &Path = Path::new(args.get(1).ok_or_else(|| {
+        anyhow::anyhow!(
+            "usage: assembler <file.asm> [--listing] [--symbols] [--format <format>] [--object]"
+        )
+    })?);
+    // --listing: ROMアドレス・機械語・元の行の対応表 (.lst) を出力する
+    // --symbols: シンボルとアドレスの対応表 (.sym) を出力する
+    // --format <hack|bin|hex|logisim>: 機械語の出力形式 (既定は hack)
+    // --object: リンカで結合するための再配置可能なオブジェクトファイル (.obj) を出力する
+    let mut listing = false;
+    let mut object = false;
+    let mut symbols = false;
+    let mut format = FileFormat::Text;
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--listing" => listing = true,
+            "--symbols" => symbols = true,
+            "--object" => object = true,
+            "--format" => {
+                let name = options
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--format requires a format name"))?;
+                format = FileFormat::from_name(name).ok_or_else(|| {
+                    anyhow::anyhow!("unknown format: {name} (hack, bin, hex or logisim)")
+                })?;
+            }
+            _ => anyhow::bail!("unknown option: {option}"),
+        }
+    }
 
     // .asm 以外はエラーにする
-    if input_path.extension().unwrap() != "asm" {
-        panic!("input file format must be .asm");
+    if input_path.extension() != Some("asm".as_ref()) {
+        anyhow::bail!("input file format must be .asm");
     }
 
-    let input = std::fs::read_to_string(input_path).unwrap();
+    // マクロ・定数定義・インクルードを展開する
+    // 構文エラーは 'file.asm:LINE:COL: message' の形式で全て報告する
+    let expanded_commands = macro_expander::expand_file(input_path)?;
+    let source_commands: Vec<hack::SourceCommand> = expanded_commands
+        .iter()
+        .map(|expanded_command| expanded_command.source_command.clone())
+        .collect();
+    let commands: Vec<hack::Command> = source_commands
+        .iter()
+        .map(|source_command| source_command.command.clone())
+        .collect();
 
-    let commands: Vec<hack::Command> = hack::parse(input).unwrap();
+    // 意味的に疑わしい箇所を警告する
+    for warning in lint::lint(&commands) {
+        let source_command = &source_commands[warning.index];
+        eprintln!(
+            "{}:{}:{}: warning: {}\n    {}",
+            expanded_commands[warning.index].path.display(),
+            source_command.line_number,
+            source_command.column,
+            warning.kind,
+            source_command.source
+        );
+    }
 
-    let symbol_table = symbol_table::SymbolTable::new(&commands);
+    if object {
+        if symbols {
+            anyhow::bail!(
+                "--symbols cannot be used with --object (symbols are resolved by the linker)"
+            );
+        }
+        let name = input_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let object_file = ObjectFile::assemble(name, &commands)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", input_path.display()))?;
+        if listing {
+            std::fs::write(
+                output_path(input_path, "lst")?,
+                listing::generate_listing(&source_commands, &object_file.words),
+            )?;
+        }
+        std::fs::write(output_path(input_path, "obj")?, object_file.to_text())?;
+        return Ok(());
+    }
 
-    let machine_code = machine_code::construct(&symbol_table, commands).unwrap();
+    // 位置を持つエラーは 'file.asm:LINE:COL: message' の形式で報告する
+    let assembled = assembler::assemble_source_commands(&source_commands).map_err(|error| {
+        match error.index() {
+            Some(index) => anyhow::anyhow!("{}:{error}", expanded_commands[index].path.display()),
+            None => anyhow::anyhow!("{}: {error}", input_path.display()),
+        }
+    })?;
 
-    let machine_code_str = machine_code::generate(machine_code);
+    if listing {
+        std::fs::write(
+            output_path(input_path, "lst")?,
+            listing::generate_listing(&source_commands, &assembled.words),
+        )?;
+    }
+    if symbols {
+        std::fs::write(
+            output_path(input_path, "sym")?,
+            listing::generate_symbol_map(&assembled.symbol_table),
+        )?;
+    }
+
+    std::fs::write(
+        output_path(input_path, format.extension())?,
+        format.write(&assembled.words),
+    )?;
+    Ok(())
+}
 
-    // アセンブラ言語から生成された機械語を出力するパス
-    let output_path: PathBuf = {
-        let mut path = std::path::PathBuf::from(input_path.parent().unwrap());
-        path.push(format!(
-            "{}.hack",
-            input_path.file_stem().unwrap().to_str().unwrap()
-        ));
-        path
-    };
-    std::fs::write(output_path, machine_code_str).unwrap();
+// アセンブラ言語から生成されたファイルを出力するパス
+fn output_path(input_path: &Path, extension: &str) -> anyhow::Result<PathBuf> {
+    let stem = input_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid input path: {}", input_path.display()))?;
+    Ok(input_path.with_file_name(format!("{stem}.{extension}")))
 }
@@ -0,0 +1,216 @@
+//! 再配置可能なオブジェクトファイル (.obj)
+//!
+//! .asm ファイルを1つずつアセンブルした結果で、ROM アドレス 0 から配置した機械語と
+//! 他のファイルと結合するための情報を持つ。
+//!
+//! ```text
+//! HACKOBJ Main
+//! export LOOP 2
+//! import i
+//! import Sys.halt jump
+//! reloc 0 i
+//! reloc 2 LOOP
+//! code 0000000000000000
+//! ...
+//! ```
+//!
+//! - `export`: このファイルで定義されたラベルとファイル内の ROM アドレス
+//! - `import`: このファイルで定義されていないシンボル（`jump` はジャンプ先として使われたもの）
+//! - `reloc`: シンボルを参照する A命令のファイル内の ROM アドレスとシンボル
+
+use crate::error::AssembleError;
+use crate::machine_code::{Instruction, ROM_SIZE};
+use schema::hack;
+use std::collections::HashMap;
+
+const HEADER: &str = "HACKOBJ";
+
+// ファイル外のシンボルの参照
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub symbol: hack::Symbol,
+    // ジャンプ先として参照されている（変数としては解決できない）
+    pub jump: bool,
+}
+
+// リンク時にアドレスを書き込む A命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub address: u16,
+    pub symbol: hack::Symbol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectFile {
+    pub name: String,
+    pub words: Vec<u16>,
+    pub exports: Vec<(hack::Symbol, u16)>,
+    pub imports: Vec<Import>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl ObjectFile {
+    // 1つのファイルのコマンド列をアセンブルする
+    // ラベル参照はファイル内のアドレス、それ以外のシンボル参照は 0 を仮に書き込む
+    pub fn assemble(name: &str, commands: &[hack::Command]) -> Result<Self, AssembleError> {
+        let rom_size = commands
+            .iter()
+            .filter(|command| !matches!(command, hack::Command::L(_)))
+            .count();
+        if rom_size > ROM_SIZE {
+            return Err(AssembleError::RomOverflow {
+                rom_size,
+                highest_variable_address: None,
+            });
+        }
+
+        let mut labels: HashMap<&hack::Symbol, u16> = HashMap::new();
+        let mut rom_address = 0;
+        for command in commands {
+            match command {
+                hack::Command::L(symbol) => {
+                    labels.insert(symbol, rom_address);
+                }
+                _ => rom_address += 1,
+            }
+        }
+
+        let mut words = Vec::new();
+        let mut imports: Vec<Import> = Vec::new();
+        let mut relocations = Vec::new();
+        let instructions = commands
+            .iter()
+            .filter(|command| !matches!(command, hack::Command::L(_)))
+            .collect::<Vec<_>>();
+        for (address, command) in instructions.iter().enumerate() {
+            let word = match command {
+                hack::Command::A(hack::ACommand::Address(value)) => *value,
+                hack::Command::A(hack::ACommand::Symbol(symbol)) => {
+                    relocations.push(Relocation {
+                        address: address as u16,
+                        symbol: symbol.clone(),
+                    });
+                    match labels.get(symbol) {
+                        Some(label_address) => *label_address,
+                        None => {
+                            let jump = matches!(
+                                instructions.get(address + 1),
+                                Some(hack::Command::C(hack::CCommand { jump: Some(_), .. }))
+                            );
+                            match imports.iter_mut().find(|import| &import.symbol == symbol) {
+                                Some(import) => import.jump |= jump,
+                                None => imports.push(Import {
+                                    symbol: symbol.clone(),
+                                    jump,
+                                }),
+                            }
+                            0
+                        }
+                    }
+                }
+                hack::Command::C(c_command) => {
+                    Instruction::from_c_command(c_command.clone()).into_word()
+                }
+                hack::Command::L(_) => unreachable!(),
+            };
+            words.push(word);
+        }
+
+        let mut exports: Vec<(hack::Symbol, u16)> = labels
+            .into_iter()
+            .map(|(symbol, address)| (symbol.clone(), address))
+            .collect();
+        exports.sort_by(|(symbol_a, address_a), (symbol_b, address_b)| {
+            address_a.cmp(address_b).then(symbol_a.cmp(symbol_b))
+        });
+
+        Ok(Self {
+            name: name.to_string(),
+            words,
+            exports,
+            imports,
+            relocations,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("{HEADER} {}", self.name)];
+        lines.extend(
+            self.exports
+                .iter()
+                .map(|(symbol, address)| format!("export {} {address}", symbol.get())),
+        );
+        lines.extend(self.imports.iter().map(|import| {
+            if import.jump {
+                format!("import {} jump", import.symbol.get())
+            } else {
+                format!("import {}", import.symbol.get())
+            }
+        }));
+        lines.extend(
+            self.relocations.iter().map(|relocation| {
+                format!("reloc {} {}", relocation.address, relocation.symbol.get())
+            }),
+        );
+        lines.extend(self.words.iter().map(|word| format!("code {word:016b}")));
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().enumerate();
+        let name = match lines.next().map(|(_, line)| line.split_once(' ')) {
+            Some(Some((HEADER, name))) => name.to_string(),
+            _ => anyhow::bail!("not a Hack object file: missing '{HEADER} <name>' header"),
+        };
+        let mut object = Self {
+            name,
+            words: Vec::new(),
+            exports: Vec::new(),
+            imports: Vec::new(),
+            relocations: Vec::new(),
+        };
+        for (index, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parse_address = |field: &str| {
+                field
+                    .parse::<u16>()
+                    .map_err(|e| anyhow::anyhow!("line {}: {e}: {line}", index + 1))
+            };
+            match fields.as_slice() {
+                [] => {}
+                ["export", symbol, address] => object
+                    .exports
+                    .push((hack::Symbol::new(symbol), parse_address(address)?)),
+                ["import", symbol] => object.imports.push(Import {
+                    symbol: hack::Symbol::new(symbol),
+                    jump: false,
+                }),
+                ["import", symbol, "jump"] => object.imports.push(Import {
+                    symbol: hack::Symbol::new(symbol),
+                    jump: true,
+                }),
+                ["reloc", address, symbol] => object.relocations.push(Relocation {
+                    address: parse_address(address)?,
+                    symbol: hack::Symbol::new(symbol),
+                }),
+                ["code", bits] if bits.len() == 16 => object.words.push(
+                    u16::from_str_radix(bits, 2)
+                        .map_err(|e| anyhow::anyhow!("line {}: {e}: {line}", index + 1))?,
+                ),
+                _ => anyhow::bail!("line {}: unknown object file record: {line}", index + 1),
+            }
+        }
+        if let Some(relocation) = object
+            .relocations
+            .iter()
+            .find(|relocation| relocation.address as usize >= object.words.len())
+        {
+            anyhow::bail!(
+                "relocation at {} is outside of the code ({} words)",
+                relocation.address,
+                object.words.len()
+            );
+        }
+        Ok(object)
+    }
+}
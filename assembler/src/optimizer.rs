@@ -0,0 +1,596 @@
+//! hack::Command 列の覗き穴最適化
+//!
+//! ラベルで区切られた直線的なコードの中でレジスタとメモリの値を追跡し、次の命令を取り除く。
+//! - 冗長な読み込み: 既に同じ値を持つ A, D への `@X`, `D=M`, `D=A` や、同じ値を書き込む `M=D`
+//! - 使われない書き込み: 読まれる前に上書きされる `@X` や `D=...`
+//! - 直後の命令へのジャンプ
+//! - 打ち消し合う push と pop
+//!
+//! ラベルは全て残すので、ジャンプ先は変わらない。
+//! push と pop の組み合わせは vm_translator の出力する命令列を対象とし、
+//! vm_translator の規約に従って SP より上の RAM と R13, R15 を作業領域とみなす。
+
+use crate::symbol_table::{PRE_DEFINED_SYMBOLS, VARIABLE_START_RAM_ADDRESS};
+use schema::hack::{self, KBD_ADDRESS};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// push と pop の間で pop 先のアドレスを計算する命令列の最大の長さ
+const MAX_POP_ADDRESS_LENGTH: usize = 8;
+
+// 最適化の結果（origins は各コマンドが元のコマンド列の何番目に由来するか）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimized {
+    pub commands: Vec<hack::Command>,
+    pub origins: Vec<usize>,
+    pub report: OptimizeReport,
+}
+
+// 最適化の前後の命令数と、削減した命令数の内訳
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeReport {
+    pub before: usize,
+    pub after: usize,
+    pub redundant_loads: usize,
+    pub dead_writes: usize,
+    pub jumps_to_next: usize,
+    pub push_pop_saved: usize, // スタックを経由しない代入にした push と pop
+    pub cancelled_increments: usize, // 直後に打ち消される M=M+1, M=M-1 (D=D+1, D=D-1)
+}
+
+impl OptimizeReport {
+    pub fn saved(&self) -> usize {
+        self.before - self.after
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} instructions (saved {}: {} redundant loads, {} dead writes, {} jumps to next, {} push/pop, {} cancelled increments)",
+            self.before,
+            self.after,
+            self.saved(),
+            self.redundant_loads,
+            self.dead_writes,
+            self.jumps_to_next,
+            self.push_pop_saved,
+            self.cancelled_increments
+        )
+    }
+}
+
+pub fn optimize(commands: &[hack::Command]) -> Optimized {
+    let labels: HashSet<hack::Symbol> = commands
+        .iter()
+        .filter_map(|command| match command {
+            hack::Command::L(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut report = OptimizeReport {
+        before: count_instructions(commands.iter()),
+        ..Default::default()
+    };
+    let mut items: Vec<(usize, hack::Command)> = commands.iter().cloned().enumerate().collect();
+    // 1つの最適化で別の最適化ができるようになるので、変化がなくなるまで繰り返す
+    loop {
+        let changed = [
+            cancel_push_pop(&mut items, &mut report),
+            remove_redundant_loads(&mut items, &labels, &mut report),
+            remove_dead_writes(&mut items, &mut report),
+        ]
+        .contains(&true);
+        if !changed {
+            break;
+        }
+    }
+    report.after = count_instructions(items.iter().map(|(_, command)| command));
+    let (origins, commands) = items.into_iter().unzip();
+    Optimized {
+        commands,
+        origins,
+        report,
+    }
+}
+
+fn count_instructions<'a>(commands: impl Iterator<Item = &'a hack::Command>) -> usize {
+    commands
+        .filter(|command| !matches!(command, hack::Command::L(_)))
+        .count()
+}
+
+// A レジスタに設定される値（アドレス）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Address {
+    Number(u16),
+    Symbol(hack::Symbol),
+}
+
+impl Address {
+    // ラベルと同名でない定義済みシンボルは数値として扱う
+    fn new(a_command: &hack::ACommand, labels: &HashSet<hack::Symbol>) -> Self {
+        match a_command {
+            hack::ACommand::Address(value) => Self::Number(*value),
+            hack::ACommand::Symbol(symbol) => PRE_DEFINED_SYMBOLS
+                .iter()
+                .find(|(name, _)| *name == symbol.get() && !labels.contains(symbol))
+                .map_or(Self::Symbol(symbol.clone()), |(_, address)| {
+                    Self::Number(*address)
+                }),
+        }
+    }
+
+    // 同じ RAM を指している可能性があるか
+    // 変数は R0-R15 とは重ならないが、ラベルの値はどの数値とも重なりうる
+    fn may_alias(&self, other: &Self, labels: &HashSet<hack::Symbol>) -> bool {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::Symbol(a), Self::Symbol(b)) => a == b,
+            (Self::Number(number), Self::Symbol(symbol))
+            | (Self::Symbol(symbol), Self::Number(number)) => {
+                *number >= VARIABLE_START_RAM_ADDRESS || labels.contains(symbol)
+            }
+        }
+    }
+}
+
+// レジスタやメモリの値（Unknown は番号が同じであれば同じ値）
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Constant(Address),
+    Unknown(u32),
+}
+
+// 直線的なコードの中で分かっているレジスタとメモリの値
+struct State<'a> {
+    labels: &'a HashSet<hack::Symbol>,
+    a: Value,
+    d: Value,
+    memory: HashMap<Address, Value>,
+    next_unknown: u32,
+}
+
+impl<'a> State<'a> {
+    fn new(labels: &'a HashSet<hack::Symbol>) -> Self {
+        Self {
+            labels,
+            a: Value::Unknown(0),
+            d: Value::Unknown(1),
+            memory: HashMap::new(),
+            next_unknown: 2,
+        }
+    }
+
+    // ラベル（他の場所からのジャンプ先）では何も分からない
+    fn reset(&mut self) {
+        self.a = self.unknown();
+        self.d = self.unknown();
+        self.memory.clear();
+    }
+
+    fn unknown(&mut self) -> Value {
+        self.next_unknown += 1;
+        Value::Unknown(self.next_unknown)
+    }
+
+    // A が指すメモリの値（キーボードは毎回異なる値とみなす）
+    fn memory_value(&self) -> Option<&Value> {
+        match &self.a {
+            Value::Constant(address) if *address != Address::Number(KBD_ADDRESS) => {
+                self.memory.get(address)
+            }
+            _ => None,
+        }
+    }
+
+    fn read_memory(&mut self) -> Value {
+        if let Some(value) = self.memory_value() {
+            return value.clone();
+        }
+        let value = self.unknown();
+        if let Value::Constant(address) = &self.a {
+            if *address != Address::Number(KBD_ADDRESS) {
+                self.memory.insert(address.clone(), value.clone());
+            }
+        }
+        value
+    }
+
+    fn write_memory(&mut self, value: Value) {
+        match &self.a {
+            Value::Constant(address) => {
+                let labels = self.labels;
+                self.memory.retain(|key, _| !key.may_alias(address, labels));
+                if *address != Address::Number(KBD_ADDRESS) {
+                    self.memory.insert(address.clone(), value);
+                }
+            }
+            // どこに書き込んだか分からない
+            Value::Unknown(_) => self.memory.clear(),
+        }
+    }
+
+    fn evaluate(&mut self, comp: &hack::CompMnemonic) -> Value {
+        match comp {
+            hack::CompMnemonic::Zero => Value::Constant(Address::Number(0)),
+            hack::CompMnemonic::One => Value::Constant(Address::Number(1)),
+            hack::CompMnemonic::D => self.d.clone(),
+            hack::CompMnemonic::A => self.a.clone(),
+            hack::CompMnemonic::M => self.read_memory(),
+            _ => self.unknown(),
+        }
+    }
+}
+
+// dest の (A, D, M)
+fn dest_registers(dest: &Option<hack::DestMnemonic>) -> (bool, bool, bool) {
+    use hack::DestMnemonic::*;
+    match dest {
+        None | Some(Null) => (false, false, false),
+        Some(M) => (false, false, true),
+        Some(D) => (false, true, false),
+        Some(MD) => (false, true, true),
+        Some(A) => (true, false, false),
+        Some(AM) => (true, false, true),
+        Some(AD) => (true, true, false),
+        Some(AMD) => (true, true, true),
+    }
+}
+
+fn to_dest(a: bool, d: bool, m: bool) -> Option<hack::DestMnemonic> {
+    use hack::DestMnemonic::*;
+    match (a, d, m) {
+        (false, false, false) => None,
+        (false, false, true) => Some(M),
+        (false, true, false) => Some(D),
+        (false, true, true) => Some(MD),
+        (true, false, false) => Some(A),
+        (true, false, true) => Some(AM),
+        (true, true, false) => Some(AD),
+        (true, true, true) => Some(AMD),
+    }
+}
+
+// comp が読むレジスタ (A, D, M)
+fn comp_registers(comp: &hack::CompMnemonic) -> (bool, bool, bool) {
+    use hack::CompMnemonic::*;
+    match comp {
+        Zero | One | MinusOne => (false, false, false),
+        D | NegateD | MinusD | DPlusOne | DMinusOne => (false, true, false),
+        A | NegateA | MinusA | APlusOne | AMinusOne => (true, false, false),
+        DPlusA | DMinusA | AMinusD | DAndA | DOrA => (true, true, false),
+        M | NegateM | MinusM | MPlusOne | MMinusOne => (false, false, true),
+        DPlusM | DMinusM | MMinusD | DAndM | DOrM => (false, true, true),
+        #[cfg(feature = "shift")]
+        DShiftLeft | DShiftRight => (false, true, false),
+        #[cfg(feature = "shift")]
+        AShiftLeft | AShiftRight => (true, false, false),
+        #[cfg(feature = "shift")]
+        MShiftLeft | MShiftRight => (false, false, true),
+    }
+}
+
+fn jumps(c_command: &hack::CCommand) -> bool {
+    !matches!(c_command.jump, None | Some(hack::JumpMnemonic::Null))
+}
+
+// 値を追跡して、既に同じ値を持つレジスタやメモリへの読み書きと、直後の命令へのジャンプを取り除く
+fn remove_redundant_loads(
+    items: &mut Vec<(usize, hack::Command)>,
+    labels: &HashSet<hack::Symbol>,
+    report: &mut OptimizeReport,
+) -> bool {
+    let mut state = State::new(labels);
+    let mut changed = false;
+    let mut optimized = Vec::with_capacity(items.len());
+    for (index, (origin, command)) in items.iter().enumerate() {
+        match command {
+            hack::Command::L(_) => state.reset(),
+            hack::Command::A(a_command) => {
+                let value = Value::Constant(Address::new(a_command, labels));
+                if state.a == value {
+                    report.redundant_loads += 1;
+                    changed = true;
+                    continue;
+                }
+                state.a = value;
+            }
+            hack::Command::C(c_command) => {
+                let mut c_command = c_command.clone();
+                // ジャンプ先が直後のラベルであれば、ジャンプしてもしなくても同じ
+                if jumps(&c_command) && jumps_to_next(&state.a, &items[index + 1..]) {
+                    changed = true;
+                    if c_command.dest.is_none() {
+                        report.jumps_to_next += 1;
+                        continue;
+                    }
+                    c_command.jump = None;
+                }
+                let value = state.evaluate(&c_command.comp);
+                let (dest_a, dest_d, dest_m) = dest_registers(&c_command.dest);
+                if !jumps(&c_command) {
+                    let redundant = match (dest_a, dest_d, dest_m) {
+                        (true, false, false) => state.a == value,
+                        (false, true, false) => state.d == value,
+                        (false, false, true) => state.memory_value() == Some(&value),
+                        _ => false,
+                    };
+                    if redundant {
+                        report.redundant_loads += 1;
+                        changed = true;
+                        continue;
+                    }
+                }
+                // M への書き込みは書き込む前の A のアドレスに対して行われる
+                if dest_m {
+                    state.write_memory(value.clone());
+                }
+                if dest_a {
+                    state.a = value.clone();
+                }
+                if dest_d {
+                    state.d = value;
+                }
+                if c_command.jump == Some(hack::JumpMnemonic::JMP) {
+                    state.reset();
+                }
+                optimized.push((*origin, hack::Command::C(c_command)));
+                continue;
+            }
+        }
+        optimized.push((*origin, command.clone()));
+    }
+    *items = optimized;
+    changed
+}
+
+// A の値が、続くラベルのいずれかであるか
+fn jumps_to_next(a: &Value, rest: &[(usize, hack::Command)]) -> bool {
+    let Value::Constant(Address::Symbol(target)) = a else {
+        return false;
+    };
+    rest.iter()
+        .map_while(|(_, command)| match command {
+            hack::Command::L(label) => Some(label),
+            _ => None,
+        })
+        .any(|label| label == target)
+}
+
+// 後ろから A, D が使われるかを調べ、使われない値を書き込む命令を取り除く
+fn remove_dead_writes(
+    items: &mut Vec<(usize, hack::Command)>,
+    report: &mut OptimizeReport,
+) -> bool {
+    // 最後の命令の後には別のコード（リンクされる他のファイル）が続きうる
+    let mut a_live = true;
+    let mut d_live = true;
+    let mut changed = false;
+    let mut optimized = Vec::with_capacity(items.len());
+    for (origin, command) in items.iter().rev() {
+        match command {
+            hack::Command::L(_) => {}
+            hack::Command::A(_) => {
+                if !a_live {
+                    report.dead_writes += 1;
+                    changed = true;
+                    continue;
+                }
+                a_live = false;
+            }
+            hack::Command::C(c_command) => {
+                let mut c_command = c_command.clone();
+                let (comp_a, comp_d, comp_m) = comp_registers(&c_command.comp);
+                if jumps(&c_command) {
+                    // ジャンプ先では全て使われうる
+                    a_live = true;
+                    d_live = true;
+                } else {
+                    let (dest_a, dest_d, dest_m) = dest_registers(&c_command.dest);
+                    let dest = to_dest(dest_a && a_live, dest_d && d_live, dest_m);
+                    if dest.is_none() {
+                        report.dead_writes += 1;
+                        changed = true;
+                        continue;
+                    }
+                    if dest != c_command.dest {
+                        c_command.dest = dest;
+                        changed = true;
+                    }
+                    let (dest_a, dest_d, dest_m) = dest_registers(&c_command.dest);
+                    a_live = (a_live && !dest_a) || dest_m;
+                    d_live = d_live && !dest_d;
+                }
+                a_live |= comp_a || comp_m;
+                d_live |= comp_d;
+                optimized.push((*origin, hack::Command::C(c_command)));
+                continue;
+            }
+        }
+        optimized.push((*origin, command.clone()));
+    }
+    optimized.reverse();
+    *items = optimized;
+    changed
+}
+
+// vm_translator の push (D の値をスタックに積む) と、続く pop (スタックから取り出して R13 のアドレスに書き込む)
+// の間にあるアドレスの計算を残して、スタックを経由しないようにする
+// また、直後に打ち消される M=M+1, M=M-1 (D=D+1, D=D-1) を取り除く
+fn cancel_push_pop(items: &mut Vec<(usize, hack::Command)>, report: &mut OptimizeReport) -> bool {
+    let parse = |code: &str| hack::parse(code.to_string()).unwrap();
+    let push = parse("@SP\nA=M\nM=D\n@SP\nM=M+1");
+    let pop = parse("@SP\nA=M-1\nD=M\n@SP\nM=M-1\n@R13\nA=M\nM=D");
+    let save_address = parse("@R13\nM=D");
+    let increments = parse("M=M+1\nM=M-1\nM=M-1\nM=M+1\nD=D+1\nD=D-1\nD=D-1\nD=D+1");
+
+    let mut changed = false;
+    let mut optimized = Vec::with_capacity(items.len());
+    let mut index = 0;
+    'items: while index < items.len() {
+        let commands_at = |start: usize, expected: &[hack::Command]| {
+            items.len() >= start + expected.len()
+                && items[start..start + expected.len()]
+                    .iter()
+                    .zip(expected)
+                    .all(|((_, command), expected)| command == expected)
+        };
+
+        for pair in increments.chunks(2) {
+            if commands_at(index, pair) {
+                report.cancelled_increments += 2;
+                changed = true;
+                index += 2;
+                continue 'items;
+            }
+        }
+
+        if commands_at(index, &push) {
+            let start = index + push.len();
+            // pop 先のアドレスを計算して R13 に保存する命令列
+            let address_length = (save_address.len()..=MAX_POP_ADDRESS_LENGTH)
+                .take_while(|length| {
+                    items
+                        .get(start + length - save_address.len())
+                        .is_some_and(|(_, command)| is_pop_address_command(command))
+                })
+                .find(|length| commands_at(start + length - save_address.len(), &save_address));
+            if let Some(address_length) = address_length {
+                let pop_start = start + address_length;
+                if commands_at(pop_start, &pop) {
+                    let address = &items[start..pop_start];
+                    let origin = |offset: usize| items[offset].0;
+                    let before = pop_start + pop.len() - index;
+                    let replacement: Vec<(usize, hack::Command)> = match address {
+                        // @X D=A @R13 M=D: 直接 X に書き込む
+                        [(x_origin, x @ hack::Command::A(_)), (_, d_equals_a), ..]
+                            if address_length == 4 && *d_equals_a == parse("D=A")[0] =>
+                        {
+                            vec![
+                                (*x_origin, x.clone()),
+                                (origin(pop_start + 7), parse("M=D")[0].clone()),
+                            ]
+                        }
+                        // アドレスの計算の間は R15 に値を退避する
+                        _ => {
+                            let mut replacement = vec![
+                                (origin(index), parse("@R15")[0].clone()),
+                                (origin(index + 2), parse("M=D")[0].clone()),
+                            ];
+                            replacement.extend(address.iter().cloned());
+                            replacement.extend(
+                                [0, 2, 5, 6, 7]
+                                    .into_iter()
+                                    .zip(parse("@R15\nD=M\n@R13\nA=M\nM=D"))
+                                    .map(|(offset, command)| (origin(pop_start + offset), command)),
+                            );
+                            replacement
+                        }
+                    };
+                    report.push_pop_saved += before - replacement.len();
+                    optimized.extend(replacement);
+                    changed = true;
+                    index = pop_start + pop.len();
+                    continue;
+                }
+            }
+        }
+
+        optimized.push(items[index].clone());
+        index += 1;
+    }
+    *items = optimized;
+    changed
+}
+
+// pop 先のアドレスの計算に含まれてよい命令（スタックと作業用レジスタに触れず、メモリに書き込まない）
+fn is_pop_address_command(command: &hack::Command) -> bool {
+    match command {
+        hack::Command::A(hack::ACommand::Symbol(symbol)) => !["SP", "R15"].contains(&symbol.get()),
+        hack::Command::A(hack::ACommand::Address(address)) => ![0, 15].contains(address),
+        hack::Command::C(c_command) => !jumps(c_command),
+        hack::Command::L(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize_code(code: &[&str]) -> (String, OptimizeReport) {
+        let commands = hack::parse(code.join("\n")).unwrap();
+        let optimized = optimize(&commands);
+        let code = optimized
+            .commands
+            .iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        (code, optimized.report)
+    }
+
+    #[test]
+    fn test_optimize() {
+        // pop static 0 の直後の push static 0 は読み込み直さない
+        let (code, report) = optimize_code(&[
+            "@Sys.0", "D=A", "@R13", "M=D", "@SP", "A=M-1", "D=M", "@SP", "M=M-1", "@R13", "A=M",
+            "M=D", // pop static 0
+            "@Sys.0", "D=M", "@SP", "A=M", "M=D", "@SP", "M=M+1", // push static 0
+            "(LOOP)", "@LOOP", "0;JMP",
+        ]);
+        assert_eq!(
+            code,
+            "@Sys.0\nD=A\n@R13\nM=D\n@SP\nA=M-1\nD=M\n@SP\nM=M-1\n@R13\nA=M\nM=D\n\
+             @SP\nA=M\nM=D\n@SP\nM=M+1\n(LOOP)\n@LOOP\n0;JMP"
+        );
+        assert_eq!(report.redundant_loads, 2);
+        assert_eq!((report.before, report.after), (21, 19));
+
+        // push constant 3; pop static 1 は直接書き込む
+        // push constant 4; pop local 2 は R15 を経由する
+        let (code, report) = optimize_code(&[
+            "@3", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1", // push constant 3
+            "@Sys.1", "D=A", "@R13", "M=D", "@SP", "A=M-1", "D=M", "@SP", "M=M-1", "@R13", "A=M",
+            "M=D", // pop static 1
+            "@4", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1", // push constant 4
+            "@LCL", "D=M", "@2", "D=D+A", "@R13", "M=D", "@SP", "A=M-1", "D=M", "@SP", "M=M-1",
+            "@R13", "A=M", "M=D", // pop local 2
+            "(END)", "@END", "0;JMP",
+        ]);
+        assert_eq!(
+            code,
+            "@3\nD=A\n@Sys.1\nM=D\n@4\nD=A\n@R15\nM=D\n@LCL\nD=M\n@2\nD=D+A\n@R13\nM=D\n\
+             @R15\nD=M\n@R13\nA=M\nM=D\n(END)\n@END\n0;JMP"
+        );
+        assert_eq!(report.push_pop_saved, 15 + 6);
+        assert_eq!(report.cancelled_increments, 0);
+        assert_eq!(report.saved(), 21);
+
+        // 直後へのジャンプ、使われない D と A、打ち消し合う増減
+        // キーボードは読むたびに値が変わりうるので読み込み直す
+        let (code, report) = optimize_code(&[
+            "@NEXT", "D;JGT", "(NEXT)", "D=M", "D=A", "@1", "@SP", "M=M+1", "@SP", "M=M-1", "@KBD",
+            "D=M", "@R0", "M=D", "@KBD", "D=M", "@R1", "M=D", "(END)", "@END", "0;JMP",
+        ]);
+        assert_eq!(
+            code,
+            "(NEXT)\n@KBD\nD=M\n@R0\nM=D\n@KBD\nD=M\n@R1\nM=D\n(END)\n@END\n0;JMP"
+        );
+        assert_eq!(report.jumps_to_next, 1);
+        assert_eq!(report.push_pop_saved, 0);
+        assert_eq!(
+            report.to_string(),
+            "19 -> 10 instructions (saved 9: 1 redundant loads, 5 dead writes, 1 jumps to next, 0 push/pop, 2 cancelled increments)"
+        );
+
+        // ラベルの後では値が分からないので読み込み直す
+        let code = [
+            "@R1", "D=M", "@R2", "M=D", "(L)", "@R1", "D=M", "@L", "D;JNE",
+        ];
+        assert_eq!(optimize_code(&code).0, code.join("\n"));
+        let (code, _) = optimize_code(&["@R1", "D=M", "@R2", "M=D", "@R1", "D=M", "@L", "D;JNE"]);
+        assert_eq!(code, "@R1\nD=M\n@R2\nM=D\n@L\nD;JNE");
+    }
+}
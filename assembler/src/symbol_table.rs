@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use schema::hack;
 
-const PRE_DEFINED_SYMBOLS: [(&str, u16); 23] = [
+pub(crate) const PRE_DEFINED_SYMBOLS: [(&str, u16); 23] = [
     ("SP", 0),
     ("LCL", 1),
     ("ARG", 2),
@@ -27,54 +27,133 @@ const PRE_DEFINED_SYMBOLS: [(&str, u16); 23] = [
     ("SCREEN", 16384),
     ("KBD", 24576),
 ];
-const VARIABLE_START_RAM_ADDRESS: u16 = 16;
+pub(crate) const VARIABLE_START_RAM_ADDRESS: u16 = 16;
+
+// シンボルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    PreDefined, // 定義済みシンボル（RAMアドレス）
+    Label,      // ラベル（ROMアドレス）
+    Variable,   // 変数（RAMアドレス）
+}
 
 // Map <シンボル, 値（RAMアドレス, ROMアドレス）>
 #[derive(Debug, Clone)]
-pub struct SymbolTable(HashMap<hack::Symbol, u16>);
+pub struct SymbolTable {
+    symbols: HashMap<hack::Symbol, (u16, SymbolKind)>,
+    // A命令から参照された定義済みシンボル
+    used_pre_defined_symbols: HashSet<hack::Symbol>,
+    // 次に変数へ割り当てる RAM アドレス
+    next_variable_address: u16,
+}
 
 impl SymbolTable {
     pub fn new(commands: &[hack::Command]) -> SymbolTable {
-        let mut symbol_table: HashMap<hack::Symbol, u16> = PRE_DEFINED_SYMBOLS
-            .iter()
-            .map(|(symbol, address)| (hack::Symbol::new(symbol), *address))
-            .collect();
-        // ラベルをシンボルテーブルに追加
+        // ラベルとROMアドレスの組合せを登録する
+        let mut labels = Vec::new();
         {
             let mut rom_address = 0;
             for command in commands.iter() {
                 match command {
-                    hack::Command::L(symbol) => {
-                        // ラベルとROMアドレスの組合せを登録する
-                        symbol_table.insert(symbol.clone(), rom_address);
-                    }
-                    _ => {
-                        rom_address += 1;
-                    }
+                    hack::Command::L(symbol) => labels.push((symbol.clone(), rom_address)),
+                    _ => rom_address += 1,
                 }
             }
         }
+        let mut symbol_table = Self::with_labels(labels);
 
         // 変数（ラベルやシンボルとして見つからないもの）を追加
-        {
-            let mut ram_address = VARIABLE_START_RAM_ADDRESS;
-            for command in commands.iter() {
-                match command {
-                    hack::Command::A(hack::ACommand::Symbol(symbol))
-                        if !symbol_table.contains_key(symbol) =>
-                    {
-                        symbol_table.insert(symbol.clone(), ram_address);
-                        ram_address += 1;
-                    }
-                    _ => {}
-                }
+        for command in commands.iter() {
+            if let hack::Command::A(hack::ACommand::Symbol(symbol)) = command {
+                symbol_table.resolve(symbol);
             }
         }
-        Self(symbol_table)
+        symbol_table
+    }
+
+    // 定義済みシンボルとラベルだけを登録したシンボルテーブル
+    pub(crate) fn with_labels(labels: impl IntoIterator<Item = (hack::Symbol, u16)>) -> Self {
+        let mut symbols: HashMap<hack::Symbol, (u16, SymbolKind)> = PRE_DEFINED_SYMBOLS
+            .iter()
+            .map(|(symbol, address)| {
+                (
+                    hack::Symbol::new(symbol),
+                    (*address, SymbolKind::PreDefined),
+                )
+            })
+            .collect();
+        for (symbol, rom_address) in labels {
+            symbols.insert(symbol, (rom_address, SymbolKind::Label));
+        }
+        Self {
+            symbols,
+            used_pre_defined_symbols: HashSet::new(),
+            next_variable_address: VARIABLE_START_RAM_ADDRESS,
+        }
+    }
+
+    // A命令から参照されたシンボルのアドレスを返す
+    // 見つからなければ変数として次の RAM アドレスを割り当てる
+    pub(crate) fn resolve(&mut self, symbol: &hack::Symbol) -> u16 {
+        match self.symbols.get(symbol) {
+            None => {
+                let address = self.next_variable_address;
+                self.symbols
+                    .insert(symbol.clone(), (address, SymbolKind::Variable));
+                self.next_variable_address += 1;
+                address
+            }
+            Some((address, SymbolKind::PreDefined)) => {
+                self.used_pre_defined_symbols.insert(symbol.clone());
+                *address
+            }
+            Some((address, _)) => *address,
+        }
     }
 
     pub fn get(&self, symbol: &hack::Symbol) -> Option<u16> {
-        self.0.get(symbol).copied()
+        self.symbols.get(symbol).map(|(address, _)| *address)
+    }
+
+    pub fn kind(&self, symbol: &hack::Symbol) -> Option<SymbolKind> {
+        self.symbols.get(symbol).map(|(_, kind)| *kind)
+    }
+
+    // 指定した種類のシンボルをアドレス順に列挙する
+    pub fn symbols(&self, kind: SymbolKind) -> Vec<(&hack::Symbol, u16)> {
+        let mut symbols: Vec<_> = self
+            .symbols
+            .iter()
+            .filter(|(_, (_, symbol_kind))| *symbol_kind == kind)
+            .map(|(symbol, (address, _))| (symbol, *address))
+            .collect();
+        symbols.sort_by(|(symbol_a, address_a), (symbol_b, address_b)| {
+            address_a.cmp(address_b).then(symbol_a.cmp(symbol_b))
+        });
+        symbols
+    }
+
+    // 変数に割り当てられた RAM アドレスの最大値
+    pub fn highest_variable_address(&self) -> Option<u16> {
+        self.symbols(SymbolKind::Variable)
+            .last()
+            .map(|(_, address)| *address)
+    }
+
+    // 指定した ROM アドレスを含むラベル（そのアドレス以前で最も近いラベル）
+    pub fn enclosing_label(&self, rom_address: u16) -> Option<(&hack::Symbol, u16)> {
+        self.symbols(SymbolKind::Label)
+            .into_iter()
+            .filter(|(_, address)| *address <= rom_address)
+            .max_by_key(|(_, address)| *address)
+    }
+
+    // プログラム中で実際に使用された定義済みシンボルをアドレス順に列挙する
+    pub fn used_pre_defined_symbols(&self) -> Vec<(&hack::Symbol, u16)> {
+        self.symbols(SymbolKind::PreDefined)
+            .into_iter()
+            .filter(|(symbol, _)| self.used_pre_defined_symbols.contains(*symbol))
+            .collect()
     }
 }
 
@@ -121,5 +200,27 @@ mod tests {
         // 定義済みシンボルを使用したAコマンドで意図せず定義済みシンボルの参照先アドレスが
         // 書き換わらないことの確認
         assert_eq!(symbol_table.get(&Symbol::new("R0")), Some(0));
+
+        // シンボルの種類ごとの列挙
+        assert_eq!(
+            symbol_table.symbols(SymbolKind::Variable),
+            vec![
+                (&Symbol::new("hoge_var"), VARIABLE_START_RAM_ADDRESS),
+                (&Symbol::new("hufa_var"), VARIABLE_START_RAM_ADDRESS + 1)
+            ]
+        );
+        assert_eq!(
+            symbol_table.symbols(SymbolKind::Label),
+            vec![(&Symbol::new("hoge_label"), 6)]
+        );
+        assert_eq!(
+            symbol_table.enclosing_label(9),
+            Some((&Symbol::new("hoge_label"), 6))
+        );
+        assert_eq!(symbol_table.enclosing_label(5), None);
+        assert_eq!(
+            symbol_table.used_pre_defined_symbols(),
+            vec![(&Symbol::new("R0"), 0)]
+        );
     }
 }
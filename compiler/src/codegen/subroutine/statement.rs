@@ -138,7 +138,6 @@ fn while_statement_to_commands(
             class_name,
             &while_statement.condition,
         ))
-        .into_iter()
         .chain([
             vm::Command::Arithmetic(vm::ArithmeticCommand::Not),
             vm::Command::IfGoto(if_label_2.clone()),
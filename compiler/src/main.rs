@@ -5,17 +5,19 @@ use schema::jack::{
     token_analyzer::parse_tokens_as_class,
     tokenizer::{tokenize, Token},
 };
+use schema::vm;
 use std::path::{Path, PathBuf};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     let input_arg_path: &Path = Path::new(args.get(1).unwrap());
+    // --optimize: 出力する VM コマンドを覗き穴最適化する
+    let optimize = args.iter().skip(2).any(|arg| arg == "--optimize");
 
     if input_arg_path.is_dir() {
         let input_files: Vec<PathBuf> = std::fs::read_dir(input_arg_path)
             .unwrap()
-            .into_iter()
             .map(|p| p.unwrap().path())
             .filter(|p| p.is_file())
             .filter(|p| p.extension().unwrap() == std::ffi::OsStr::new("jack"))
@@ -26,12 +28,12 @@ fn main() {
         }
 
         input_files.into_iter().for_each(|path| {
-            generate_files(&path)
+            generate_files(&path, optimize)
                 .map_err(|e: anyhow::Error| {
                     format!(
                         "Compile failed!\nPath: {}, \nError: {}",
                         path.as_os_str().to_str().unwrap(),
-                        e.to_string()
+                        e
                     )
                 })
                 .unwrap();
@@ -40,13 +42,13 @@ fn main() {
         if input_arg_path.extension().unwrap() != std::ffi::OsStr::new("jack") {
             panic!("input file has to be .jack file");
         }
-        generate_files(input_arg_path).unwrap();
+        generate_files(input_arg_path, optimize).unwrap();
     } else {
         panic!("First argument has to be file path or directory path.")
     };
 }
 
-fn generate_files(path: impl AsRef<Path>) -> anyhow::Result<()> {
+fn generate_files(path: impl AsRef<Path>, optimize: bool) -> anyhow::Result<()> {
     let tokens = construct_tokens(&path)?;
     let tokens_xml = xml::tokens_to_xml(&tokens);
     std::fs::write(output_tokens_xml_path(&path).unwrap(), tokens_xml)?;
@@ -56,6 +58,13 @@ fn generate_files(path: impl AsRef<Path>) -> anyhow::Result<()> {
     std::fs::write(output_jack_token_xml_path(&path).unwrap(), class_xml)?;
 
     let vm_commands = codegen::class_to_commands(&class);
+    let vm_commands = if optimize {
+        let optimized = vm::optimizer::optimize(&vm_commands);
+        println!("{}: {}", path.as_ref().display(), optimized.report);
+        optimized.commands
+    } else {
+        vm_commands
+    };
     let vm_code = codegen::commands_to_code(&vm_commands);
     std::fs::write(output_vm_path(&path).unwrap(), vm_code)?;
 
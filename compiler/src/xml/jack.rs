@@ -165,7 +165,6 @@ impl XmlNode {
         Self::NonTerminal {
             key: "subroutineBody",
             values: std::iter::once(Self::from_symbol(Symbol::WaveBracketStart))
-                .into_iter()
                 .chain(
                     subroutine_body
                         .variable_declerations
@@ -255,8 +254,7 @@ impl XmlNode {
                     ]
                 } else {
                     vec![]
-                }
-                .into_iter(),
+                },
             )
             .collect(),
         }
@@ -269,8 +267,7 @@ impl XmlNode {
                     return_statement
                         .expression
                         .as_ref()
-                        .map(Self::from_expression)
-                        .into_iter(),
+                        .map(Self::from_expression),
                 )
                 .chain(std::iter::once(Self::from_symbol(Symbol::SemiColon)))
                 .collect(),
@@ -307,7 +304,7 @@ impl XmlNode {
         Self::NonTerminal {
             key: "doStatement",
             values: std::iter::once(Self::from_keyword(Keyword::Do))
-                .chain(Self::from_subroutine_call(&do_statement.subroutine_call).into_iter())
+                .chain(Self::from_subroutine_call(&do_statement.subroutine_call))
                 .chain(std::iter::once(Self::from_symbol(Symbol::SemiColon)))
                 .collect(),
         }
@@ -400,12 +397,12 @@ fn test_xml_to_string() {
     };
     assert_eq!(
         xml_node.into_string(0),
-        vec![
+        [
             "<expression>",
             "  <term>",
             "    <keyword> true </keyword>",
             "  </term>",
-            "</expression>",
+            "</expression>"
         ]
         .join("\n")
     )
@@ -0,0 +1,392 @@
+use assembler::machine_code::Instruction;
+use assembler::symbol_table::{SymbolKind, SymbolTable};
+use assembler::Assembled;
+use emulator::Computer;
+use schema::hack;
+use std::collections::BTreeSet;
+
+// continue で1度に実行する最大サイクル数
+const CONTINUE_MAX_CYCLES: u64 = 100_000_000;
+// list で PC の前後に表示する命令数
+const LIST_RADIUS: u16 = 5;
+
+const HELP: &str = "\
+break <label|address>    PC がラベル又は ROM アドレスに来たら止める (b)
+delete <label|address>   ブレークポイントを削除する
+watch <symbol|address>   RAM の値が変わったら止める (w)
+unwatch <symbol|address> ウォッチを削除する
+step [n]                 n 命令実行する (s)
+continue                 ブレークポイント・ウォッチ・停止まで実行する (c)
+print <name|address>     A, D, PC 又は RAM の値を表示する (p)
+set <name|address> <v>   A, D, PC 又は RAM に値を書き込む
+info                     レジスタ・ブレークポイント・ウォッチを表示する (i)
+list [label|address]     PC 又は指定したアドレスの前後の命令を表示する (l)
+quit                     終了する (q)";
+
+// RAM の監視対象
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Watch {
+    name: String,
+    address: u16,
+    value: u16,
+}
+
+pub struct Debugger {
+    computer: Computer,
+    symbol_table: SymbolTable,
+    breakpoints: BTreeSet<u16>,
+    watches: Vec<Watch>,
+    // ROM の大きさ（プログラムの外は表示しない）
+    program_size: u16,
+}
+
+impl Debugger {
+    pub fn new(assembled: Assembled) -> anyhow::Result<Self> {
+        Ok(Self {
+            computer: Computer::new(&assembled.words)?,
+            symbol_table: assembled.symbol_table,
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            program_size: assembled.words.len() as u16,
+        })
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    // コマンドを1行実行して、表示する内容を返す
+    pub fn execute(&mut self, line: &str) -> anyhow::Result<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["break" | "b", target] => {
+                let address = self.rom_address(target)?;
+                self.breakpoints.insert(address);
+                Ok(format!("breakpoint at {}", self.location(address)))
+            }
+            ["delete", target] => {
+                let address = self.rom_address(target)?;
+                if !self.breakpoints.remove(&address) {
+                    anyhow::bail!("no breakpoint at {}", self.location(address));
+                }
+                Ok(format!("deleted breakpoint at {}", self.location(address)))
+            }
+            ["watch" | "w", target] => {
+                let address = self.ram_address(target)?;
+                let watch = Watch {
+                    name: target.to_string(),
+                    address,
+                    value: self.computer.ram(address),
+                };
+                let message = format!("watch {}", self.format_watch(&watch));
+                self.watches.push(watch);
+                Ok(message)
+            }
+            ["unwatch", target] => {
+                let address = self.ram_address(target)?;
+                let count = self.watches.len();
+                self.watches.retain(|watch| watch.address != address);
+                if self.watches.len() == count {
+                    anyhow::bail!("no watch on {target}");
+                }
+                Ok(format!("deleted watch on {target}"))
+            }
+            ["step" | "s"] => Ok(self.step(1)),
+            ["step" | "s", count] => Ok(self.step(count.parse()?)),
+            ["continue" | "c"] => Ok(self.continue_execution()),
+            ["print" | "p", target] => self.print(target),
+            ["set", target, value] => {
+                let value = value.parse::<i32>()? as u16;
+                match *target {
+                    "A" => self.computer.set_a(value),
+                    "D" => self.computer.set_d(value),
+                    "PC" => self.computer.set_pc(value),
+                    _ => {
+                        let address = self.ram_address(target)?;
+                        self.computer.set_ram(address, value);
+                    }
+                }
+                self.print(target)
+            }
+            ["info" | "i"] => Ok(self.info()),
+            ["list" | "l"] => Ok(self.list(self.computer.pc())),
+            ["list" | "l", target] => Ok(self.list(self.rom_address(target)?)),
+            ["help" | "h"] => Ok(HELP.to_string()),
+            _ => anyhow::bail!("unknown command: {line} (type 'help' for commands)"),
+        }
+    }
+
+    fn step(&mut self, count: u64) -> String {
+        for _ in 0..count {
+            if self.computer.is_halted() {
+                return format!("program halted\n{}", self.current());
+            }
+            self.computer.step();
+            if let Some(message) = self.changed_watches() {
+                return format!("{message}\n{}", self.current());
+            }
+        }
+        self.current()
+    }
+
+    fn continue_execution(&mut self) -> String {
+        let max_cycles = self.computer.cycles() + CONTINUE_MAX_CYCLES;
+        // 今いるブレークポイントで止まらないように、まず1命令実行する
+        let mut first = true;
+        while self.computer.cycles() < max_cycles {
+            if self.computer.is_halted() {
+                return format!("program halted\n{}", self.current());
+            }
+            if !first && self.breakpoints.contains(&self.computer.pc()) {
+                return format!("breakpoint\n{}", self.current());
+            }
+            first = false;
+            self.computer.step();
+            if let Some(message) = self.changed_watches() {
+                return format!("{message}\n{}", self.current());
+            }
+        }
+        format!(
+            "stopped after {CONTINUE_MAX_CYCLES} cycles\n{}",
+            self.current()
+        )
+    }
+
+    // 値が変わったウォッチを報告し、現在の値を記録する
+    fn changed_watches(&mut self) -> Option<String> {
+        let mut messages = Vec::new();
+        for watch in self.watches.iter_mut() {
+            let value = self.computer.ram(watch.address);
+            if value != watch.value {
+                messages.push(format!(
+                    "watch {}: {} -> {}",
+                    watch.name, watch.value as i16, value as i16
+                ));
+                watch.value = value;
+            }
+        }
+        (!messages.is_empty()).then(|| messages.join("\n"))
+    }
+
+    fn print(&self, target: &str) -> anyhow::Result<String> {
+        Ok(match target {
+            "A" => format!("A = {}", self.format_a()),
+            "D" => format!("D = {}", self.computer.d() as i16),
+            "PC" => format!("PC = {}", self.location(self.computer.pc())),
+            _ => {
+                let address = self.ram_address(target)?;
+                format!(
+                    "{target} = RAM[{address}] = {}",
+                    self.computer.ram(address) as i16
+                )
+            }
+        })
+    }
+
+    fn info(&self) -> String {
+        let mut lines = vec![format!(
+            "PC = {}  A = {}  D = {}  cycles = {}",
+            self.location(self.computer.pc()),
+            self.format_a(),
+            self.computer.d() as i16,
+            self.computer.cycles()
+        )];
+        for address in &self.breakpoints {
+            lines.push(format!("breakpoint at {}", self.location(*address)));
+        }
+        for watch in &self.watches {
+            lines.push(format!("watch {}", self.format_watch(watch)));
+        }
+        lines.join("\n")
+    }
+
+    // 止まった位置のレジスタと命令
+    fn current(&self) -> String {
+        let pc = self.computer.pc();
+        format!(
+            "PC = {}  A = {}  D = {}\n{}",
+            self.location(pc),
+            self.format_a(),
+            self.computer.d() as i16,
+            self.list_range(pc, pc)
+        )
+    }
+
+    fn list(&self, center: u16) -> String {
+        self.list_range(
+            center.saturating_sub(LIST_RADIUS),
+            center.saturating_add(LIST_RADIUS),
+        )
+    }
+
+    // ラベルを付けて逆アセンブルした命令を表示する
+    fn list_range(&self, start: u16, end: u16) -> String {
+        let mut lines = Vec::new();
+        for address in start..=end.min(self.program_size.saturating_sub(1)) {
+            for (label, _) in self
+                .symbol_table
+                .symbols(SymbolKind::Label)
+                .into_iter()
+                .filter(|(_, label_address)| *label_address == address)
+            {
+                lines.push(format!("         ({})", label.get()));
+            }
+            let marker = match (
+                address == self.computer.pc(),
+                self.breakpoints.contains(&address),
+            ) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            lines.push(format!(
+                "{marker} {address:>5}    {}",
+                self.disassemble(address)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // A命令の値は、次の命令がジャンプならラベル、M を読み書きするなら変数・定義済みシンボルの名前で表示する
+    fn disassemble(&self, address: u16) -> String {
+        let word = self.computer.rom(address);
+        match Instruction::from_word(word) {
+            Instruction::A(value) => {
+                let kinds: &[SymbolKind] =
+                    match Instruction::from_word(self.computer.rom(address.wrapping_add(1))) {
+                        Instruction::C { jump, .. } if jump != [false; 3] => &[SymbolKind::Label],
+                        Instruction::C { a, dest, .. } if a || dest[2] => {
+                            &[SymbolKind::Variable, SymbolKind::PreDefined]
+                        }
+                        _ => &[],
+                    };
+                match self.symbol_name(value, kinds) {
+                    Some(name) => format!("@{name}"),
+                    None => format!("@{value}"),
+                }
+            }
+            instruction => instruction
+                .try_into_command()
+                .map(|command| command.to_string())
+                .unwrap_or_else(|_| format!("{word:016b} (invalid)")),
+        }
+    }
+
+    fn symbol_name(&self, value: u16, kinds: &[SymbolKind]) -> Option<&str> {
+        let used: Vec<&hack::Symbol> = self
+            .symbol_table
+            .used_pre_defined_symbols()
+            .into_iter()
+            .map(|(symbol, _)| symbol)
+            .collect();
+        kinds.iter().find_map(|kind| {
+            self.symbol_table
+                .symbols(*kind)
+                .into_iter()
+                .filter(|(symbol, _)| *kind != SymbolKind::PreDefined || used.contains(symbol))
+                .find(|(_, address)| *address == value)
+                .map(|(symbol, _)| symbol.get())
+        })
+    }
+
+    // 'LABEL+offset' の形式の ROM アドレス
+    fn location(&self, address: u16) -> String {
+        match self.symbol_table.enclosing_label(address) {
+            Some((label, label_address)) if label_address == address => {
+                format!("{address} ({})", label.get())
+            }
+            Some((label, label_address)) => {
+                format!("{address} ({}+{})", label.get(), address - label_address)
+            }
+            None => address.to_string(),
+        }
+    }
+
+    fn format_a(&self) -> String {
+        let a = self.computer.a();
+        match self.symbol_name(a, &[SymbolKind::Variable, SymbolKind::PreDefined]) {
+            Some(name) => format!("{} ({name})", a as i16),
+            None => (a as i16).to_string(),
+        }
+    }
+
+    fn format_watch(&self, watch: &Watch) -> String {
+        format!(
+            "{} = RAM[{}] = {}",
+            watch.name, watch.address, watch.value as i16
+        )
+    }
+
+    // ラベル又は数値の ROM アドレス
+    fn rom_address(&self, target: &str) -> anyhow::Result<u16> {
+        if let Ok(address) = target.parse() {
+            return Ok(address);
+        }
+        let symbol = hack::Symbol::new(target);
+        match self.symbol_table.kind(&symbol) {
+            Some(SymbolKind::Label) => Ok(self.symbol_table.get(&symbol).unwrap()),
+            Some(_) => anyhow::bail!("{target} is not a label"),
+            None => anyhow::bail!("unknown label: {target}"),
+        }
+    }
+
+    // 変数・定義済みシンボル又は数値の RAM アドレス
+    fn ram_address(&self, target: &str) -> anyhow::Result<u16> {
+        if let Ok(address) = target.parse() {
+            return Ok(address);
+        }
+        let symbol = hack::Symbol::new(target);
+        match self.symbol_table.kind(&symbol) {
+            Some(SymbolKind::Variable | SymbolKind::PreDefined) => {
+                Ok(self.symbol_table.get(&symbol).unwrap())
+            }
+            Some(SymbolKind::Label) => anyhow::bail!("{target} is a label, not a RAM address"),
+            None => anyhow::bail!("unknown symbol: {target}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debugger() {
+        let source =
+            "@10\nD=A\n@counter\nM=D\n(LOOP)\n@counter\nMD=M-1\n@LOOP\nD;JGT\n(END)\n@END\n0;JMP";
+        let mut debugger = Debugger::new(assembler::assemble(source).unwrap()).unwrap();
+
+        assert_eq!(
+            debugger.execute("break LOOP").unwrap(),
+            "breakpoint at 4 (LOOP)"
+        );
+        assert_eq!(
+            debugger.execute("watch counter").unwrap(),
+            "watch counter = RAM[16] = 0"
+        );
+        // ウォッチした変数が変わったところで止まる
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            [
+                "watch counter: 0 -> 10",
+                "PC = 4 (LOOP)  A = 16 (counter)  D = 10",
+                "         (LOOP)",
+                "=>     4    @counter",
+            ]
+            .join("\n")
+        );
+        debugger.execute("unwatch counter").unwrap();
+        debugger.execute("c").unwrap();
+        assert_eq!(
+            debugger.execute("p counter").unwrap(),
+            "counter = RAM[16] = 9"
+        );
+        assert_eq!(
+            debugger.execute("step 3").unwrap().lines().next(),
+            Some("PC = 7 (LOOP+3)  A = 4  D = 8")
+        );
+        assert!(debugger.execute("list").unwrap().contains("    7    D;JGT"));
+        assert!(debugger.execute("list").unwrap().contains("    6    @LOOP"));
+        assert!(debugger.execute("break counter").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use assembler::macro_expander;
+use debugger::Debugger;
+use schema::hack;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    // デバッグする .asm ファイルのパス（シンボルを使うためアセンブラ言語から読み込む）
+    let input_path: &Path = Path::new(
+        args.get(1)
+            .ok_or_else(|| anyhow::anyhow!("usage: debugger <file.asm>"))?,
+    );
+    let source_commands: Vec<hack::SourceCommand> = macro_expander::expand_file(input_path)?
+        .into_iter()
+        .map(|expanded_command| expanded_command.source_command)
+        .collect();
+    let assembled = assembler::assemble_source_commands(&source_commands)
+        .map_err(|error| anyhow::anyhow!("{}: {error}", input_path.display()))?;
+    let mut debugger = Debugger::new(assembled)?;
+
+    println!("{}", debugger.execute("list")?);
+    // 空行は直前のコマンドを繰り返す
+    let mut last_line = String::new();
+    let stdin = std::io::stdin();
+    loop {
+        print!("(hdb) ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = match line.trim() {
+            "" => last_line.clone(),
+            line => line.to_string(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if matches!(line.as_str(), "quit" | "q") {
+            break;
+        }
+        match debugger.execute(&line) {
+            Ok(output) => println!("{output}"),
+            Err(e) => println!("error: {e}"),
+        }
+        last_line = line;
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use assembler::machine_code::Instruction;
+use schema::hack;
+use std::collections::BTreeSet;
+
+// 機械語命令列をアセンブラコマンド列に逆変換する
+// ジャンプ先として使われているアドレスには (LABEL_n) を生成する
+pub fn disassemble(words: &[u16]) -> anyhow::Result<Vec<hack::Command>> {
+    let commands = decode(words)?;
+    let jump_address_commands = jump_address_commands(&commands);
+    let jump_targets: BTreeSet<u16> = jump_address_commands
+        .iter()
+        .map(|index| match commands[*index] {
+            hack::Command::A(hack::ACommand::Address(address)) => address,
+            _ => unreachable!(),
+        })
+        .collect();
+
+    let mut res = Vec::with_capacity(commands.len() + jump_targets.len());
+    for (rom_address, command) in commands.into_iter().enumerate() {
+        if jump_targets.contains(&(rom_address as u16)) {
+            res.push(hack::Command::L(label(rom_address as u16)));
+        }
+        res.push(match command {
+            hack::Command::A(hack::ACommand::Address(address))
+                if jump_address_commands.contains(&rom_address) =>
+            {
+                hack::Command::A(hack::ACommand::Symbol(label(address)))
+            }
+            command => command,
+        });
+    }
+    // プログラム末尾を指すラベル
+    let end_address = words.len() as u16;
+    if jump_targets.contains(&end_address) {
+        res.push(hack::Command::L(label(end_address)));
+    }
+    Ok(res)
+}
+
+// アセンブラ言語のテキストを生成する
+pub fn generate(commands: &[hack::Command]) -> String {
+    commands
+        .iter()
+        .map(|command| match command {
+            hack::Command::L(_) => command.to_string(),
+            _ => format!("    {command}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn decode(words: &[u16]) -> anyhow::Result<Vec<hack::Command>> {
+    let mut errors: Vec<String> = vec![];
+    let commands = words
+        .iter()
+        .enumerate()
+        .filter_map(|(rom_address, word)| {
+            Instruction::from_word(*word)
+                .try_into_command()
+                .map_err(|e| errors.push(format!("ROM {rom_address} ({word:016b}): {e}")))
+                .ok()
+        })
+        .collect();
+    if !errors.is_empty() {
+        anyhow::bail!("{}", errors.join("\n"));
+    }
+    Ok(commands)
+}
+
+// ジャンプ命令の直前にあり、ジャンプ先アドレスを指定している A 命令の位置
+fn jump_address_commands(commands: &[hack::Command]) -> BTreeSet<usize> {
+    commands
+        .windows(2)
+        .enumerate()
+        .filter_map(|(index, window)| match window {
+            [hack::Command::A(hack::ACommand::Address(address)), hack::Command::C(hack::CCommand {
+                jump: Some(_),
+                ..
+            })] if *address as usize <= commands.len() => Some(index),
+            _ => None,
+        })
+        .collect()
+}
+
+fn label(rom_address: u16) -> hack::Symbol {
+    hack::Symbol(format!("LABEL_{rom_address}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::{machine_code, symbol_table::SymbolTable};
+
+    #[test]
+    fn test_disassemble() {
+        let source = "@3\nD=A\n@END\n(LOOP)\nD=D-1;JGT\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP";
+        let commands = hack::parse(source.to_string()).unwrap();
+        let words = machine_code::construct(&SymbolTable::new(&commands), commands)
+            .unwrap()
+            .into_iter()
+            .map(machine_code::Instruction::into_word)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            generate(&disassemble(&words).unwrap()),
+            [
+                "    @3",
+                "    D=A",
+                "    @LABEL_6",
+                "(LABEL_3)",
+                "    D=D-1;JGT",
+                "    @LABEL_3",
+                "    0;JMP",
+                "(LABEL_6)",
+                "    @LABEL_6",
+                "    0;JMP",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[cfg(feature = "shift")]
+    #[test]
+    fn test_disassemble_shift() {
+        // 101 で始まる命令はシフト命令
+        let source =
+            generate(&disassemble(&[0b1010_1100_0001_0000, 0b1011_0000_0010_1001]).unwrap());
+        assert_eq!(source, "    D=D<<\n    AM=M>>;JGT");
+    }
+
+    #[test]
+    fn test_invalid_comp() {
+        let error = disassemble(&[0, 0b1111_1010_1000_0000]).unwrap_err();
+        assert!(error.to_string().starts_with("ROM 1 "));
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    // 入力される機械語のパス
+    let input_path: &Path = Path::new(args.get(1).unwrap());
+
+    // .hack, .bin, .hex, .rom (Logisim) のいずれかの形式で読み込む
+    let words = assembler::file_format::read_file(input_path).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+
+    let commands = disassembler::disassemble(&words).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+
+    // 既存の .asm を上書きしないように別名で出力する
+    let output_path: PathBuf = input_path.with_file_name(format!(
+        "{}_disassembled.asm",
+        input_path.file_stem().unwrap().to_str().unwrap()
+    ));
+    std::fs::write(output_path, disassembler::generate(&commands)).unwrap();
+}
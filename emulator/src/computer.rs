@@ -0,0 +1,288 @@
+use crate::keyboard::ScriptedKeyboard;
+use crate::memory::{Ram, Rom, KBD_ADDRESS, SCREEN_ADDRESS, SCREEN_SIZE};
+use assembler::{machine_code, symbol_table::SymbolTable};
+use schema::hack;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    // '(END) @END 0;JMP' のような自分自身へのジャンプで停止した
+    Halted,
+    // 指定したサイクル数に達した
+    CycleLimit,
+}
+
+// CPU (A, D, PC レジスタ) と ROM, RAM からなる Hack コンピュータ
+#[derive(Debug, Clone)]
+pub struct Computer {
+    rom: Rom,
+    ram: Ram,
+    a: u16,
+    d: u16,
+    pc: u16,
+    cycles: u64,
+}
+
+impl Computer {
+    pub fn new(program: &[u16]) -> anyhow::Result<Self> {
+        Ok(Self {
+            rom: Rom::new(program)?,
+            ram: Ram::new(),
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+        })
+    }
+
+    // .hack ファイルの内容を ROM に読み込む
+    pub fn from_hack_code(code: &str) -> anyhow::Result<Self> {
+        Self::new(&machine_code::parse_words(code)?)
+    }
+
+    // アセンブラコマンド列をシンボル解決して ROM に読み込む
+    pub fn from_commands(commands: Vec<hack::Command>) -> anyhow::Result<Self> {
+        let symbol_table = SymbolTable::new(&commands);
+        let program = machine_code::construct(&symbol_table, commands)?
+            .into_iter()
+            .map(machine_code::Instruction::into_word)
+            .collect::<Vec<_>>();
+        Self::new(&program)
+    }
+
+    // レジスタとサイクル数を初期化する（RAM の内容は保持される）
+    pub fn reset(&mut self) {
+        self.a = 0;
+        self.d = 0;
+        self.pc = 0;
+        self.cycles = 0;
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+    pub fn set_a(&mut self, value: u16) {
+        self.a = value
+    }
+    pub fn set_d(&mut self, value: u16) {
+        self.d = value
+    }
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value
+    }
+    pub fn rom(&self, address: u16) -> u16 {
+        self.rom.get(address)
+    }
+    pub fn ram(&self, address: u16) -> u16 {
+        self.ram.get(address)
+    }
+    pub fn set_ram(&mut self, address: u16, value: u16) {
+        self.ram.set(address, value)
+    }
+    pub fn ram_slice(&self, start: u16, len: usize) -> &[u16] {
+        self.ram.slice(start, len)
+    }
+    pub fn screen(&self) -> &[u16] {
+        self.ram.slice(SCREEN_ADDRESS, SCREEN_SIZE)
+    }
+    pub fn keyboard(&self) -> u16 {
+        self.ram.get(KBD_ADDRESS)
+    }
+    // キーボードから入力されたキーコードを設定する（0 は何も押されていない状態）
+    pub fn set_keyboard(&mut self, key_code: u16) {
+        self.ram.set(KBD_ADDRESS, key_code)
+    }
+
+    // 1命令実行する
+    pub fn step(&mut self) {
+        let instruction = self.rom.get(self.pc);
+        self.cycles += 1;
+
+        // A命令: 0vvv vvvv vvvv vvvv
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1);
+            return;
+        }
+
+        // C命令: 111a cccc ccdd djjj
+        let bit = |n: u16| instruction & (1 << n) != 0;
+        let y = if bit(12) {
+            self.ram.get(self.a)
+        } else {
+            self.a
+        };
+        let out = alu(
+            self.d,
+            y,
+            [bit(11), bit(10), bit(9), bit(8), bit(7), bit(6)],
+        );
+        // シフト命令: 101a cccc ccdd djjj (c1 が左シフト、c2 が D を選ぶ)
+        #[cfg(feature = "shift")]
+        let out = if instruction & 0xe000 == 0xa000 {
+            let x = if bit(10) { self.d } else { y };
+            if bit(11) {
+                x << 1
+            } else {
+                ((x as i16) >> 1) as u16
+            }
+        } else {
+            out
+        };
+
+        let address = self.a;
+        if bit(5) {
+            self.a = out;
+        }
+        if bit(4) {
+            self.d = out;
+        }
+        // キーボードのメモリマップは読み出し専用
+        if bit(3) && address != KBD_ADDRESS {
+            self.ram.set(address, out);
+        }
+
+        let out = out as i16;
+        let jump = (bit(2) && out < 0) || (bit(1) && out == 0) || (bit(0) && out > 0);
+        self.pc = if jump {
+            address
+        } else {
+            self.pc.wrapping_add(1)
+        };
+    }
+
+    // 停止するか、最大サイクル数に達するまで実行する
+    pub fn run(&mut self, max_cycles: u64) -> RunResult {
+        self.run_with(max_cycles, |_| {})
+    }
+
+    // 各命令を実行する前に before_step を呼びながら実行する
+    pub fn run_with(
+        &mut self,
+        max_cycles: u64,
+        mut before_step: impl FnMut(&mut Self),
+    ) -> RunResult {
+        while self.cycles < max_cycles {
+            if self.is_halted() {
+                return RunResult::Halted;
+            }
+            before_step(self);
+            self.step();
+        }
+        RunResult::CycleLimit
+    }
+
+    // スクリプトに従ってキーボードに入力しながら実行する
+    pub fn run_with_keyboard(
+        &mut self,
+        keyboard: &mut ScriptedKeyboard,
+        max_cycles: u64,
+    ) -> RunResult {
+        self.run_with(max_cycles, |computer| keyboard.update(computer))
+    }
+
+    // 現在の命令が KBD (M) を読み出すか
+    pub fn reads_keyboard(&self) -> bool {
+        let instruction = self.rom.get(self.pc);
+        // C命令（シフト命令を含む）で a=1
+        instruction & 0x8000 != 0 && instruction & 0x1000 != 0 && self.a == KBD_ADDRESS
+    }
+
+    // 現在の命令が '@自分の直前のアドレス' への無条件ジャンプ（無限ループ）であれば停止したとみなす
+    pub fn is_halted(&self) -> bool {
+        let instruction = self.rom.get(self.pc);
+        // dest を持たない無条件ジャンプ命令 (111a cccc cc00 0111)
+        let is_unconditional_jump = instruction & 0xe03f == 0xe007;
+        let jumps_to_previous_a_command =
+            self.pc.checked_sub(1) == Some(self.a) && self.rom.get(self.a) == self.a;
+        is_unconditional_jump && jumps_to_previous_a_command
+    }
+}
+
+// Hack ALU
+// control: [zx, nx, zy, ny, f, no]
+fn alu(x: u16, y: u16, [zx, nx, zy, ny, f, no]: [bool; 6]) -> u16 {
+    let x = if zx { 0 } else { x };
+    let x = if nx { !x } else { x };
+    let y = if zy { 0 } else { y };
+    let y = if ny { !y } else { y };
+    let out = if f { x.wrapping_add(y) } else { x & y };
+    if no {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RAM[2] = RAM[0] + RAM[1]
+    const ADD_PROGRAM: &str = "
+        @R0
+        D=M
+        @R1
+        D=D+M
+        @R2
+        M=D
+        (END)
+        @END
+        0;JMP
+    ";
+
+    #[test]
+    fn test_run_add_program() {
+        let mut computer =
+            Computer::from_commands(hack::parse(ADD_PROGRAM.to_string()).unwrap()).unwrap();
+        computer.set_ram(0, 2);
+        computer.set_ram(1, 0xffff); // -1
+        assert_eq!(computer.run(100), RunResult::Halted);
+        assert_eq!(computer.ram(2), 1);
+        assert_eq!(computer.pc(), 7);
+        assert_eq!(computer.cycles(), 7);
+    }
+
+    #[test]
+    fn test_alu() {
+        let (x, y) = (5, 3);
+        assert_eq!(alu(x, y, [true, false, true, false, true, false]), 0); // 0
+        assert_eq!(alu(x, y, [true, true, true, true, true, true]), 1); // 1
+        assert_eq!(alu(x, y, [false, false, true, true, false, true]), !x); // !D
+        assert_eq!(alu(x, y, [false, true, false, false, true, true]), 2); // D-A
+        assert_eq!(alu(x, y, [false, false, false, true, true, true]), 0xfffe); // A-D
+        assert_eq!(alu(x, y, [false, true, false, true, false, true]), 7); // D|A
+    }
+
+    #[cfg(feature = "shift")]
+    #[test]
+    fn test_shift() {
+        let program = "@R0\nD=M<<\nD=D<<\nM=D\n@R1\nM=M>>\nA=M\nD=A>>\n@R2\nM=D";
+        let mut computer =
+            Computer::from_commands(hack::parse(program.to_string()).unwrap()).unwrap();
+        computer.set_ram(0, 3);
+        computer.set_ram(1, 0xfff8); // -8
+        computer.run(10);
+        assert_eq!(computer.ram(0), 12);
+        // 右シフトは算術シフト
+        assert_eq!(computer.ram(1), 0xfffc);
+        assert_eq!(computer.ram(2), 0xfffe);
+    }
+
+    #[test]
+    fn test_cycle_limit() {
+        let mut computer =
+            Computer::from_commands(hack::parse("(LOOP)\n@LOOP\nM=M+1;JMP".to_string()).unwrap())
+                .unwrap();
+        assert_eq!(computer.run(10), RunResult::CycleLimit);
+        assert_eq!(computer.cycles(), 10);
+    }
+}
@@ -0,0 +1,225 @@
+//! 実行された ROM アドレスを記録し、アセンブリのソースの行とラベルに対応付ける
+//!
+//! 注釈付きのソース (gcov 風) と lcov 形式のレポートを生成する。
+
+use crate::computer::Computer;
+use crate::memory::ROM_SIZE;
+use schema::hack;
+use std::fmt::Write;
+
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    counts: Vec<u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; ROM_SIZE],
+        }
+    }
+
+    // 次に実行する命令を記録する（Computer::run_with から命令の実行前に呼ぶ）
+    pub fn record(&mut self, computer: &Computer) {
+        self.counts[computer.pc() as usize] += 1;
+    }
+
+    pub fn count(&self, rom_address: u16) -> u64 {
+        self.counts[rom_address as usize]
+    }
+
+    // アセンブル前のコマンド列（行番号付き）に対応付ける
+    pub fn report(&self, source_commands: &[hack::SourceCommand]) -> CoverageReport {
+        let mut lines = Vec::new();
+        let mut labels: Vec<LabelCoverage> = Vec::new();
+        let mut rom_address: u16 = 0;
+        for source_command in source_commands {
+            match &source_command.command {
+                hack::Command::L(symbol) => labels.push(LabelCoverage {
+                    name: symbol.get().to_string(),
+                    line_number: source_command.line_number,
+                    count: self.count(rom_address),
+                    covered: 0,
+                    total: 0,
+                }),
+                _ => {
+                    let count = self.count(rom_address);
+                    lines.push(LineCoverage {
+                        line_number: source_command.line_number,
+                        rom_address,
+                        count,
+                    });
+                    // 命令はその直前のラベルに含まれる
+                    if let Some(label) = labels.last_mut() {
+                        label.total += 1;
+                        label.covered += usize::from(count > 0);
+                    }
+                    rom_address += 1;
+                }
+            }
+        }
+        CoverageReport { lines, labels }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineCoverage {
+    pub line_number: usize,
+    pub rom_address: u16,
+    pub count: u64,
+}
+
+// ラベルから次のラベルまでの命令の実行状況
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelCoverage {
+    pub name: String,
+    pub line_number: usize,
+    // ラベルの位置の命令の実行回数
+    pub count: u64,
+    pub covered: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub lines: Vec<LineCoverage>,
+    pub labels: Vec<LabelCoverage>,
+}
+
+impl CoverageReport {
+    // 実行された命令の数と命令の総数
+    pub fn covered(&self) -> (usize, usize) {
+        let covered = self.lines.iter().filter(|line| line.count > 0).count();
+        (covered, self.lines.len())
+    }
+
+    pub fn summary(&self) -> String {
+        let (covered, total) = self.covered();
+        format!(
+            "coverage: {covered}/{total} instructions ({:.2}%)",
+            covered as f64 * 100.0 / total.max(1) as f64
+        )
+    }
+
+    // ソースの各行の前に実行回数を付ける（'#####' は実行されなかった命令、'-' は命令でない行）
+    // 先頭にラベルごとの実行された命令の割合を並べる
+    pub fn annotate(&self, source: &str) -> String {
+        let mut text = String::new();
+        writeln!(text, "{}", self.summary()).unwrap();
+        writeln!(text, "\n[labels]").unwrap();
+        for label in &self.labels {
+            writeln!(
+                text,
+                "{:>6}/{:<6} {:>5}  {}",
+                label.covered, label.total, label.line_number, label.name
+            )
+            .unwrap();
+        }
+        writeln!(text, "\n[source]").unwrap();
+        let mut lines = self.lines.iter().peekable();
+        for (index, source_line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let mut count = None;
+            while let Some(line) = lines.next_if(|line| line.line_number == line_number) {
+                *count.get_or_insert(0) += line.count;
+            }
+            let marker = match count {
+                None => "-".to_string(),
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+            };
+            writeln!(text, "{marker:>12}: {line_number:>5}: {source_line}").unwrap();
+        }
+        text
+    }
+
+    // lcov のトレースファイル形式（ラベルを関数として扱う）
+    pub fn to_lcov(&self, source_path: &str) -> String {
+        let mut lcov = String::new();
+        writeln!(lcov, "TN:").unwrap();
+        writeln!(lcov, "SF:{source_path}").unwrap();
+        for label in &self.labels {
+            writeln!(lcov, "FN:{},{}", label.line_number, label.name).unwrap();
+        }
+        for label in &self.labels {
+            writeln!(lcov, "FNDA:{},{}", label.count, label.name).unwrap();
+        }
+        writeln!(lcov, "FNF:{}", self.labels.len()).unwrap();
+        writeln!(
+            lcov,
+            "FNH:{}",
+            self.labels.iter().filter(|label| label.count > 0).count()
+        )
+        .unwrap();
+        for line in &self.lines {
+            writeln!(lcov, "DA:{},{}", line.line_number, line.count).unwrap();
+        }
+        let (covered, total) = self.covered();
+        writeln!(lcov, "LF:{total}").unwrap();
+        writeln!(lcov, "LH:{covered}").unwrap();
+        writeln!(lcov, "end_of_record").unwrap();
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        // R0 が負でなければ ERROR には到達しない
+        let source = [
+            "// abs", "@R0", "D=M", "@ERROR", "D;JLT", "(END)", "@END", "0;JMP", "(ERROR)", "@R1",
+            "M=-1",
+        ]
+        .join("\n");
+        let source_commands = hack::parse_with_source(source.clone()).unwrap();
+        let assembled = assembler::assemble_source_commands(&source_commands).unwrap();
+        let mut computer = Computer::new(&assembled.words).unwrap();
+        let mut coverage = Coverage::new();
+        let result = computer.run_with(100, |computer| coverage.record(computer));
+        // 停止した命令も実行されたとみなす
+        assert_eq!(result, crate::RunResult::Halted);
+        coverage.record(&computer);
+
+        let report = coverage.report(&source_commands);
+        assert_eq!(report.covered(), (6, 8));
+        assert_eq!(
+            report.labels,
+            vec![
+                LabelCoverage {
+                    name: "END".to_string(),
+                    line_number: 6,
+                    count: 1,
+                    covered: 2,
+                    total: 2,
+                },
+                LabelCoverage {
+                    name: "ERROR".to_string(),
+                    line_number: 9,
+                    count: 0,
+                    covered: 0,
+                    total: 2,
+                },
+            ]
+        );
+
+        let annotated = report.annotate(&source);
+        assert!(annotated.contains("           -:     1: // abs\n"));
+        assert!(annotated.contains("           1:     2: @R0\n"));
+        assert!(annotated.contains("       #####:    10: @R1\n"));
+
+        let lcov = report.to_lcov("abs.asm");
+        assert!(lcov.starts_with("TN:\nSF:abs.asm\nFN:6,END\nFN:9,ERROR\n"));
+        assert!(lcov.contains("FNDA:0,ERROR\nFNF:2\nFNH:1\n"));
+        assert!(lcov.contains("DA:7,1\nDA:8,1\n"));
+        assert!(lcov.ends_with("LF:8\nLH:6\nend_of_record\n"));
+    }
+}
@@ -0,0 +1,227 @@
+//! キーボード入力のスクリプト
+//!
+//! 1行に1つ、キーボードのメモリマップ (24576) に書き込むタイミングと内容を書く。
+//!
+//! ```text
+//! // 100 万サイクル目に 'A' を押し、1000 サイクル後に離す
+//! cycle 1000000 press A
+//! after 1000 release
+//! // プログラムが KBD を読むたびに1文字ずつ押して離す
+//! poll type 42
+//! poll press ENTER
+//! poll release
+//! ```
+//!
+//! - タイミング: `cycle N` (N サイクル目), `after N` (前の入力から N サイクル後),
+//!   `poll` (前の入力の後、プログラムが次に KBD を読むとき)
+//! - 内容: `press KEY`, `release`, `type TEXT` (各文字を poll ごとに押して離す)
+//! - KEY: 1文字, `#65` のようなキーコード, 又は `SPACE`, `ENTER` (`NEWLINE`), `BACKSPACE`,
+//!   `LEFT`, `UP`, `RIGHT`, `DOWN`, `HOME`, `END`, `PAGEUP`, `PAGEDOWN`, `INSERT`, `DELETE`,
+//!   `ESC`, `F1`-`F12`
+
+use crate::computer::Computer;
+
+// Hack の特殊キーのキーコード
+const KEY_NAMES: [(&str, u16); 15] = [
+    ("SPACE", 32),
+    ("ENTER", 128),
+    ("NEWLINE", 128),
+    ("BACKSPACE", 129),
+    ("LEFT", 130),
+    ("UP", 131),
+    ("RIGHT", 132),
+    ("DOWN", 133),
+    ("HOME", 134),
+    ("END", 135),
+    ("PAGEUP", 136),
+    ("PAGEDOWN", 137),
+    ("INSERT", 138),
+    ("DELETE", 139),
+    ("ESC", 140),
+];
+const F1_KEY_CODE: u16 = 141;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    // 指定したサイクル数に達したとき
+    Cycle(u64),
+    // 前の入力から指定したサイクル数が経過したとき
+    After(u64),
+    // 前の入力の後、プログラムが KBD を読むとき
+    Poll,
+}
+
+// キーボードのメモリマップへの書き込み（0 はキーを離したことを表す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub trigger: Trigger,
+    pub key_code: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptedKeyboard {
+    events: Vec<KeyEvent>,
+    next: usize,
+    last_event_cycle: u64,
+    // 前の入力の後にプログラムが KBD を読んだか
+    polled: bool,
+}
+
+impl ScriptedKeyboard {
+    pub fn new(events: Vec<KeyEvent>) -> Self {
+        Self {
+            events,
+            next: 0,
+            last_event_cycle: 0,
+            polled: true,
+        }
+    }
+
+    pub fn parse(script: &str) -> anyhow::Result<Self> {
+        let mut events = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            parse_line(line, &mut events)
+                .map_err(|e| anyhow::anyhow!("line {}: {e}: {line}", index + 1))?;
+        }
+        Ok(Self::new(events))
+    }
+
+    // 全ての入力を終えたか
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    // 次の命令を実行する前に、タイミングが来た入力をキーボードに書き込む
+    pub fn update(&mut self, computer: &mut Computer) {
+        let reads_keyboard = computer.reads_keyboard();
+        while let Some(event) = self.events.get(self.next) {
+            let triggered = match event.trigger {
+                Trigger::Cycle(cycle) => computer.cycles() >= cycle,
+                Trigger::After(cycles) => computer.cycles() >= self.last_event_cycle + cycles,
+                Trigger::Poll => reads_keyboard && self.polled,
+            };
+            if !triggered {
+                break;
+            }
+            computer.set_keyboard(event.key_code);
+            self.next += 1;
+            self.last_event_cycle = computer.cycles();
+            self.polled = false;
+        }
+        if reads_keyboard {
+            self.polled = true;
+        }
+    }
+}
+
+fn parse_line(line: &str, events: &mut Vec<KeyEvent>) -> anyhow::Result<()> {
+    let (trigger, rest) = match line.split_once(' ') {
+        Some(("poll", rest)) => (Trigger::Poll, rest),
+        Some((kind @ ("cycle" | "after"), rest)) => {
+            let (cycles, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let cycles: u64 = cycles.parse()?;
+            let trigger = if kind == "cycle" {
+                Trigger::Cycle(cycles)
+            } else {
+                Trigger::After(cycles)
+            };
+            (trigger, rest)
+        }
+        _ => anyhow::bail!("expected 'cycle N', 'after N' or 'poll'"),
+    };
+    let (action, argument) = rest.split_once(' ').unwrap_or((rest, ""));
+    match action {
+        "press" => events.push(KeyEvent {
+            trigger,
+            key_code: key_code(argument.trim())?,
+        }),
+        "release" => events.push(KeyEvent {
+            trigger,
+            key_code: 0,
+        }),
+        "type" => {
+            for (index, c) in argument.chars().enumerate() {
+                events.push(KeyEvent {
+                    trigger: if index == 0 { trigger } else { Trigger::Poll },
+                    key_code: c as u16,
+                });
+                events.push(KeyEvent {
+                    trigger: Trigger::Poll,
+                    key_code: 0,
+                });
+            }
+        }
+        _ => anyhow::bail!("expected 'press KEY', 'release' or 'type TEXT'"),
+    }
+    Ok(())
+}
+
+fn key_code(key: &str) -> anyhow::Result<u16> {
+    let upper = key.to_ascii_uppercase();
+    if let Some((_, key_code)) = KEY_NAMES.iter().find(|(name, _)| *name == upper) {
+        return Ok(*key_code);
+    }
+    if let Some(number) = upper.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+        if (1..=12).contains(&number) {
+            return Ok(F1_KEY_CODE + number - 1);
+        }
+    }
+    if let Some(code) = key.strip_prefix('#') {
+        return Ok(code.parse()?);
+    }
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => Ok(c as u16),
+        _ => anyhow::bail!("unknown key: {key:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_keyboard() {
+        // KBD が 0 以外になるのを待ち、0 に戻るのを待ってから RAM[100..] に順に保存する
+        let code = [
+            "@100",
+            "D=A",
+            "@p",
+            "M=D",
+            "(WAIT_PRESS)",
+            "@KBD",
+            "D=M",
+            "@WAIT_PRESS",
+            "D;JEQ",
+            "@p",
+            "A=M",
+            "M=D",
+            "@p",
+            "M=M+1",
+            "(WAIT_RELEASE)",
+            "@KBD",
+            "D=M",
+            "@WAIT_RELEASE",
+            "D;JNE",
+            "@WAIT_PRESS",
+            "0;JMP",
+        ]
+        .join("\n");
+        let mut computer = Computer::from_commands(schema::hack::parse(code).unwrap()).unwrap();
+        let mut keyboard =
+            ScriptedKeyboard::parse("// line\npoll type -1\npoll press enter\npoll release")
+                .unwrap();
+        computer.run_with_keyboard(&mut keyboard, 10_000);
+
+        assert!(keyboard.is_finished());
+        assert_eq!(computer.ram_slice(100, 4), &[45, 49, 128, 0]);
+
+        assert_eq!(key_code("F12").unwrap(), 152);
+        assert_eq!(key_code("#7").unwrap(), 7);
+        assert!(ScriptedKeyboard::parse("poll press NOPE").is_err());
+    }
+}
@@ -0,0 +1,15 @@
+mod computer;
+mod coverage;
+mod keyboard;
+mod memory;
+mod profiler;
+mod screen;
+mod test_script;
+
+pub use computer::{Computer, RunResult};
+pub use coverage::{Coverage, CoverageReport, LabelCoverage, LineCoverage};
+pub use keyboard::{KeyEvent, ScriptedKeyboard, Trigger};
+pub use memory::{KBD_ADDRESS, RAM_SIZE, ROM_SIZE, SCREEN_ADDRESS, SCREEN_SIZE};
+pub use profiler::Profiler;
+pub use screen::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use test_script::{ComparisonFailure, TestReport, TestScript};
@@ -0,0 +1,188 @@
+use emulator::{
+    Computer, Coverage, Framebuffer, Profiler, RunResult, ScriptedKeyboard, TestScript,
+};
+use schema::hack;
+use std::path::Path;
+
+const DEFAULT_MAX_CYCLES: u64 = 10_000_000;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    // 実行する .asm 又は機械語 (.hack, .bin, .hex, .rom) ファイル、又はテストスクリプト (.tst) のパス
+    let input_path: &Path = Path::new(args.get(1).ok_or_else(|| {
+        anyhow::anyhow!(
+            "usage: emulator <file> [max_cycles] [--screen <file.pbm|file.png>] [--print-screen <ascii|braille>] [--golden <file.pbm|file.png>] [--keyboard <script>] [--profile <report>] [--folded <file>] [--coverage <file>] [--lcov <file>]"
+        )
+    })?);
+    // --screen <file>: 実行後のスクリーンを画像 (.pbm, .png) として出力する
+    // --print-screen <ascii|braille>: 実行後のスクリーンを文字で標準出力に表示する
+    // --golden <file>: 実行後のスクリーンを画像と比較し、異なれば失敗する
+    // --keyboard <script>: スクリプトに従ってキーボードに入力しながら実行する
+    // --profile <report>: ラベルごと、命令ごとの実行回数を多い順に出力する (.asm のみ)
+    // --folded <file>: flamegraph 用の folded stack 形式で実行命令数を出力する (.asm のみ)
+    // --coverage <file>: ソースの各行に実行回数を付けて出力する (.asm のみ)
+    // --lcov <file>: カバレッジを lcov 形式で出力する (.asm のみ)
+    let mut max_cycles = DEFAULT_MAX_CYCLES;
+    let mut screen_path = None;
+    let mut print_screen = None;
+    let mut golden_path = None;
+    let mut keyboard = None;
+    let mut profile_path = None;
+    let mut folded_path = None;
+    let mut coverage_path = None;
+    let mut lcov_path = None;
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{option} requires a value"))
+        };
+        match option.as_str() {
+            "--screen" => screen_path = Some(Path::new(value()?)),
+            "--print-screen" => print_screen = Some(value()?.as_str()),
+            "--golden" => golden_path = Some(Path::new(value()?)),
+            "--profile" => profile_path = Some(Path::new(value()?)),
+            "--folded" => folded_path = Some(Path::new(value()?)),
+            "--coverage" => coverage_path = Some(Path::new(value()?)),
+            "--lcov" => lcov_path = Some(Path::new(value()?)),
+            "--keyboard" => {
+                let script_path = value()?;
+                keyboard = Some(
+                    ScriptedKeyboard::parse(&std::fs::read_to_string(script_path)?)
+                        .map_err(|e| anyhow::anyhow!("{script_path}: {e}"))?,
+                );
+            }
+            _ => {
+                max_cycles = option
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("unknown option: {option}"))?
+            }
+        }
+    }
+
+    // .tst: CPU Emulator のテストスクリプトを実行し、.cmp ファイルと比較する
+    if input_path.extension() == Some("tst".as_ref()) {
+        let report = TestScript::run_file(input_path, max_cycles)?;
+        match report.compared {
+            Some(Ok(())) => println!("End of script - Comparison ended successfully"),
+            Some(Err(failure)) => anyhow::bail!("{}: {failure}", input_path.display()),
+            None => print!("{}", report.output),
+        }
+        return Ok(());
+    }
+
+    // プロファイルとカバレッジにはラベルと行番号が必要なので、.asm はソースと構文解析したコマンドを残す
+    let (mut computer, assembly) = match input_path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm") => {
+            let source = std::fs::read_to_string(input_path)?;
+            let assembled = hack::parse_with_source(source.clone())
+                .map_err(|errors| assembler::AssembleError::Parse(errors.0))
+                .and_then(|source_commands| {
+                    let assembled = assembler::assemble_source_commands(&source_commands)?;
+                    Ok((source_commands, assembled))
+                });
+            let (source_commands, assembled) =
+                assembled.map_err(|e| anyhow::anyhow!("{}: {e}", input_path.display()))?;
+            (
+                Computer::new(&assembled.words)?,
+                Some((source, source_commands)),
+            )
+        }
+        _ => (
+            Computer::new(&assembler::file_format::read_file(input_path)?)?,
+            None,
+        ),
+    };
+    let requires_assembly = [profile_path, folded_path, coverage_path, lcov_path]
+        .iter()
+        .any(Option::is_some);
+    if requires_assembly && assembly.is_none() {
+        anyhow::bail!("--profile, --folded, --coverage and --lcov require an .asm file");
+    }
+    let mut profiler = match (&assembly, profile_path.or(folded_path)) {
+        // 関数が呼び出されれば関数単位、そうでなければ全てのラベル単位で集計する
+        (Some((_, source_commands)), Some(_)) => Some(Profiler::new(source_commands)),
+        _ => None,
+    };
+    let mut coverage = coverage_path.or(lcov_path).map(|_| Coverage::new());
+
+    let result = computer.run_with(max_cycles, |computer| {
+        if let Some(keyboard) = &mut keyboard {
+            keyboard.update(computer);
+        }
+        if let Some(profiler) = &mut profiler {
+            profiler.record(computer);
+        }
+        if let Some(coverage) = &mut coverage {
+            coverage.record(computer);
+        }
+    });
+    println!(
+        "{}: PC={} A={} D={} cycles={}",
+        match result {
+            RunResult::Halted => "halted",
+            RunResult::CycleLimit => "cycle limit reached",
+        },
+        computer.pc(),
+        computer.a(),
+        computer.d(),
+        computer.cycles()
+    );
+    for (address, value) in computer.ram_slice(0, 16).iter().enumerate() {
+        println!("RAM[{address}] = {}", *value as i16);
+    }
+
+    if let Some(profiler) = &profiler {
+        if let Some(profile_path) = profile_path {
+            std::fs::write(profile_path, profiler.hotspot_report())?;
+        }
+        if let Some(folded_path) = folded_path {
+            std::fs::write(folded_path, profiler.folded_stacks())?;
+        }
+    }
+
+    if let (Some(coverage), Some((source, source_commands))) = (&mut coverage, &assembly) {
+        // 停止した命令（無限ループのジャンプ）も実行されたとみなす
+        if result == RunResult::Halted {
+            coverage.record(&computer);
+        }
+        let report = coverage.report(source_commands);
+        println!("{}", report.summary());
+        if let Some(coverage_path) = coverage_path {
+            std::fs::write(coverage_path, report.annotate(source))?;
+        }
+        if let Some(lcov_path) = lcov_path {
+            std::fs::write(lcov_path, report.to_lcov(&input_path.to_string_lossy()))?;
+        }
+    }
+
+    let framebuffer = Framebuffer::from_screen(computer.screen());
+    if let Some(screen_path) = screen_path {
+        framebuffer.write_file(screen_path)?;
+    }
+    match print_screen {
+        Some("ascii") => println!("{}", framebuffer.to_ascii()),
+        Some("braille") => println!("{}", framebuffer.to_braille()),
+        Some(style) => anyhow::bail!("unknown screen style: {style} (ascii or braille)"),
+        None => {}
+    }
+    if let Some(golden_path) = golden_path {
+        let golden = Framebuffer::read_file(golden_path)?;
+        match framebuffer.diff(&golden) {
+            0 => println!("screen matches {}", golden_path.display()),
+            count => anyhow::bail!(
+                "screen differs from {}: {count} pixels differ",
+                golden_path.display()
+            ),
+        }
+    }
+    Ok(())
+}
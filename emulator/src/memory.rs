@@ -0,0 +1,49 @@
+//! Hack コンピュータのメモリマップ
+
+// 定数はアセンブラと共通 (schema::hack)
+pub use schema::hack::{KBD_ADDRESS, RAM_SIZE, ROM_SIZE, SCREEN_ADDRESS, SCREEN_SIZE};
+
+// アドレスバスは 15bit なので、上位 1bit は無視される
+pub(crate) fn address(value: u16) -> usize {
+    (value & 0x7fff) as usize
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Rom(Vec<u16>);
+
+impl Rom {
+    pub(crate) fn new(program: &[u16]) -> anyhow::Result<Self> {
+        if program.len() > ROM_SIZE {
+            anyhow::bail!(
+                "program has {} instructions, but ROM can hold only {} words.",
+                program.len(),
+                ROM_SIZE
+            );
+        }
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        Ok(Self(rom))
+    }
+    pub(crate) fn get(&self, address: u16) -> u16 {
+        self.0[self::address(address)]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Ram(Vec<u16>);
+
+impl Ram {
+    pub(crate) fn new() -> Self {
+        Self(vec![0; RAM_SIZE])
+    }
+    pub(crate) fn get(&self, address: u16) -> u16 {
+        self.0[self::address(address)]
+    }
+    pub(crate) fn set(&mut self, address: u16, value: u16) {
+        self.0[self::address(address)] = value;
+    }
+    pub(crate) fn slice(&self, start: u16, len: usize) -> &[u16] {
+        let start = self::address(start);
+        &self.0[start..(start + len).min(RAM_SIZE)]
+    }
+}
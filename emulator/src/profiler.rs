@@ -0,0 +1,532 @@
+//! 実行された命令数を ROM アドレスごとに数えるプロファイラ
+//!
+//! 各命令の実行回数をその命令の直前にあるラベルに集計したホットスポットの一覧と、
+//! flamegraph 用の folded stack 形式 (`Sys.init;Main.main;Math.multiply 1234`) を出力する。
+//! vm_translator の呼び出し規約で関数が呼ばれていれば、呼ばれた関数の入口ごとに集計する。
+
+use crate::computer::Computer;
+use crate::memory::ROM_SIZE;
+use schema::hack;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+// ホットスポットの一覧に表示する命令の数
+const TOP_INSTRUCTIONS: usize = 20;
+
+// 呼び出し規約で使う RAM アドレス
+const SP: u16 = 0;
+const LCL: u16 = 1;
+// call で積むフレームの大きさ（戻りアドレス, LCL, ARG, THIS, THAT）
+const FRAME_SIZE: u16 = 5;
+
+// 呼び出しの戻りアドレスから呼び出し先のシンボルを探す命令数
+// (call の命令列は戻りアドレスの直前で呼び出し先のアドレスを A レジスタに読み込んでいる)
+const CALL_SITE_LENGTH: u16 = 64;
+
+#[derive(Debug, Clone)]
+struct Frame {
+    entry: u16,
+    return_address: u16,
+    lcl: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    // ラベル（アドレス順、同じアドレスのものは定義順）
+    labels: Vec<(String, u16)>,
+    // ROM アドレスごとの A命令のシンボル
+    rom_symbols: Vec<Option<String>>,
+    counts: Vec<u64>,
+    previous_pc: Option<u16>,
+    // 最初のジャンプが呼び出しでなければその行き先（ブートストラップからの Sys.init へのジャンプ）
+    root: Option<u16>,
+    jumped_once: bool,
+    // 呼び出された関数の入口
+    entries: BTreeSet<u16>,
+    // 関数から関数より前の命令へジャンプした先（--compact の共通ルーチンなど）
+    routines: BTreeSet<u16>,
+    // 呼び出し元の A命令から分かった入口の名前（同じアドレスに複数のラベルがありうる）
+    names: HashMap<u16, String>,
+    // 関数の呼び出しで積み、戻りアドレスに来たら降ろす呼び出しスタック
+    stack: Vec<Frame>,
+    // 呼び出しスタックが空の間の命令ごとの実行回数
+    unstacked_counts: Vec<u64>,
+    // 呼び出しスタック（関数の入口の列）ごとの実行命令数
+    stack_ids: HashMap<Vec<u16>, usize>,
+    stack_counts: Vec<u64>,
+    current_stack_id: Option<usize>,
+}
+
+impl Profiler {
+    pub fn new(source_commands: &[hack::SourceCommand]) -> Self {
+        let mut labels: Vec<(String, u16)> = Vec::new();
+        let mut rom_symbols: Vec<Option<String>> = Vec::new();
+        for source_command in source_commands {
+            match &source_command.command {
+                hack::Command::L(symbol) => {
+                    labels.push((symbol.get().to_string(), rom_symbols.len() as u16))
+                }
+                hack::Command::A(hack::ACommand::Symbol(symbol)) => {
+                    rom_symbols.push(Some(symbol.get().to_string()))
+                }
+                _ => rom_symbols.push(None),
+            }
+        }
+        labels.sort_by_key(|(_, address)| *address);
+        Self {
+            labels,
+            rom_symbols,
+            counts: vec![0; ROM_SIZE],
+            previous_pc: None,
+            root: None,
+            jumped_once: false,
+            entries: BTreeSet::new(),
+            routines: BTreeSet::new(),
+            names: HashMap::new(),
+            stack: Vec::new(),
+            unstacked_counts: vec![0; ROM_SIZE],
+            stack_ids: HashMap::new(),
+            stack_counts: Vec::new(),
+            current_stack_id: None,
+        }
+    }
+
+    // 次に実行する命令を記録する（Computer::run_with から命令の実行前に呼ぶ）
+    pub fn record(&mut self, computer: &Computer) {
+        let pc = computer.pc();
+        self.counts[pc as usize] += 1;
+
+        if let Some(previous_pc) = self.previous_pc {
+            // 直後のアドレスへのジャンプもあるので、ジャンプ命令の後は常に調べる
+            let is_jump_instruction = computer.rom(previous_pc) & 0x8007 > 0x8000;
+            if previous_pc.wrapping_add(1) != pc || is_jump_instruction {
+                self.jumped(computer, previous_pc, pc);
+            }
+        }
+        self.previous_pc = Some(pc);
+
+        if self.stack.is_empty() {
+            self.unstacked_counts[pc as usize] += 1;
+            return;
+        }
+        let stack_id = match self.current_stack_id {
+            Some(stack_id) => stack_id,
+            None => {
+                let key: Vec<u16> = self.stack.iter().map(|frame| frame.entry).collect();
+                let next_id = self.stack_ids.len();
+                let stack_id = *self.stack_ids.entry(key).or_insert(next_id);
+                if stack_id == self.stack_counts.len() {
+                    self.stack_counts.push(0);
+                }
+                self.current_stack_id = Some(stack_id);
+                stack_id
+            }
+        };
+        self.stack_counts[stack_id] += 1;
+    }
+
+    fn jumped(&mut self, computer: &Computer, from: u16, to: u16) {
+        let is_first_jump = !self.jumped_once;
+        self.jumped_once = true;
+
+        // 呼び出し元への復帰
+        if from.wrapping_add(1) != to {
+            if let Some(position) = self
+                .stack
+                .iter()
+                .rposition(|frame| frame.return_address == to)
+            {
+                self.stack.truncate(position);
+                self.current_stack_id = None;
+                return;
+            }
+        }
+
+        // 関数の呼び出し: 戻りアドレスと呼び出し元の LCL, ARG, THIS, THAT を積み、
+        // LCL = SP としてからラベルへジャンプしている
+        let sp = computer.ram(SP);
+        let lcl = computer.ram(LCL);
+        let is_call = sp == lcl
+            && sp >= FRAME_SIZE
+            && self.stack.last().is_none_or(|frame| frame.lcl != lcl)
+            && self.is_label_address(to)
+            && self.is_label_address(computer.ram(sp - FRAME_SIZE));
+        if is_call {
+            let return_address = computer.ram(sp - FRAME_SIZE);
+            if !self.names.contains_key(&to) {
+                if let Some(name) = self.called_symbol(return_address, to) {
+                    self.names.insert(to, name);
+                }
+            }
+            self.entries.insert(to);
+            self.stack.push(Frame {
+                entry: to,
+                return_address,
+                lcl,
+            });
+            self.current_stack_id = None;
+            return;
+        }
+
+        let first_entry = self.entries.first().into_iter().chain(&self.root).min();
+        let is_root = is_first_jump;
+        let is_routine = first_entry
+            .is_some_and(|first_entry| to < *first_entry && from >= *first_entry)
+            && !self.routines.contains(&to);
+        if !(is_root || is_routine) {
+            return;
+        }
+        let Some(name) = self.jumped_symbol(from, to) else {
+            return;
+        };
+        if is_root {
+            self.root = Some(to);
+        } else {
+            self.routines.insert(to);
+        }
+        self.names.entry(to).or_insert(name);
+    }
+
+    fn is_label_address(&self, rom_address: u16) -> bool {
+        self.labels
+            .binary_search_by_key(&rom_address, |(_, address)| *address)
+            .is_ok()
+    }
+
+    // A命令のシンボルが to のラベルであれば、その名前
+    fn symbol_at(&self, rom_address: u16, to: u16) -> Option<String> {
+        let symbol = self.rom_symbols.get(rom_address as usize)?.as_ref()?;
+        self.labels
+            .iter()
+            .any(|(name, address)| name == symbol && *address == to)
+            .then(|| symbol.clone())
+    }
+
+    // '@X, 0;JMP' のようにジャンプ命令の直前で読み込んだラベル
+    fn jumped_symbol(&self, from: u16, to: u16) -> Option<String> {
+        self.symbol_at(from.checked_sub(1)?, to)
+    }
+
+    // 戻りアドレスの手前の call の命令列で読み込んだ呼び出し先のラベル
+    fn called_symbol(&self, return_address: u16, to: u16) -> Option<String> {
+        (return_address.saturating_sub(CALL_SITE_LENGTH)..return_address)
+            .rev()
+            .find_map(|rom_address| self.symbol_at(rom_address, to))
+    }
+
+    // 指定した ROM アドレスを含む集計単位の先頭アドレス
+    // 関数が呼び出されていれば関数の入口（関数より前の命令は共通ルーチンの入口）、
+    // そうでなければ直前のラベル
+    fn unit_address(&self, rom_address: u16) -> Option<u16> {
+        if !self.entries.is_empty() {
+            let unit = self
+                .entries
+                .range(..=rom_address)
+                .next_back()
+                .into_iter()
+                .chain(self.root.as_ref().filter(|root| **root <= rom_address))
+                .max()
+                .or_else(|| self.routines.range(..=rom_address).next_back());
+            if unit.is_some() {
+                return unit.copied();
+            }
+        }
+        let index = self
+            .labels
+            .partition_point(|(_, address)| *address <= rom_address)
+            .checked_sub(1)?;
+        Some(self.labels[index].1)
+    }
+
+    // 呼び出し元から分かった名前か、アドレスの最初のラベル
+    fn unit_name(&self, unit_address: Option<u16>) -> &str {
+        unit_address.map_or("<no label>", |unit_address| {
+            if let Some(name) = self.names.get(&unit_address) {
+                return name.as_str();
+            }
+            let index = self
+                .labels
+                .partition_point(|(_, address)| *address < unit_address);
+            self.labels[index].0.as_str()
+        })
+    }
+
+    // 実行された命令の総数
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn count(&self, rom_address: u16) -> u64 {
+        self.counts[rom_address as usize]
+    }
+
+    // ラベルごとの実行命令数（多い順）
+    pub fn label_counts(&self) -> Vec<(&str, u64)> {
+        let mut label_counts: HashMap<Option<u16>, u64> = HashMap::new();
+        for (address, count) in self.counts.iter().enumerate() {
+            if *count > 0 {
+                *label_counts
+                    .entry(self.unit_address(address as u16))
+                    .or_default() += count;
+            }
+        }
+        let mut label_counts: Vec<_> = label_counts
+            .into_iter()
+            .map(|(unit_address, count)| (self.unit_name(unit_address), count))
+            .collect();
+        label_counts.sort_by(|(name_a, count_a), (name_b, count_b)| {
+            count_b.cmp(count_a).then(name_a.cmp(name_b))
+        });
+        label_counts
+    }
+
+    // ラベルごと、命令ごとの実行回数を多い順に並べたレポート
+    pub fn hotspot_report(&self) -> String {
+        let total = self.total();
+        let percent = |count: u64| count as f64 * 100.0 / total.max(1) as f64;
+        let mut report = String::new();
+        writeln!(report, "total: {total} instructions").unwrap();
+
+        writeln!(report, "\n[labels]").unwrap();
+        writeln!(report, "{:>12} {:>7}  label", "count", "%").unwrap();
+        for (name, count) in self.label_counts() {
+            writeln!(report, "{count:>12} {:>6.2}%  {name}", percent(count)).unwrap();
+        }
+
+        writeln!(report, "\n[instructions]").unwrap();
+        writeln!(report, "{:>12} {:>7}  {:>5}  location", "count", "%", "rom").unwrap();
+        let mut instructions: Vec<(usize, u64)> = self
+            .counts
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        instructions.sort_by(|(address_a, count_a), (address_b, count_b)| {
+            count_b.cmp(count_a).then(address_a.cmp(address_b))
+        });
+        for (address, count) in instructions.into_iter().take(TOP_INSTRUCTIONS) {
+            let unit_address = self.unit_address(address as u16);
+            let location = match unit_address {
+                Some(unit_address) => format!(
+                    "{}+{}",
+                    self.unit_name(Some(unit_address)),
+                    address as u16 - unit_address
+                ),
+                None => address.to_string(),
+            };
+            writeln!(
+                report,
+                "{count:>12} {:>6.2}%  {address:>5}  {location}",
+                percent(count)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    // flamegraph 用の folded stack 形式（'呼び出し元;...;関数 実行命令数' を1行ずつ）
+    // 呼び出しスタックが空の間は集計単位ごと、そうでなければ最初のジャンプ先を根とする
+    pub fn folded_stacks(&self) -> String {
+        let mut unstacked: HashMap<Option<u16>, u64> = HashMap::new();
+        for (address, count) in self.unstacked_counts.iter().enumerate() {
+            if *count > 0 {
+                *unstacked
+                    .entry(self.unit_address(address as u16))
+                    .or_default() += count;
+            }
+        }
+        let mut lines: Vec<String> = unstacked
+            .into_iter()
+            .map(|(unit_address, count)| format!("{} {count}", self.unit_name(unit_address)))
+            .chain(
+                self.stack_ids
+                    .iter()
+                    .filter(|(_, stack_id)| self.stack_counts[**stack_id] > 0)
+                    .map(|(key, stack_id)| {
+                        let frames: Vec<&str> = self
+                            .root
+                            .iter()
+                            .chain(key)
+                            .map(|entry| self.unit_name(Some(*entry)))
+                            .collect();
+                        format!("{} {}", frames.join(";"), self.stack_counts[*stack_id])
+                    }),
+            )
+            .collect();
+        lines.sort();
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // vm_translator と同じ命令列で 'call function 0' をする
+    fn call(function: &str, return_label: &str) -> Vec<String> {
+        let push_d = ["@SP", "A=M", "M=D", "@SP", "M=M+1"];
+        [format!("@{return_label}"), "D=A".to_string()]
+            .into_iter()
+            .chain(push_d.map(String::from))
+            .chain(
+                ["LCL", "ARG", "THIS", "THAT"]
+                    .into_iter()
+                    .flat_map(|segment| {
+                        [format!("@{segment}"), "D=M".to_string()]
+                            .into_iter()
+                            .chain(push_d.map(String::from))
+                    }),
+            )
+            .chain(
+                [
+                    "@SP", "D=M", "@5", "D=D-A", "@ARG", "M=D", "@SP", "D=M", "@LCL", "M=D",
+                ]
+                .map(String::from),
+            )
+            .chain([format!("@{function}"), "0;JMP".to_string()])
+            .chain([format!("({return_label})")])
+            .collect()
+    }
+
+    // 0 を返す 'return'
+    fn return_zero() -> Vec<String> {
+        [
+            "@SP", "A=M", "M=0", "@SP", "M=M+1", // push constant 0
+            "@LCL", "D=M", "@R13", "M=D", // FRAME = LCL
+            "@5", "A=D-A", "D=M", "@R14", "M=D", // RET = *(FRAME-5)
+            "@SP", "AM=M-1", "D=M", "@ARG", "A=M", "M=D", // *ARG = pop()
+            "@ARG", "D=M+1", "@SP", "M=D", // SP = ARG+1
+            "@R13", "AM=M-1", "D=M", "@THAT", "M=D", // THAT = *(FRAME-1)
+            "@R13", "AM=M-1", "D=M", "@THIS", "M=D", // THIS = *(FRAME-2)
+            "@R13", "AM=M-1", "D=M", "@ARG", "M=D", // ARG = *(FRAME-3)
+            "@R13", "AM=M-1", "D=M", "@LCL", "M=D", // LCL = *(FRAME-4)
+            "@R14", "A=M", "0;JMP", // goto RET
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    fn run(code: &[String]) -> (Computer, Profiler) {
+        let source_commands = hack::parse_with_source(code.join("\n")).unwrap();
+        let assembled = assembler::assemble_source_commands(&source_commands).unwrap();
+        let mut computer = Computer::new(&assembled.words).unwrap();
+        let mut profiler = Profiler::new(&source_commands);
+        computer.run_with(100000, |computer| profiler.record(computer));
+        (computer, profiler)
+    }
+
+    #[test]
+    fn test_profiler() {
+        // Sys.init から Main.double を2回呼ぶ
+        let code: Vec<String> = ["@256", "D=A", "@SP", "M=D", "@Sys.init", "0;JMP"]
+            .map(String::from)
+            .into_iter()
+            .chain(["(Main.double)", "@R5", "M=M+1"].map(String::from))
+            .chain(return_zero())
+            .chain(["(Sys.init)".to_string()])
+            .chain(call("Main.double", "return_address_Sys.init.0"))
+            .chain(call("Main.double", "return_address_Sys.init.1"))
+            .chain(["(END)", "@END", "0;JMP"].map(String::from))
+            .collect();
+        let (computer, profiler) = run(&code);
+
+        assert_eq!(computer.ram(5), 2);
+        // ブートストラップ 6 + Sys.init の call 45 x 2 と停止までの 5 + Main.double 49 x 2
+        assert_eq!(profiler.total(), 199);
+        assert_eq!(
+            profiler.label_counts(),
+            vec![("Main.double", 98), ("Sys.init", 95), ("<no label>", 6)]
+        );
+        assert_eq!(
+            profiler.folded_stacks(),
+            "<no label> 6\nSys.init 95\nSys.init;Main.double 98\n"
+        );
+        assert!(profiler.hotspot_report().contains("Main.double+1"));
+    }
+
+    #[test]
+    fn test_profiler_with_loop_labels() {
+        // コンパイラの while 文のラベルは '.' を含むが、関数の入口にはならない
+        // (Main.main はローカル変数がないので while 文のラベルと同じアドレスになり、
+        // Main.inc は直前の関数の末尾にある if 文のラベルと同じアドレスになる)
+        let code: Vec<String> = ["@256", "D=A", "@SP", "M=D", "@Sys.init", "0;JMP"]
+            .map(String::from)
+            .into_iter()
+            .chain(["(init.If.0.L2)", "(Main.inc)", "@R5", "M=M+1"].map(String::from))
+            .chain(return_zero())
+            .chain(
+                [
+                    "(Main.main)",
+                    "(main.While.0.L1)",
+                    "@R6",
+                    "D=M",
+                    "@3",
+                    "D=D-A",
+                    "@main.While.0.L2",
+                    "D;JGE",
+                    "@R6",
+                    "M=M+1",
+                ]
+                .map(String::from),
+            )
+            .chain(call("Main.inc", "return_address_Main.Main.main.0"))
+            .chain(
+                [
+                    "@SP",
+                    "M=M-1",
+                    "@main.While.0.L1",
+                    "0;JMP",
+                    "(main.While.0.L2)",
+                ]
+                .map(String::from),
+            )
+            .chain(return_zero())
+            .chain(["(Sys.init)".to_string()])
+            .chain(call("Main.main", "return_address_Sys.init.0"))
+            .chain(["(END)", "@END", "0;JMP"].map(String::from))
+            .collect();
+        let (computer, profiler) = run(&code);
+
+        assert_eq!(computer.ram(5), 3);
+        let folded = profiler.folded_stacks();
+        assert!(!folded.contains("While"), "{folded}");
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            vec![
+                "<no label>",
+                "Sys.init",
+                "Sys.init;Main.main",
+                "Sys.init;Main.main;Main.inc"
+            ]
+        );
+        let label_names: Vec<&str> = profiler
+            .label_counts()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(label_names.len(), 4);
+        assert!(label_names.starts_with(&["Main.main"]), "{label_names:?}");
+    }
+
+    #[test]
+    fn test_profiler_without_calls() {
+        // 関数の呼び出しがなければ全てのラベルに集計する
+        let code: Vec<String> = [
+            "@3", "D=A", "@R0", "M=D", "(LOOP)", "@R0", "MD=M-1", "@LOOP", "D;JGT", "(END)",
+            "@END", "0;JMP",
+        ]
+        .map(String::from)
+        .to_vec();
+        let (_, profiler) = run(&code);
+        assert_eq!(
+            profiler.label_counts(),
+            vec![("LOOP", 12), ("<no label>", 4), ("END", 1)]
+        );
+        assert_eq!(profiler.count(4), 3);
+    }
+}
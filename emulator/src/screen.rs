@@ -0,0 +1,269 @@
+//! スクリーンのメモリマップを画像として扱う
+//!
+//! 各 word の最下位 bit が左端の pixel で、1 が黒を表す。
+
+use crate::memory::SCREEN_SIZE;
+use std::path::Path;
+
+pub const SCREEN_WIDTH: usize = 512;
+pub const SCREEN_HEIGHT: usize = 256;
+
+// 512 x 256 の白黒画像（true が黒）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    pub fn from_screen(screen: &[u16]) -> Self {
+        let mut pixels = vec![false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (index, word) in screen.iter().take(SCREEN_SIZE).enumerate() {
+            for bit in 0..16 {
+                pixels[index * 16 + bit] = word & (1 << bit) != 0;
+            }
+        }
+        Self { pixels }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    // 異なる pixel の数
+    pub fn diff(&self, other: &Self) -> usize {
+        self.pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+
+    // 拡張子 (.pbm, .png) で形式を決めて読み書きする
+    pub fn read_file(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pbm") => Self::from_pbm(&bytes),
+            Some("png") => Self::from_png(&bytes),
+            _ => anyhow::bail!("unknown image format: {} (.pbm or .png)", path.display()),
+        }
+    }
+
+    pub fn write_file(&self, path: &Path) -> anyhow::Result<()> {
+        let bytes = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pbm") => self.to_pbm(),
+            Some("png") => self.to_png()?,
+            _ => anyhow::bail!("unknown image format: {} (.pbm or .png)", path.display()),
+        };
+        Ok(std::fs::write(path, bytes)?)
+    }
+
+    // PBM (P4, 1bit/pixel の binary 形式)
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut bytes = format!("P4\n{SCREEN_WIDTH} {SCREEN_HEIGHT}\n").into_bytes();
+        bytes.extend(self.packed_rows());
+        bytes
+    }
+
+    // P1 (ASCII) 及び P4 (binary) 形式の PBM を読み込む
+    pub fn from_pbm(bytes: &[u8]) -> anyhow::Result<Self> {
+        // ヘッダ (magic, width, height) をコメントを飛ばしながら読む
+        let mut header = Vec::new();
+        let mut position = 0;
+        while header.len() < 3 {
+            match bytes.get(position) {
+                None => anyhow::bail!("invalid PBM: unexpected end of header"),
+                Some(b'#') => {
+                    while bytes.get(position).is_some_and(|byte| *byte != b'\n') {
+                        position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => position += 1,
+                Some(_) => {
+                    let start = position;
+                    while bytes
+                        .get(position)
+                        .is_some_and(|byte| !byte.is_ascii_whitespace())
+                    {
+                        position += 1;
+                    }
+                    header.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+                }
+            }
+        }
+        if header[1..] != [SCREEN_WIDTH.to_string(), SCREEN_HEIGHT.to_string()] {
+            anyhow::bail!(
+                "invalid PBM: image size must be {SCREEN_WIDTH}x{SCREEN_HEIGHT}, but {}x{}",
+                header[1],
+                header[2]
+            );
+        }
+        let pixels: Vec<bool> = match header[0].as_str() {
+            "P1" => bytes[position..]
+                .iter()
+                .filter(|byte| matches!(byte, b'0' | b'1'))
+                .map(|byte| *byte == b'1')
+                .collect(),
+            // ヘッダの直後の空白1文字を読み飛ばす (途中で切れたファイルでは画素数の検査でエラーにする)
+            "P4" => bytes
+                .get(position + 1..)
+                .unwrap_or_default()
+                .iter()
+                .flat_map(|byte| (0..8).rev().map(move |bit| byte & (1 << bit) != 0))
+                .collect(),
+            magic => anyhow::bail!("invalid PBM: unsupported magic number {magic}"),
+        };
+        Self::from_pixels(pixels)
+    }
+
+    // 1bit grayscale の PNG（0 が黒）
+    pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        let data: Vec<u8> = self.packed_rows().map(|byte| !byte).collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(bytes)
+    }
+
+    // 明るさが半分未満の pixel を黒とみなす
+    pub fn from_png(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            anyhow::bail!(
+                "invalid PNG: image size must be {SCREEN_WIDTH}x{SCREEN_HEIGHT}, but {}x{}",
+                info.width,
+                info.height
+            );
+        }
+        let pixels = data[..info.buffer_size()]
+            .chunks(info.color_type.samples())
+            .map(|samples| match samples {
+                [gray] | [gray, _] => *gray < 128,
+                [r, g, b, ..] => (*r as u32 * 299 + *g as u32 * 587 + *b as u32 * 114) < 128_000,
+                _ => false,
+            })
+            .collect();
+        Self::from_pixels(pixels)
+    }
+
+    // 4 x 8 pixel を1文字で表す（黒の割合で ' ', '.', ':', '#'）
+    pub fn to_ascii(&self) -> String {
+        self.to_text(4, 8, |cell| {
+            let count = cell.iter().filter(|pixel| **pixel).count();
+            match count {
+                0 => ' ',
+                n if n * 4 < cell.len() => '.',
+                n if n * 2 < cell.len() => ':',
+                _ => '#',
+            }
+        })
+    }
+
+    // 2 x 4 pixel を点字1文字で表す
+    pub fn to_braille(&self) -> String {
+        // 点字の各点の bit 位置 (左列 上から, 右列 上から)
+        const DOTS: [u32; 8] = [0, 1, 2, 6, 3, 4, 5, 7];
+        self.to_text(2, 4, |cell| {
+            let bits = cell
+                .iter()
+                .enumerate()
+                .filter(|(_, pixel)| **pixel)
+                .map(|(index, _)| {
+                    // cell は行優先なので列優先の点の番号に変換する
+                    let (x, y) = (index % 2, index / 2);
+                    1 << DOTS[x * 4 + y]
+                })
+                .sum::<u32>();
+            char::from_u32(0x2800 + bits).unwrap()
+        })
+    }
+
+    fn to_text(&self, width: usize, height: usize, to_char: impl Fn(&[bool]) -> char) -> String {
+        (0..SCREEN_HEIGHT / height)
+            .map(|row| {
+                (0..SCREEN_WIDTH / width)
+                    .map(|column| {
+                        let cell: Vec<bool> = (0..height)
+                            .flat_map(|y| {
+                                (0..width).map(move |x| (column * width + x, row * height + y))
+                            })
+                            .map(|(x, y)| self.get(x, y))
+                            .collect();
+                        to_char(&cell)
+                    })
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // 1行を左端の pixel が最上位 bit になるように 8 pixel ずつ詰める
+    fn packed_rows(&self) -> impl Iterator<Item = u8> + '_ {
+        self.pixels.chunks(8).map(|pixels| {
+            pixels
+                .iter()
+                .fold(0, |byte, pixel| (byte << 1) | u8::from(*pixel))
+        })
+    }
+
+    fn from_pixels(mut pixels: Vec<bool>) -> anyhow::Result<Self> {
+        if pixels.len() < SCREEN_WIDTH * SCREEN_HEIGHT {
+            anyhow::bail!(
+                "image has only {} pixels, but {} pixels are required",
+                pixels.len(),
+                SCREEN_WIDTH * SCREEN_HEIGHT
+            );
+        }
+        pixels.truncate(SCREEN_WIDTH * SCREEN_HEIGHT);
+        Ok(Self { pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framebuffer() {
+        let mut screen = vec![0; SCREEN_SIZE];
+        // (0, 0), (1, 0), (17, 0), (0, 1)
+        screen[0] = 0b11;
+        screen[1] = 0b10;
+        screen[32] = 0b1;
+        let framebuffer = Framebuffer::from_screen(&screen);
+        assert!(framebuffer.get(0, 0) && framebuffer.get(1, 0) && framebuffer.get(17, 0));
+        assert!(framebuffer.get(0, 1) && !framebuffer.get(2, 0));
+
+        assert_eq!(
+            Framebuffer::from_pbm(&framebuffer.to_pbm()).unwrap(),
+            framebuffer
+        );
+        assert_eq!(
+            Framebuffer::from_png(&framebuffer.to_png().unwrap()).unwrap(),
+            framebuffer
+        );
+        let plain_pbm = format!(
+            "P1\n# plain\n512 256\n{}",
+            "1 ".repeat(3) + &"0 ".repeat(SCREEN_WIDTH * SCREEN_HEIGHT - 3)
+        );
+        assert_eq!(
+            Framebuffer::from_pbm(plain_pbm.as_bytes())
+                .unwrap()
+                .diff(&framebuffer),
+            3
+        );
+        assert!(Framebuffer::from_pbm(b"P4 512 256").is_err());
+        assert!(Framebuffer::from_pbm(b"P4 512 256\n").is_err());
+
+        // 左上の 2 x 4 pixel は左列の上2点と右列の上1点
+        assert!(framebuffer.to_braille().starts_with('\u{280b}'));
+        assert!(framebuffer.to_ascii().starts_with('.'));
+    }
+}
@@ -0,0 +1,567 @@
+//! nand2tetris CPU Emulator のテストスクリプト (.tst) の実行
+//!
+//! 対応するコマンド: `load`, `output-file`, `compare-to`, `output-list`, `set`, `repeat`,
+//! `while`, `ticktock`, `tick`, `tock`, `output`, `echo`, `clear-echo`
+//!
+//! 変数は `RAM[n]`, `PC`, `A`, `D`, `time` を使える。
+
+use crate::computer::Computer;
+use schema::hack;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable {
+    Ram(u16),
+    Pc,
+    A,
+    D,
+    Time,
+}
+
+// output-list の1列 (例: RAM[0]%D2.6.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OutputColumn {
+    variable: Variable,
+    name: String,
+    format: char,
+    left_padding: usize,
+    width: usize,
+    right_padding: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    variable: Variable,
+    operator: String,
+    value: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ScriptCommand {
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<OutputColumn>),
+    Set(Variable, i32),
+    Repeat(Option<u64>, Vec<ScriptCommand>),
+    While(Condition, Vec<ScriptCommand>),
+    TickTock,
+    Output,
+    Echo(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestScript {
+    commands: Vec<ScriptCommand>,
+}
+
+// .cmp ファイルとの最初の不一致
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparisonFailure {
+    pub line: usize,
+    pub column: usize,
+    pub name: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ComparisonFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "comparison failure at line {}, column {} ({}): expected {:?}, got {:?}",
+            self.line, self.column, self.name, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ComparisonFailure {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestReport {
+    // 出力された表 (.out の内容)
+    pub output: String,
+    // compare-to が無ければ None
+    pub compared: Option<Result<(), ComparisonFailure>>,
+}
+
+impl TestScript {
+    pub fn parse(script: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(&remove_comments(script));
+        let mut tokens = tokens.iter().map(String::as_str).peekable();
+        let commands = parse_commands(&mut tokens, false)?;
+        Ok(Self { commands })
+    }
+
+    // .tst ファイルを読み込んで実行する
+    // load, output-file, compare-to のパスは .tst ファイルのディレクトリからの相対パス
+    pub fn run_file(path: &Path, max_cycles: u64) -> anyhow::Result<TestReport> {
+        let script = Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        let base_directory = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        script.run(&base_directory, max_cycles)
+    }
+
+    pub fn run(&self, base_directory: &Path, max_cycles: u64) -> anyhow::Result<TestReport> {
+        let mut runner = Runner {
+            base_directory,
+            max_cycles,
+            computer: Computer::new(&[])?,
+            output_path: None,
+            compare_lines: None,
+            output_list: Vec::new(),
+            output_lines: Vec::new(),
+            failure: None,
+        };
+        let result = runner.run(&self.commands);
+        if let Some(output_path) = &runner.output_path {
+            std::fs::write(output_path, runner.output())?;
+        }
+        result?;
+        Ok(TestReport {
+            output: runner.output(),
+            compared: runner
+                .compare_lines
+                .map(|_| runner.failure.map_or(Ok(()), Err)),
+        })
+    }
+}
+
+struct Runner<'a> {
+    base_directory: &'a Path,
+    max_cycles: u64,
+    computer: Computer,
+    output_path: Option<PathBuf>,
+    compare_lines: Option<Vec<String>>,
+    output_list: Vec<OutputColumn>,
+    output_lines: Vec<String>,
+    failure: Option<ComparisonFailure>,
+}
+
+impl Runner<'_> {
+    // 比較に失敗したら、そこで実行を終える
+    fn run(&mut self, commands: &[ScriptCommand]) -> anyhow::Result<()> {
+        for command in commands {
+            if self.failure.is_some() {
+                break;
+            }
+            match command {
+                ScriptCommand::Load(file) => {
+                    let path = self.base_directory.join(file.as_deref().ok_or_else(|| {
+                        anyhow::anyhow!("'load' without a file name is not supported")
+                    })?);
+                    self.computer = match path.extension().and_then(|ext| ext.to_str()) {
+                        Some("asm") => {
+                            Computer::from_commands(hack::parse(std::fs::read_to_string(&path)?)?)?
+                        }
+                        _ => Computer::new(&assembler::file_format::read_file(&path)?)?,
+                    };
+                }
+                ScriptCommand::OutputFile(file) => {
+                    self.output_path = Some(self.base_directory.join(file))
+                }
+                ScriptCommand::CompareTo(file) => {
+                    let path = self.base_directory.join(file);
+                    let compare = std::fs::read_to_string(&path)
+                        .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+                    self.compare_lines = Some(compare.lines().map(str::to_string).collect());
+                }
+                ScriptCommand::OutputList(columns) => {
+                    self.output_list = columns.clone();
+                    let header = self
+                        .output_list
+                        .iter()
+                        .map(|column| {
+                            let width = column.left_padding + column.width + column.right_padding;
+                            let name: String = column.name.chars().take(width).collect();
+                            let left = (width - name.chars().count()) / 2;
+                            format!("{}{name:width$}", " ".repeat(left), width = width - left)
+                        })
+                        .collect();
+                    self.write_line(header);
+                }
+                ScriptCommand::Set(variable, value) => self.set(variable, *value),
+                ScriptCommand::Repeat(count, commands) => match count {
+                    Some(count) => {
+                        for _ in 0..*count {
+                            self.run(commands)?;
+                        }
+                    }
+                    None => {
+                        while self.failure.is_none() {
+                            self.run(commands)?;
+                            self.check_cycle_limit()?;
+                        }
+                    }
+                },
+                ScriptCommand::While(condition, commands) => {
+                    while self.failure.is_none() && self.evaluate(condition) {
+                        self.run(commands)?;
+                        self.check_cycle_limit()?;
+                    }
+                }
+                ScriptCommand::TickTock => {
+                    self.computer.step();
+                    self.check_cycle_limit()?;
+                }
+                ScriptCommand::Output => {
+                    let values = self
+                        .output_list
+                        .iter()
+                        .map(|column| self.format_value(column))
+                        .collect();
+                    self.write_line(values);
+                }
+                ScriptCommand::Echo(message) => println!("{message}"),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cycle_limit(&self) -> anyhow::Result<()> {
+        if self.computer.cycles() >= self.max_cycles {
+            anyhow::bail!("cycle limit ({}) reached", self.max_cycles);
+        }
+        Ok(())
+    }
+
+    fn get(&self, variable: &Variable) -> i32 {
+        let value = match variable {
+            Variable::Ram(address) => self.computer.ram(*address),
+            Variable::Pc => self.computer.pc(),
+            Variable::A => self.computer.a(),
+            Variable::D => self.computer.d(),
+            Variable::Time => return self.computer.cycles() as i32,
+        };
+        value as i16 as i32
+    }
+
+    fn set(&mut self, variable: &Variable, value: i32) {
+        let value = value as u16;
+        match variable {
+            Variable::Ram(address) => self.computer.set_ram(*address, value),
+            Variable::Pc => self.computer.set_pc(value),
+            Variable::A => self.computer.set_a(value),
+            Variable::D => self.computer.set_d(value),
+            Variable::Time => {}
+        }
+    }
+
+    fn evaluate(&self, condition: &Condition) -> bool {
+        let value = self.get(&condition.variable);
+        match condition.operator.as_str() {
+            "=" => value == condition.value,
+            "<>" => value != condition.value,
+            "<" => value < condition.value,
+            "<=" => value <= condition.value,
+            ">" => value > condition.value,
+            ">=" => value >= condition.value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn format_value(&self, column: &OutputColumn) -> String {
+        let value = self.get(&column.variable);
+        let width = column.width;
+        let text = match column.format {
+            'X' => format!("{:0width$X}", value as u16),
+            'B' => format!("{:0width$b}", value as u16),
+            _ => format!("{value:>width$}"),
+        };
+        // 幅を超える場合は下位の桁を残す
+        let text: String = text.chars().skip(text.chars().count() - width).collect();
+        format!(
+            "{}{text}{}",
+            " ".repeat(column.left_padding),
+            " ".repeat(column.right_padding)
+        )
+    }
+
+    // '|' 区切りの1行を出力し、.cmp ファイルの同じ行と比較する
+    fn write_line(&mut self, cells: Vec<String>) {
+        let line = format!("|{}|", cells.join("|"));
+        self.output_lines.push(line.clone());
+        let line_number = self.output_lines.len();
+        let Some(compare_lines) = &self.compare_lines else {
+            return;
+        };
+        let expected_line = compare_lines
+            .get(line_number - 1)
+            .map(|line| line.trim_end())
+            .unwrap_or_default();
+        if expected_line == line {
+            return;
+        }
+        let expected_cells: Vec<&str> = expected_line.trim_matches('|').split('|').collect();
+        for (index, actual) in cells.iter().enumerate() {
+            let expected = expected_cells.get(index).copied().unwrap_or_default();
+            // '*' だけのセルは任意の値と一致する
+            let is_wildcard =
+                !expected.trim().is_empty() && expected.trim().chars().all(|c| c == '*');
+            if expected != actual && !is_wildcard {
+                self.failure = Some(ComparisonFailure {
+                    line: line_number,
+                    column: index + 1,
+                    name: self
+                        .output_list
+                        .get(index)
+                        .map(|column| column.name.clone())
+                        .unwrap_or_default(),
+                    expected: expected.to_string(),
+                    actual: actual.clone(),
+                });
+                return;
+            }
+        }
+        if expected_cells.len() != cells.len() {
+            self.failure = Some(ComparisonFailure {
+                line: line_number,
+                column: cells.len().min(expected_cells.len()) + 1,
+                name: String::new(),
+                expected: expected_line.to_string(),
+                actual: line,
+            });
+        }
+    }
+
+    fn output(&self) -> String {
+        self.output_lines
+            .iter()
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
+}
+
+fn remove_comments(script: &str) -> String {
+    let mut output = String::new();
+    let mut rest = script;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            output.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    output
+}
+
+// 空白と ',' ';' '{' '}' で区切る（"..." は1つのトークンにする）
+fn tokenize(script: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = script.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                token.extend(chars.by_ref().take_while(|c| *c != '"'));
+                tokens.push(std::mem::take(&mut token));
+            }
+            ',' | ';' | '{' | '}' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn parse_commands<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+    in_block: bool,
+) -> anyhow::Result<Vec<ScriptCommand>> {
+    let mut commands = Vec::new();
+    while let Some(keyword) = tokens.next() {
+        if keyword == "}" && in_block {
+            return Ok(commands);
+        }
+        if keyword == "," || keyword == ";" {
+            continue;
+        }
+        // コマンドの引数 (',' ';' '{' まで)
+        let mut arguments = Vec::new();
+        while let Some(token) = tokens.next_if(|token| ![",", ";", "{", "}"].contains(token)) {
+            arguments.push(token);
+        }
+        let command = match (keyword, arguments.as_slice()) {
+            ("load", []) => ScriptCommand::Load(None),
+            ("load", [file]) => ScriptCommand::Load(Some(file.to_string())),
+            ("output-file", [file]) => ScriptCommand::OutputFile(file.to_string()),
+            ("compare-to", [file]) => ScriptCommand::CompareTo(file.to_string()),
+            ("output-list", columns) => ScriptCommand::OutputList(
+                columns
+                    .iter()
+                    .map(|column| parse_output_column(column))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            ("set", [variable, value]) => {
+                ScriptCommand::Set(parse_variable(variable)?, parse_value(value)?)
+            }
+            ("repeat", count) if count.len() <= 1 => {
+                let count = count.first().map(|count| count.parse()).transpose()?;
+                ScriptCommand::Repeat(count, parse_block(tokens)?)
+            }
+            ("while", [variable, operator, value]) => {
+                if !["=", "<>", "<", "<=", ">", ">="].contains(operator) {
+                    anyhow::bail!("unknown operator in 'while': {operator}");
+                }
+                let condition = Condition {
+                    variable: parse_variable(variable)?,
+                    operator: operator.to_string(),
+                    value: parse_value(value)?,
+                };
+                ScriptCommand::While(condition, parse_block(tokens)?)
+            }
+            ("ticktock" | "tick", []) => ScriptCommand::TickTock,
+            // tick と tock で1命令を実行する
+            ("tock", []) => continue,
+            ("output", []) => ScriptCommand::Output,
+            ("echo", message) => ScriptCommand::Echo(message.join(" ")),
+            ("clear-echo" | "breakpoint" | "clear-breakpoints", _) => continue,
+            _ => anyhow::bail!("unknown command: {keyword} {}", arguments.join(" ")),
+        };
+        commands.push(command);
+    }
+    if in_block {
+        anyhow::bail!("missing '}}'");
+    }
+    Ok(commands)
+}
+
+fn parse_block<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> anyhow::Result<Vec<ScriptCommand>> {
+    if tokens.next() != Some("{") {
+        anyhow::bail!("expected '{{'");
+    }
+    parse_commands(tokens, true)
+}
+
+fn parse_variable(name: &str) -> anyhow::Result<Variable> {
+    Ok(match name {
+        "PC" => Variable::Pc,
+        "A" => Variable::A,
+        "D" => Variable::D,
+        "time" => Variable::Time,
+        _ => match name
+            .strip_prefix("RAM[")
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            Some(address) => Variable::Ram(address.parse()?),
+            None => anyhow::bail!("unknown variable: {name}"),
+        },
+    })
+}
+
+// 10進数、又は %D, %X, %B を前に付けた値
+fn parse_value(value: &str) -> anyhow::Result<i32> {
+    let parsed = match value.get(..2) {
+        Some("%X") => i32::from_str_radix(&value[2..], 16),
+        Some("%B") => i32::from_str_radix(&value[2..], 2),
+        Some("%D") => value[2..].parse(),
+        _ => value.parse(),
+    };
+    parsed.map_err(|e| anyhow::anyhow!("invalid value {value}: {e}"))
+}
+
+// NAME%D1.6.1 の形式（書式を省略した場合は %D1.6.1）
+fn parse_output_column(column: &str) -> anyhow::Result<OutputColumn> {
+    let (name, format) = column.split_once('%').unwrap_or((column, "D1.6.1"));
+    let mut chars = format.chars();
+    let format_char = chars.next().unwrap_or('D');
+    if !['D', 'X', 'B', 'S'].contains(&format_char) {
+        anyhow::bail!("unknown output format: {column}");
+    }
+    let sizes: Vec<usize> = chars
+        .as_str()
+        .split('.')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|e| anyhow::anyhow!("invalid output format {column}: {e}"))?;
+    let [left_padding, width, right_padding] = sizes[..] else {
+        anyhow::bail!("invalid output format: {column}");
+    };
+    Ok(OutputColumn {
+        variable: parse_variable(name)?,
+        name: name.to_string(),
+        format: format_char,
+        left_padding,
+        width,
+        right_padding,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_script() {
+        let directory = std::env::temp_dir().join(format!("test_script_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        // RAM[2] = max(RAM[0], RAM[1])
+        std::fs::write(
+            directory.join("Max.asm"),
+            "@R0\nD=M\n@R1\nD=D-M\n@FIRST\nD;JGT\n@R1\nD=M\n@R2\nM=D\n@END\n0;JMP\n\
+             (FIRST)\n@R0\nD=M\n@R2\nM=D\n(END)\n@END\n0;JMP",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("Max.cmp"),
+            "|  RAM[0]  |  RAM[1]  |  RAM[2]  |\n\
+             |       3  |       5  |       5  |\n\
+             |      -1  |     -10  |      -1  |\n",
+        )
+        .unwrap();
+        let script = "// Max.tst\n\
+            load Max.asm,\noutput-file Max.out,\ncompare-to Max.cmp,\n\
+            output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 RAM[2]%D2.6.2;\n\
+            set RAM[0] 3, set RAM[1] 5;\nrepeat 14 {\n  ticktock;\n}\noutput;\n\
+            set PC 0, /* reset */ set RAM[0] -1, set RAM[1] %XFFF6;\n\
+            repeat 14 { ticktock; }\noutput;\n";
+
+        let report = TestScript::parse(script)
+            .unwrap()
+            .run(&directory, 1000)
+            .unwrap();
+        assert_eq!(report.compared, Some(Ok(())));
+        assert_eq!(
+            std::fs::read_to_string(directory.join("Max.out")).unwrap(),
+            std::fs::read_to_string(directory.join("Max.cmp")).unwrap()
+        );
+
+        // 2回目の実行で RAM[2] が比較と一致しない
+        let script = script.replace("set RAM[1] %XFFF6", "set RAM[1] 4");
+        let report = TestScript::parse(&script)
+            .unwrap()
+            .run(&directory, 1000)
+            .unwrap();
+        assert_eq!(
+            report.compared,
+            Some(Err(ComparisonFailure {
+                line: 3,
+                column: 2,
+                name: "RAM[1]".to_string(),
+                expected: "     -10  ".to_string(),
+                actual: "       4  ".to_string(),
+            }))
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
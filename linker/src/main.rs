@@ -0,0 +1,106 @@
+use assembler::file_format::FileFormat;
+use assembler::object::ObjectFile;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+// ブートストラップコードのオブジェクトファイル (vm_translator --separate が出力する)
+const BOOTSTRAP_OBJECT: &str = "Bootstrap.obj";
+
+// ディレクトリ内の .obj ファイル
+// ROM アドレス 0 から実行が始まるよう Bootstrap.obj を先頭にし、残りはファイル名順に並べる
+fn object_paths_in_directory(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| path.is_file() && path.extension() == Some("obj".as_ref()));
+    paths.sort_by_key(|path| {
+        (
+            path.file_name() != Some(BOOTSTRAP_OBJECT.as_ref()),
+            path.clone(),
+        )
+    });
+    Ok(paths)
+}
+
+fn run() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let usage = "usage: linker <output.hack|.bin|.hex|.rom> <file.obj|directory>... [--symbols]";
+    // 出力する機械語のパス（拡張子で形式を決める）
+    let output_path: &Path = Path::new(args.get(1).ok_or_else(|| anyhow::anyhow!(usage))?);
+    let format = output_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(FileFormat::from_extension)
+        .ok_or_else(|| anyhow::anyhow!("unknown output format: {}", output_path.display()))?;
+
+    // --symbols: シンボルとアドレスの対応表 (.sym) を出力する
+    let mut symbols = false;
+    // 与えられた順に ROM へ配置する（ディレクトリの場合は Bootstrap.obj の後にファイル名順）
+    let mut input_paths: Vec<PathBuf> = Vec::new();
+    for arg in args.iter().skip(2) {
+        let path = Path::new(arg);
+        if arg == "--symbols" {
+            symbols = true;
+        } else if path.is_dir() {
+            input_paths.extend(object_paths_in_directory(path)?);
+        } else {
+            input_paths.push(path.to_path_buf());
+        }
+    }
+    if input_paths.is_empty() {
+        anyhow::bail!(".obj files could not be found in the input paths\n{usage}");
+    }
+
+    let objects = input_paths
+        .iter()
+        .map(|path| {
+            ObjectFile::from_text(&std::fs::read_to_string(path)?)
+                .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let linked = assembler::linker::link(&objects)?;
+
+    if symbols {
+        std::fs::write(
+            output_path.with_extension("sym"),
+            assembler::listing::generate_symbol_map(&linked.symbol_table),
+        )?;
+    }
+    std::fs::write(output_path, format.write(&linked.words))?;
+    Ok(())
+}
+
+#[test]
+fn test_bootstrap_object_first() {
+    use schema::hack;
+
+    let directory = std::env::temp_dir().join(format!("linker_test_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    for (name, source) in [
+        ("Array", "(Array.new)\n@Array.new\n0;JMP\n"),
+        ("Bootstrap", "@256\nD=A\n@SP\nM=D\n@Array.new\n0;JMP\n"),
+    ] {
+        let object = ObjectFile::assemble(name, &hack::parse(source.to_string()).unwrap()).unwrap();
+        std::fs::write(directory.join(format!("{name}.obj")), object.to_text()).unwrap();
+    }
+
+    let paths = object_paths_in_directory(&directory).unwrap();
+    let objects: Vec<ObjectFile> = paths
+        .iter()
+        .map(|path| ObjectFile::from_text(&std::fs::read_to_string(path).unwrap()).unwrap())
+        .collect();
+    let linked = assembler::linker::link(&objects).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(paths[0].file_name(), Some(BOOTSTRAP_OBJECT.as_ref()));
+    // ROM 0 はブートストラップコードの '@256'、Array.new はその後ろに置かれる
+    assert_eq!(linked.words[0], 256);
+    assert_eq!(linked.words[4], 6);
+}
@@ -1,6 +1,22 @@
 mod parser;
+mod syntax_tree;
 
-pub use parser::parse;
+pub use parser::{parse, parse_with_source};
+use std::fmt;
+pub use syntax_tree::{parse_syntax_tree, Node, SyntaxLine, SyntaxTree};
+
+// A命令で指定できる値の最大値 (15bit)
+pub const A_COMMAND_MAX_VALUE: u16 = 0x7fff;
+
+// Hack コンピュータのメモリマップ
+// ROM, RAM の容量 (word)
+pub const ROM_SIZE: usize = 32768;
+pub const RAM_SIZE: usize = 32768;
+// スクリーンのメモリマップ (512 x 256 pixel を 16bit x 8192 word で表現する)
+pub const SCREEN_ADDRESS: u16 = 16384;
+pub const SCREEN_SIZE: usize = 8192;
+// キーボードのメモリマップ
+pub const KBD_ADDRESS: u16 = 24576;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DestMnemonic {
@@ -44,6 +60,19 @@ pub enum CompMnemonic {
     MMinusD,
     DAndM,
     DOrM,
+    // 拡張 ALU のシフト命令（<< は論理左シフト、>> は算術右シフト）
+    #[cfg(feature = "shift")]
+    DShiftLeft,
+    #[cfg(feature = "shift")]
+    AShiftLeft,
+    #[cfg(feature = "shift")]
+    MShiftLeft,
+    #[cfg(feature = "shift")]
+    DShiftRight,
+    #[cfg(feature = "shift")]
+    AShiftRight,
+    #[cfg(feature = "shift")]
+    MShiftRight,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -91,3 +120,148 @@ pub enum Command {
     C(CCommand),
     L(Symbol),
 }
+
+// 元のソースコード上の位置と内容を保持したコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceCommand {
+    pub command: Command,
+    pub line_number: usize,
+    pub column: usize,
+    pub source: String,
+}
+
+// 構文エラー（位置は元のソースコード上の行番号・列番号）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line_number: usize,
+    pub column: usize,
+    pub message: String,
+    pub source: String,
+}
+
+// 'LINE:COL: message' の後にエラー箇所を示す元の行を出力する
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker: String = self
+            .source
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(
+            f,
+            "{}:{}: {}\n    {}\n    {}^",
+            self.line_number, self.column, self.message, self.source, marker
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.0.iter().map(ParseError::to_string).collect();
+        write!(f, "{}", errors.join("\n"))
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+impl DestMnemonic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::M => "M",
+            Self::D => "D",
+            Self::MD => "MD",
+            Self::A => "A",
+            Self::AM => "AM",
+            Self::AD => "AD",
+            Self::AMD => "AMD",
+        }
+    }
+}
+
+impl CompMnemonic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Zero => "0",
+            Self::One => "1",
+            Self::MinusOne => "-1",
+            Self::D => "D",
+            Self::A => "A",
+            Self::NegateD => "!D",
+            Self::NegateA => "!A",
+            Self::MinusD => "-D",
+            Self::MinusA => "-A",
+            Self::DPlusOne => "D+1",
+            Self::APlusOne => "A+1",
+            Self::DMinusOne => "D-1",
+            Self::AMinusOne => "A-1",
+            Self::DPlusA => "D+A",
+            Self::DMinusA => "D-A",
+            Self::AMinusD => "A-D",
+            Self::DAndA => "D&A",
+            Self::DOrA => "D|A",
+            Self::M => "M",
+            Self::NegateM => "!M",
+            Self::MinusM => "-M",
+            Self::MPlusOne => "M+1",
+            Self::MMinusOne => "M-1",
+            Self::DPlusM => "D+M",
+            Self::DMinusM => "D-M",
+            Self::MMinusD => "M-D",
+            Self::DAndM => "D&M",
+            Self::DOrM => "D|M",
+            #[cfg(feature = "shift")]
+            Self::DShiftLeft => "D<<",
+            #[cfg(feature = "shift")]
+            Self::AShiftLeft => "A<<",
+            #[cfg(feature = "shift")]
+            Self::MShiftLeft => "M<<",
+            #[cfg(feature = "shift")]
+            Self::DShiftRight => "D>>",
+            #[cfg(feature = "shift")]
+            Self::AShiftRight => "A>>",
+            #[cfg(feature = "shift")]
+            Self::MShiftRight => "M>>",
+        }
+    }
+}
+
+impl JumpMnemonic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::JGT => "JGT",
+            Self::JEQ => "JEQ",
+            Self::JGE => "JGE",
+            Self::JLT => "JLT",
+            Self::JNE => "JNE",
+            Self::JLE => "JLE",
+            Self::JMP => "JMP",
+        }
+    }
+}
+
+// アセンブラ言語の1行として出力する
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::A(ACommand::Address(value)) => write!(f, "@{value}"),
+            Command::A(ACommand::Symbol(symbol)) => write!(f, "@{}", symbol.get()),
+            Command::C(c_command) => {
+                if let Some(dest) = &c_command.dest {
+                    write!(f, "{}=", dest.as_str())?;
+                }
+                write!(f, "{}", c_command.comp.as_str())?;
+                if let Some(jump) = &c_command.jump {
+                    write!(f, ";{}", jump.as_str())?;
+                }
+                Ok(())
+            }
+            Command::L(symbol) => write!(f, "({})", symbol.get()),
+        }
+    }
+}
@@ -1,25 +1,106 @@
 use crate::hack::*;
-use crate::parser::easily_parse;
 use crate::pre_processor;
+use combine::error::StreamError;
 use combine::parser;
 use combine::parser::char::string;
 use combine::parser::choice::choice;
 use combine::parser::token::value;
+use combine::stream::{easy, position, StreamErrorFor};
 use combine::Stream;
-use combine::{attempt, between, optional, token};
+use combine::{attempt, between, eof, optional, token, EasyParser, Parser};
+use std::fmt;
 
 pub fn parse(input: String) -> anyhow::Result<Vec<Command>> {
-    pre_process(input)
-        .map(|line| easily_parse(command, line.as_str()))
-        .collect::<anyhow::Result<Vec<_>>>()
+    Ok(parse_with_source(input)?
+        .into_iter()
+        .map(|source_command| source_command.command)
+        .collect())
 }
 
-fn pre_process(input: String) -> impl Iterator<Item = String> {
+// 元のソースコードの行番号・列番号と内容を保持したまま構文解析する
+// 構文エラーは最初の1つで止めずに全ての行について報告する
+pub fn parse_with_source(input: String) -> Result<Vec<SourceCommand>, ParseErrors> {
+    let mut source_commands = vec![];
+    let mut errors = vec![];
+    for (line_number, source, line) in pre_process(input) {
+        match parse_line(&line) {
+            Ok(command) => source_commands.push(SourceCommand {
+                command,
+                line_number,
+                column: source.chars().take_while(|c| c.is_whitespace()).count() + 1,
+                source: source.trim().to_string(),
+            }),
+            Err((error_index, message)) => errors.push(ParseError {
+                line_number,
+                column: original_column(&source, error_index),
+                message,
+                source,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(source_commands)
+    } else {
+        Err(ParseErrors(errors))
+    }
+}
+
+// (行番号, 元の行, 前処理後の行)
+fn pre_process(input: String) -> impl Iterator<Item = (usize, String, String)> {
     use pre_processor::*;
     split_by_newline(input)
-        .map(remove_whitespace)
-        .map(remove_comment)
-        .filter(non_empty_line)
+        .enumerate()
+        .map(|(index, source)| {
+            let source = source.trim_end_matches('\r').to_string();
+            let line = remove_comment(remove_whitespace(source.clone()));
+            (index + 1, source, line)
+        })
+        .filter(|(_, _, line)| non_empty_line(line))
+}
+
+// 前処理後の行を1つのコマンドとして解析する
+// 失敗した場合は (前処理後の行でのエラー位置, エラーメッセージ) を返す
+pub(super) fn parse_line(line: &str) -> Result<Command, (usize, String)> {
+    command()
+        .skip(eof())
+        .easy_parse(position::Stream::new(line))
+        .map(|(command, _)| command)
+        .map_err(|errors| {
+            (
+                errors.position.column as usize - 1,
+                error_message(&errors.errors),
+            )
+        })
+}
+
+fn error_message(errors: &[easy::Error<char, &str>]) -> String {
+    struct Message<'a, 'b>(&'a [easy::Error<char, &'b str>]);
+    impl fmt::Display for Message<'_, '_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            easy::Error::fmt_errors(self.0, f)
+        }
+    }
+    Message(errors)
+        .to_string()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// 空白を除いた行での位置を、元の行での列番号 (1始まり) に変換する
+pub(super) fn original_column(source: &str, index: usize) -> usize {
+    let non_whitespace_columns: Vec<usize> = source
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(column, _)| column + 1)
+        .collect();
+    non_whitespace_columns
+        .get(index)
+        .copied()
+        .unwrap_or_else(|| non_whitespace_columns.last().map_or(1, |column| column + 1))
 }
 
 parser! {
@@ -39,11 +120,27 @@ parser! {
     }
 }
 
+#[cfg(feature = "shift")]
 parser! {
-    fn comp_mnemonic[Input]()(Input) -> CompMnemonic
+    fn shift_comp_mnemonic[Input]()(Input) -> CompMnemonic
     where [Input: Stream<Token = char>]
     {
         choice([
+            attempt(string("D<<").with(value(CompMnemonic::DShiftLeft))),
+            attempt(string("A<<").with(value(CompMnemonic::AShiftLeft))),
+            attempt(string("M<<").with(value(CompMnemonic::MShiftLeft))),
+            attempt(string("D>>").with(value(CompMnemonic::DShiftRight))),
+            attempt(string("A>>").with(value(CompMnemonic::AShiftRight))),
+            attempt(string("M>>").with(value(CompMnemonic::MShiftRight))),
+        ])
+    }
+}
+
+parser! {
+    fn comp_mnemonic[Input]()(Input) -> CompMnemonic
+    where [Input: Stream<Token = char>]
+    {
+        let comp = choice([
             // この順番じゃないとだめ（DをD|Mより先にパースを試みてはいけない）
             attempt(string("D|M").with(value(CompMnemonic::DOrM))),
             attempt(string("D&M").with(value(CompMnemonic::DAndM))),
@@ -73,7 +170,11 @@ parser! {
             attempt(string("1").with(value(CompMnemonic::One))),
             attempt(string("-1").with(value(CompMnemonic::MinusOne))),
             attempt(string("0").with(value(CompMnemonic::Zero))),
-        ])
+        ]);
+        // シフト命令は D, A, M より先にパースを試みる
+        #[cfg(feature = "shift")]
+        let comp = shift_comp_mnemonic().or(comp);
+        comp
     }
 }
 
@@ -109,7 +210,16 @@ parser! {
     fn p_address[Input]()(Input) -> ACommand
     where [Input: Stream<Token = char>]
     {
-        crate::parser::p_u16().map(ACommand::Address)
+        crate::parser::p_u16().and_then(|address| {
+            // A命令で指定できる値は 15bit まで
+            if address <= A_COMMAND_MAX_VALUE {
+                Ok(ACommand::Address(address))
+            } else {
+                Err(StreamErrorFor::<Input>::message_format(format!(
+                    "A-instruction value {address} does not fit in 15 bits (max {A_COMMAND_MAX_VALUE})"
+                )))
+            }
+        })
     }
 }
 
@@ -198,6 +308,17 @@ mod tests {
         easy_parser_assert(comp_mnemonic, "D|M", CompMnemonic::DOrM);
     }
 
+    #[cfg(feature = "shift")]
+    #[test]
+    fn parse_shift_comp() {
+        easy_parser_assert(comp_mnemonic, "D<<", CompMnemonic::DShiftLeft);
+        easy_parser_assert(comp_mnemonic, "A<<", CompMnemonic::AShiftLeft);
+        easy_parser_assert(comp_mnemonic, "M<<", CompMnemonic::MShiftLeft);
+        easy_parser_assert(comp_mnemonic, "D>>", CompMnemonic::DShiftRight);
+        easy_parser_assert(comp_mnemonic, "A>>", CompMnemonic::AShiftRight);
+        easy_parser_assert(comp_mnemonic, "M>>", CompMnemonic::MShiftRight);
+    }
+
     #[test]
     fn parse_jump() {
         easy_parser_assert(jump_mnemonic, "null", JumpMnemonic::Null);
@@ -233,6 +354,8 @@ mod tests {
     #[test]
     fn parse_a_command() {
         easy_parser_assert(a_command, "@12345", ACommand::Address(12345));
+        easy_parser_assert(a_command, "@32767", ACommand::Address(32767));
+        assert!(parse("@32768".to_string()).is_err());
         easy_parser_assert(
             a_command,
             "@hoge_var$fuga:fugo",
@@ -269,4 +392,42 @@ mod tests {
             Command::L(Symbol("hoge_var$fuga:fugo".to_string())),
         );
     }
+    #[test]
+    fn display_command() {
+        let code = "@12\n@LOOP\n(LOOP)\nAM=D|M;JNE\nD;JMP\nM=-1";
+        let commands = parse(code.to_string()).unwrap();
+        assert_eq!(
+            commands
+                .iter()
+                .map(Command::to_string)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            code
+        );
+    }
+
+    #[test]
+    fn parse_errors_with_position() {
+        let code = "@1\n  D=X+1 // typo\n\n  @2\n\tM=D;JXX\n0;JMP junk";
+        let errors = parse_with_source(code.to_string()).unwrap_err().0;
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.line_number, error.column))
+                .collect::<Vec<_>>(),
+            vec![(2, 5), (5, 6), (6, 7)]
+        );
+        assert!(errors[0]
+            .to_string()
+            .starts_with("2:5: Unexpected `X`; Expected"));
+        assert!(errors[0]
+            .to_string()
+            .ends_with("  D=X+1 // typo\n        ^"));
+
+        let source_commands = parse_with_source("@1\n  (LOOP)".to_string()).unwrap();
+        assert_eq!(
+            (source_commands[1].line_number, source_commands[1].column),
+            (2, 3)
+        );
+    }
 }
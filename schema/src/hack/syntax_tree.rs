@@ -0,0 +1,122 @@
+//! コメント・空行・空白を失わない Hack アセンブラの構文木
+//!
+//! `SyntaxTree` を文字列に戻すと元のソースコードと完全に一致する。
+
+use super::parser::{original_column, parse_line};
+use super::{Command, ParseError, ParseErrors};
+use crate::pre_processor;
+use std::fmt;
+
+// 行の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    // 空白だけの行
+    Blank,
+    // コメントだけの行（'//' より後ろ）
+    Comment(String),
+    // コマンドと行末のコメント（'//' より後ろ）
+    Command {
+        command: Command,
+        comment: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxLine {
+    pub node: Node,
+    pub line_number: usize,
+    // 改行文字を除いた元の行
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree {
+    pub lines: Vec<SyntaxLine>,
+}
+
+impl SyntaxTree {
+    // コマンドの列（parse() と同じ結果になる）
+    pub fn commands(&self) -> Vec<Command> {
+        self.lines
+            .iter()
+            .filter_map(|line| match &line.node {
+                Node::Command { command, .. } => Some(command.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<&str> = self.lines.iter().map(|line| line.source.as_str()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+pub fn parse_syntax_tree(input: &str) -> Result<SyntaxTree, ParseErrors> {
+    let mut lines = vec![];
+    let mut errors = vec![];
+    for (index, source) in input.split('\n').enumerate() {
+        let line_number = index + 1;
+        let (code, comment) = match source.find("//") {
+            Some(position) => (
+                &source[..position],
+                Some(source[position + 2..].trim_end().to_string()),
+            ),
+            None => (source, None),
+        };
+        let code = pre_processor::remove_whitespace(code.to_string());
+        let node = if code.is_empty() {
+            comment.map_or(Node::Blank, Node::Comment)
+        } else {
+            match parse_line(&code) {
+                Ok(command) => Node::Command { command, comment },
+                Err((error_index, message)) => {
+                    let source = source.trim_end_matches('\r').to_string();
+                    errors.push(ParseError {
+                        line_number,
+                        column: original_column(&source, error_index),
+                        message,
+                        source,
+                    });
+                    continue;
+                }
+            }
+        };
+        lines.push(SyntaxLine {
+            node,
+            line_number,
+            source: source.to_string(),
+        });
+    }
+    if errors.is_empty() {
+        Ok(SyntaxTree { lines })
+    } else {
+        Err(ParseErrors(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hack::parse;
+
+    #[test]
+    fn syntax_tree_is_lossless() {
+        let source = "// header\r\n\n  @i  // counter\r\n\tM = 1\n(LOOP)\n  0 ; JMP\n   \n";
+        let tree = parse_syntax_tree(source).unwrap();
+
+        assert_eq!(tree.to_string(), source);
+        assert_eq!(tree.commands(), parse(source.to_string()).unwrap());
+        assert_eq!(tree.lines[0].node, Node::Comment(" header".to_string()));
+        assert_eq!(tree.lines[1].node, Node::Blank);
+        assert_eq!(
+            tree.lines[2].node,
+            Node::Command {
+                command: Command::A(crate::hack::ACommand::Symbol(crate::hack::Symbol::new("i"))),
+                comment: Some(" counter".to_string()),
+            }
+        );
+    }
+}
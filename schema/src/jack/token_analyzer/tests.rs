@@ -23,8 +23,9 @@ macro_rules! tokens {
         $(ident: $ident: literal)?
         $(str_const: $str_const: literal)?
         $(int_const: $int_const: literal)?
-    ,)+) => {
-        vec![
+    ,)+) => {{
+        #[allow(clippy::useless_vec)]
+        let tokens = vec![
             $(
                 $(Token::Keyword(Keyword::$keyword))?
                 $(Token::Symbol(Symbol::$symbol))?
@@ -32,7 +33,8 @@ macro_rules! tokens {
                 $(Token::StringConstant($str_const.to_string()))?
                 $(Token::IntegerConstant($int_const))?
             ),+
-        ]
-    };
+        ];
+        tokens
+    }};
 }
 pub(crate) use tokens;
@@ -53,7 +53,6 @@ pub(crate) use parsable_enum;
 
 #[test]
 fn test_parsable_enum() {
-    use combine::parser;
     use tests::easy_parser_assert;
     parsable_enum! {
         #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
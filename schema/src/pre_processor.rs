@@ -1,8 +1,7 @@
-/// 構文解析に先立って前処理を行うための共通関数群
+//! 構文解析に先立って前処理を行うための共通関数群
 
 pub(crate) fn split_by_newline(line: String) -> impl Iterator<Item = String> {
     line.split('\n')
-        .into_iter()
         .map(String::from)
         .collect::<Vec<_>>()
         .into_iter()
@@ -93,6 +92,6 @@ fn test_remove_inline_comment() {
 }
 
 /// 空行でないことを保証する
-pub(crate) fn non_empty_line(line: &String) -> bool {
+pub(crate) fn non_empty_line(line: &str) -> bool {
     !line.is_empty()
 }
@@ -1,7 +1,9 @@
+pub mod optimizer;
 mod parser;
 
 use crate::parser::parsable_enum;
 pub use parser::parse;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Command {
@@ -21,6 +23,31 @@ pub enum Command {
     IfGoto(Label),
 }
 
+// VM 言語の1行として出力する
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Arithmetic(command) => write!(f, "{}", command.as_str()),
+            Command::MemoryAccess(command) => write!(
+                f,
+                "{} {} {}",
+                command.access_type.as_str(),
+                command.segment.as_str(),
+                command.index.get()
+            ),
+            Command::Function {
+                name,
+                local_variable_count,
+            } => write!(f, "function {} {local_variable_count}", name.get()),
+            Command::Call { name, args_count } => write!(f, "call {} {args_count}", name.get()),
+            Command::Return => write!(f, "return"),
+            Command::Label(label) => write!(f, "label {}", label.get()),
+            Command::Goto(label) => write!(f, "goto {}", label.get()),
+            Command::IfGoto(label) => write!(f, "if-goto {}", label.get()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Label(String);
 
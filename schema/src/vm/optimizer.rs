@@ -0,0 +1,466 @@
+//! vm::Command 列の覗き穴最適化
+//!
+//! ラベルで区切られた直線的なコードの中で、次の書き換えを変化がなくなるまで繰り返す。
+//! - 定数の畳み込み: `push constant 2`, `push constant 3`, `add` を `push constant 5` にする（比較や定数の条件の `if-goto` も含む）
+//! - 打ち消し合う `not`, `not` や `neg`, `neg`
+//! - 何もしない push と pop の組 (`push local 0`, `pop local 0`)
+//! - 直後のラベルへの `goto`
+//! - `not`, `if-goto L` を反転した分岐 `if-goto L.INV0`, `goto L`, `label L.INV0` にする
+//!   （直後が `goto M`, `label L` であれば `if-goto M`, `label L` にする）
+//!
+//! 反転した分岐はコマンド数は変わらないが、vm_translator の出力では `not` より `goto` の方が命令が少なく、
+//! 条件が成り立つときはジャンプ1回で済む。ラベルは全て残すので、ジャンプ先は変わらない。
+
+use super::{AccessType, ArithmeticCommand, Command, Index, Label, MemoryAccessCommand, Segment};
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimized {
+    pub commands: Vec<Command>,
+    pub report: OptimizeReport,
+}
+
+// 最適化の前後のコマンド数（ラベルを除く）と、書き換えた箇所の内訳
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptimizeReport {
+    pub before: usize,
+    pub after: usize,
+    pub folded_constants: usize,
+    pub double_negations: usize,
+    pub push_pop_pairs: usize,
+    pub jumps_to_next: usize,
+    pub inverted_branches: usize,
+}
+
+impl OptimizeReport {
+    pub fn saved(&self) -> usize {
+        self.before - self.after
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} vm commands (saved {}: {} constant folds, {} double negations, {} push/pop, {} jumps to next, {} inverted branches)",
+            self.before,
+            self.after,
+            self.saved(),
+            self.folded_constants,
+            self.double_negations,
+            self.push_pop_pairs,
+            self.jumps_to_next,
+            self.inverted_branches
+        )
+    }
+}
+
+pub fn optimize(commands: &[Command]) -> Optimized {
+    let mut labels: HashSet<String> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Label(label) => Some(label.get_string()),
+            _ => None,
+        })
+        .collect();
+    let mut report = OptimizeReport {
+        before: count_commands(commands),
+        ..Default::default()
+    };
+    let mut commands = commands.to_vec();
+    // 1つの書き換えで別の書き換えができるようになるので、変化がなくなるまで繰り返す
+    loop {
+        let (rewritten, changed) = rewrite(&commands, &mut labels, &mut report);
+        commands = rewritten;
+        if !changed {
+            break;
+        }
+    }
+    report.after = count_commands(&commands);
+    Optimized { commands, report }
+}
+
+fn count_commands(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .filter(|command| !matches!(command, Command::Label(_)))
+        .count()
+}
+
+// 先頭から順に、書き換え済みのコマンド列 (output) の末尾と次のコマンドを見て書き換える
+fn rewrite(
+    commands: &[Command],
+    labels: &mut HashSet<String>,
+    report: &mut OptimizeReport,
+) -> (Vec<Command>, bool) {
+    let mut output: Vec<Command> = Vec::with_capacity(commands.len());
+    let mut changed = false;
+    let mut i = 0;
+    while i < commands.len() {
+        let command = &commands[i];
+        i += 1;
+        match command {
+            Command::Arithmetic(arithmetic) => {
+                if let Some((value, length)) = fold(&output, arithmetic) {
+                    output.truncate(output.len() - length);
+                    output.extend(push_value(value));
+                    report.folded_constants += 1;
+                    changed = true;
+                } else if matches!(arithmetic, ArithmeticCommand::Not | ArithmeticCommand::Neg)
+                    && output.last() == Some(command)
+                {
+                    output.pop();
+                    report.double_negations += 1;
+                    changed = true;
+                } else {
+                    output.push(command.clone());
+                }
+            }
+            Command::MemoryAccess(MemoryAccessCommand {
+                access_type: AccessType::Pop,
+                segment,
+                index,
+            }) if *segment != Segment::Constant
+                && output.last() == Some(&push(segment.clone(), index.get())) =>
+            {
+                output.pop();
+                report.push_pop_pairs += 1;
+                changed = true;
+            }
+            Command::Goto(label)
+                if commands[i..]
+                    .iter()
+                    .take_while(|command| matches!(command, Command::Label(_)))
+                    .any(|command| *command == Command::Label(label.clone())) =>
+            {
+                report.jumps_to_next += 1;
+                changed = true;
+            }
+            Command::IfGoto(label) => {
+                if let Some((value, length)) = constant_suffix(&output) {
+                    // 定数の条件は、成り立てば goto にし、成り立たなければ取り除く
+                    output.truncate(output.len() - length);
+                    if value != 0 {
+                        output.push(Command::Goto(label.clone()));
+                    }
+                    report.folded_constants += 1;
+                    changed = true;
+                } else if output.last() == Some(&Command::Arithmetic(ArithmeticCommand::Not))
+                    && is_boolean_suffix(&output[..output.len() - 1])
+                {
+                    // not はビット反転なので、条件が 0 か -1 の時だけ分岐の反転と同じになる
+                    output.pop();
+                    match &commands[i..] {
+                        [Command::Goto(target), Command::Label(next), ..] if next == label => {
+                            output.push(Command::IfGoto(target.clone()));
+                            i += 1;
+                        }
+                        _ => {
+                            let inverted = new_label(labels, label);
+                            output.extend([
+                                Command::IfGoto(inverted.clone()),
+                                Command::Goto(label.clone()),
+                                Command::Label(inverted),
+                            ]);
+                        }
+                    }
+                    report.inverted_branches += 1;
+                    changed = true;
+                } else {
+                    output.push(command.clone());
+                }
+            }
+            _ => output.push(command.clone()),
+        }
+    }
+    (output, changed)
+}
+
+fn push(segment: Segment, index: u16) -> Command {
+    Command::MemoryAccess(MemoryAccessCommand {
+        access_type: AccessType::Push,
+        segment,
+        index: Index::new(index),
+    })
+}
+
+// 値を積むコマンド列（負の値は `push constant !value`, `not` で表す）
+fn push_value(value: i16) -> Vec<Command> {
+    if value >= 0 {
+        vec![push(Segment::Constant, value as u16)]
+    } else {
+        vec![
+            push(Segment::Constant, !value as u16),
+            Command::Arithmetic(ArithmeticCommand::Not),
+        ]
+    }
+}
+
+// コマンド列の末尾で定数を積んでいれば、その値とコマンド数
+fn constant_suffix(commands: &[Command]) -> Option<(i16, usize)> {
+    let constant = |command: &Command| match command {
+        Command::MemoryAccess(MemoryAccessCommand {
+            access_type: AccessType::Push,
+            segment: Segment::Constant,
+            index,
+        }) => Some(index.get() as i16),
+        _ => None,
+    };
+    let (last, rest) = commands.split_last()?;
+    if let Some(value) = constant(last) {
+        return Some((value, 1));
+    }
+    let operand = constant(rest.last()?)?;
+    match last {
+        Command::Arithmetic(ArithmeticCommand::Neg) => Some((operand.wrapping_neg(), 2)),
+        Command::Arithmetic(ArithmeticCommand::Not) => Some((!operand, 2)),
+        _ => None,
+    }
+}
+
+// コマンド列の末尾で積んだ値が真偽値 (0 か -1) だと分かるか
+// (比較の結果、その not, 0 か -1 の定数)
+fn is_boolean_suffix(commands: &[Command]) -> bool {
+    match commands.split_last() {
+        Some((
+            Command::Arithmetic(
+                ArithmeticCommand::Eq | ArithmeticCommand::Gt | ArithmeticCommand::Lt,
+            ),
+            _,
+        )) => true,
+        Some((Command::Arithmetic(ArithmeticCommand::Not), rest))
+            if constant_suffix(commands).is_none() =>
+        {
+            is_boolean_suffix(rest)
+        }
+        _ => constant_suffix(commands).is_some_and(|(value, _)| value == 0 || value == -1),
+    }
+}
+
+// 定数への演算を畳み込んだ値と、取り除くコマンド数
+// 畳み込んでもコマンドが減らない場合 (`push constant 5`, `neg` など) は None
+fn fold(commands: &[Command], arithmetic: &ArithmeticCommand) -> Option<(i16, usize)> {
+    let boolean = |value: bool| if value { -1 } else { 0 };
+    let (y, y_length) = constant_suffix(commands)?;
+    let unary = match arithmetic {
+        ArithmeticCommand::Neg => Some(y.wrapping_neg()),
+        ArithmeticCommand::Not => Some(!y),
+        _ => None,
+    };
+    if let Some(value) = unary {
+        return (push_value(value).len() <= y_length).then_some((value, y_length));
+    }
+    let (x, x_length) = constant_suffix(&commands[..commands.len() - y_length])?;
+    let value = match arithmetic {
+        ArithmeticCommand::Add => x.wrapping_add(y),
+        ArithmeticCommand::Sub => x.wrapping_sub(y),
+        ArithmeticCommand::Eq => boolean(x == y),
+        ArithmeticCommand::Gt => boolean(x > y),
+        ArithmeticCommand::Lt => boolean(x < y),
+        ArithmeticCommand::And => x & y,
+        ArithmeticCommand::Or => x | y,
+        ArithmeticCommand::Neg | ArithmeticCommand::Not => unreachable!(),
+    };
+    Some((value, x_length + y_length))
+}
+
+// 反転した分岐のための、まだ使われていないラベル
+fn new_label(labels: &mut HashSet<String>, label: &Label) -> Label {
+    let name = (0..)
+        .map(|n| format!("{}.INV{n}", label.get()))
+        .find(|name| !labels.contains(name))
+        .expect("labels should not be exhausted");
+    labels.insert(name.clone());
+    Label::new(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize_source(source: &[&str]) -> (Vec<String>, OptimizeReport) {
+        let commands = super::super::parse(source.join("\n")).unwrap();
+        let optimized = optimize(&commands);
+        (
+            optimized
+                .commands
+                .iter()
+                .map(|command| command.to_string())
+                .collect(),
+            optimized.report,
+        )
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let (commands, report) = optimize_source(&[
+            "push constant 2",
+            "push constant 3",
+            "add",
+            "push constant 7",
+            "sub",
+            "push constant 1",
+            "neg",
+            "push constant 1",
+            "neg",
+            "lt",
+            "pop local 0",
+        ]);
+        // 2 + 3 - 7 = -2, (-1 < -1) = false
+        assert_eq!(
+            commands,
+            ["push constant 1", "not", "push constant 0", "pop local 0"]
+        );
+        assert_eq!(report.folded_constants, 3);
+        assert_eq!(report.saved(), 7);
+
+        // 定数の条件の if-goto と、ラベルを跨ぐ場合
+        let (commands, _) = optimize_source(&[
+            "label LOOP",
+            "push constant 0",
+            "not",
+            "not",
+            "if-goto END",
+            "push constant 1",
+            "neg",
+            "if-goto LOOP",
+            "push constant 1",
+            "label END",
+            "neg",
+        ]);
+        assert_eq!(
+            commands,
+            [
+                "label LOOP",
+                "goto LOOP",
+                "push constant 1",
+                "label END",
+                "neg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_redundant_commands() {
+        let (commands, report) = optimize_source(&[
+            "push local 0",
+            "pop local 0",
+            "push local 1",
+            "pop local 2",
+            "push constant 3",
+            "pop temp 0",
+            "push argument 0",
+            "not",
+            "not",
+            "goto NEXT",
+            "label OTHER",
+            "label NEXT",
+            "goto OTHER",
+            "push argument 0",
+        ]);
+        assert_eq!(
+            commands,
+            [
+                "push local 1",
+                "pop local 2",
+                "push constant 3",
+                "pop temp 0",
+                "push argument 0",
+                "label OTHER",
+                "label NEXT",
+                "goto OTHER",
+                "push argument 0",
+            ]
+        );
+        assert_eq!(report.push_pop_pairs, 1);
+        assert_eq!(report.double_negations, 1);
+        assert_eq!(report.jumps_to_next, 1);
+    }
+
+    #[test]
+    fn test_invert_branch() {
+        // while 文: 反転した分岐のラベルは既存のラベルと重ならないようにする
+        let (commands, report) = optimize_source(&[
+            "label WHILE",
+            "push local 0",
+            "push constant 10",
+            "lt",
+            "not",
+            "if-goto END",
+            "call Main.loop 0",
+            "pop temp 0",
+            "goto WHILE",
+            "label END",
+            "label END.INV0",
+        ]);
+        assert_eq!(
+            commands,
+            [
+                "label WHILE",
+                "push local 0",
+                "push constant 10",
+                "lt",
+                "if-goto END.INV1",
+                "goto END",
+                "label END.INV1",
+                "call Main.loop 0",
+                "pop temp 0",
+                "goto WHILE",
+                "label END",
+                "label END.INV0",
+            ]
+        );
+        assert_eq!(report.inverted_branches, 1);
+        assert_eq!(report.saved(), 0);
+
+        // 直後が 'goto M', 'label L' であれば 'if-goto M' にする
+        let (commands, _) = optimize_source(&[
+            "push local 0",
+            "push local 1",
+            "eq",
+            "not",
+            "not",
+            "not",
+            "if-goto ELSE",
+            "goto THEN",
+            "label ELSE",
+        ]);
+        assert_eq!(
+            commands,
+            [
+                "push local 0",
+                "push local 1",
+                "eq",
+                "if-goto THEN",
+                "label ELSE"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_non_boolean_branch() {
+        // 'not' はビット反転なので、条件が真偽値と分からなければ反転しない
+        // (x = 5 の時 'x & 4' は 4 で、'not' の結果 -5 も真になる)
+        for condition in [
+            &["push local 0"][..],
+            &["push local 0", "push constant 4", "and"],
+            &[
+                "push local 0",
+                "push local 1",
+                "lt",
+                "push constant 4",
+                "and",
+            ],
+        ] {
+            let source: Vec<&str> = condition
+                .iter()
+                .copied()
+                .chain(["not", "if-goto L", "goto M", "label L"])
+                .collect();
+            let (commands, report) = optimize_source(&source);
+            assert_eq!(commands, source);
+            assert_eq!(report.inverted_branches, 0);
+        }
+    }
+}
@@ -21,7 +21,7 @@ fn pre_process(input: String) -> impl Iterator<Item = String> {
     split_by_newline(input)
         .map(remove_comment)
         .map(trim_whitespace)
-        .filter(non_empty_line)
+        .filter(|line| non_empty_line(line))
 }
 
 parser! {
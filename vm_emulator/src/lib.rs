@@ -0,0 +1,6 @@
+mod machine;
+mod os;
+mod program;
+
+pub use machine::VmEmulator;
+pub use program::{Module, Program};
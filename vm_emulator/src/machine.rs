@@ -0,0 +1,412 @@
+//! VM コマンドを直接実行するエミュレータ
+//!
+//! RAM の配置は vm_translator の出力を Hack コンピュータで実行した場合と同じにする。
+//! SP, LCL, ARG, THIS, THAT は RAM[0..5]、temp は RAM[5..13]、スタティック変数は RAM[16..256]、
+//! スタックは RAM[256] から。呼び出しフレームも同じ形で RAM に積む（戻りアドレスは VM 命令の位置）。
+
+use crate::os::{NativeResult, OsState};
+use crate::program::{Instruction, Operand, Program};
+use emulator::{RunResult, KBD_ADDRESS, RAM_SIZE, SCREEN_ADDRESS, SCREEN_SIZE};
+use schema::vm;
+
+const SP: u16 = 0;
+const LCL: u16 = 1;
+const ARG: u16 = 2;
+const THIS: u16 = 3;
+const THAT: u16 = 4;
+const TEMP: u16 = 5;
+const STACK_START_RAM_ADDRESS: u16 = 256;
+
+#[derive(Debug, Clone)]
+pub struct VmEmulator {
+    program: Program,
+    ram: Vec<u16>,
+    pc: usize,
+    steps: u64,
+    // 呼び出し中の関数の番号（RAM のフレームとは別に、デバッグ用に持つ）
+    call_stack: Vec<usize>,
+    pub(crate) os: OsState,
+    // Sys.halt で停止したか
+    halted: bool,
+    // OS の実装から VM コードの関数を呼ぶときにも守る最大ステップ数
+    max_steps: u64,
+}
+
+impl VmEmulator {
+    // vm_translator のブートストラップと同じく、SP=256 にして Sys.init から実行する
+    // Sys.init が無ければ OS のブートストラップ (Program::with_os)、又は先頭の命令から実行する
+    pub fn new(program: Program) -> Self {
+        let mut vm_emulator = Self {
+            pc: program.entry,
+            program,
+            ram: vec![0; RAM_SIZE],
+            steps: 0,
+            call_stack: Vec::new(),
+            os: OsState::default(),
+            halted: false,
+            max_steps: u64::MAX,
+        };
+        vm_emulator.set_ram(SP, STACK_START_RAM_ADDRESS);
+        vm_emulator
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc
+    }
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    pub fn ram(&self, address: u16) -> u16 {
+        self.ram[address as usize & (RAM_SIZE - 1)]
+    }
+    pub fn set_ram(&mut self, address: u16, value: u16) {
+        self.ram[address as usize & (RAM_SIZE - 1)] = value
+    }
+    pub fn ram_slice(&self, start: u16, len: usize) -> &[u16] {
+        let start = start as usize & (RAM_SIZE - 1);
+        &self.ram[start..(start + len).min(RAM_SIZE)]
+    }
+    pub fn screen(&self) -> &[u16] {
+        self.ram_slice(SCREEN_ADDRESS, SCREEN_SIZE)
+    }
+
+    // スタックに積まれている値（底から順に）
+    pub fn stack(&self) -> &[u16] {
+        let sp = self.ram(SP).max(STACK_START_RAM_ADDRESS);
+        self.ram_slice(
+            STACK_START_RAM_ADDRESS,
+            (sp - STACK_START_RAM_ADDRESS) as usize,
+        )
+    }
+
+    // 次に実行するコマンド
+    pub fn current_command(&self) -> Option<&vm::Command> {
+        self.program.command(self.pc)
+    }
+
+    // 呼び出し中の関数の名前（外側から順に）
+    pub fn backtrace(&self) -> Vec<&str> {
+        self.call_stack
+            .iter()
+            .map(|function| self.program.functions[*function].name.as_str())
+            .collect()
+    }
+
+    pub(crate) fn halt(&mut self) {
+        self.halted = true
+    }
+
+    // Sys.halt が呼ばれたか、プログラムの終わりに達したか、'label X; goto X' の無限ループにいれば停止したとみなす
+    pub fn is_halted(&self) -> bool {
+        if self.halted {
+            return true;
+        }
+        match self.program.instructions.get(self.pc) {
+            None => true,
+            Some(Instruction::Goto(target)) => *target == self.pc,
+            Some(_) => false,
+        }
+    }
+
+    // 停止するか、最大ステップ数に達するまで実行する
+    pub fn run(&mut self, max_steps: u64) -> anyhow::Result<RunResult> {
+        self.max_steps = max_steps;
+        while self.steps < max_steps {
+            if self.is_halted() {
+                return Ok(RunResult::Halted);
+            }
+            self.step()?;
+        }
+        Ok(RunResult::CycleLimit)
+    }
+
+    // 1つのコマンドを実行する（エラーにはコマンドの位置を付ける）
+    pub fn step(&mut self) -> anyhow::Result<()> {
+        let pc = self.pc;
+        self.execute().map_err(|e| {
+            let command = self
+                .program
+                .command(pc)
+                .map_or(String::new(), |command| format!(" ({command})"));
+            anyhow::anyhow!("{}{command}: {e}", self.program.location(pc))
+        })
+    }
+
+    fn execute(&mut self) -> anyhow::Result<()> {
+        let Some(instruction) = self.program.instructions.get(self.pc) else {
+            anyhow::bail!("no more commands");
+        };
+        self.steps += 1;
+        let mut next_pc = self.pc + 1;
+        match instruction.clone() {
+            Instruction::Arithmetic(command) => self.arithmetic(command),
+            Instruction::Push(operand) => {
+                let value = match operand {
+                    Operand::Constant(value) => value,
+                    operand => self.ram(self.address(&operand)),
+                };
+                self.push(value);
+            }
+            Instruction::Pop(operand) => {
+                // pop pointer 0 などで先にベースアドレスが変わらないよう、アドレスを先に求める
+                let address = self.address(&operand);
+                let value = self.pop();
+                self.write(address, value);
+            }
+            Instruction::Function {
+                local_variable_count,
+            } => {
+                for _ in 0..local_variable_count {
+                    self.push(0);
+                }
+            }
+            Instruction::Call {
+                function,
+                args_count,
+            } => {
+                let entry = &self.program.functions[function];
+                match (entry.address, entry.native) {
+                    (Some(address), _) => {
+                        self.enter(function, args_count, next_pc)?;
+                        next_pc = address;
+                    }
+                    // OS の実装は引数をスタックに積んだまま呼び、戻り値と置き換える
+                    (None, Some(native)) => {
+                        let sp = self.ram(SP);
+                        let args = self
+                            .ram_slice(sp.wrapping_sub(args_count), args_count as usize)
+                            .to_vec();
+                        match native(self, &args)? {
+                            // Sys.halt で停止したら、呼び出した位置で止まる
+                            _ if self.halted => return Ok(()),
+                            NativeResult::Return(value) => {
+                                self.set_ram(SP, sp.wrapping_sub(args_count));
+                                self.push(value);
+                            }
+                            NativeResult::Wait => next_pc = self.pc,
+                        }
+                    }
+                    (None, None) => anyhow::bail!("undefined function {}", entry.name),
+                }
+            }
+            Instruction::Return => {
+                let frame = self.ram(LCL);
+                let return_address = self.ram(frame.wrapping_sub(5));
+                let value = self.pop();
+                let arg = self.ram(ARG);
+                self.write(arg, value);
+                self.set_ram(SP, arg.wrapping_add(1));
+                for (register, offset) in [(THAT, 1), (THIS, 2), (ARG, 3), (LCL, 4)] {
+                    self.set_ram(register, self.ram(frame.wrapping_sub(offset)));
+                }
+                // 呼び出されずに実行を始めた関数から戻ったら、戻り先が無いので停止する
+                next_pc = match self.call_stack.pop() {
+                    Some(_) => return_address as usize,
+                    None => self.program.len(),
+                };
+            }
+            Instruction::Goto(target) => next_pc = target,
+            Instruction::IfGoto(target) => {
+                if self.pop() != 0 {
+                    next_pc = target;
+                }
+            }
+        }
+        self.pc = next_pc;
+        Ok(())
+    }
+
+    // 呼び出しフレームを積む（引数は積まれているとする）
+    fn enter(&mut self, function: usize, args_count: u16, return_pc: usize) -> anyhow::Result<()> {
+        let return_address = u16::try_from(return_pc)
+            .map_err(|_| anyhow::anyhow!("return address {return_pc} overflows"))?;
+        self.push(return_address);
+        for register in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram(register));
+        }
+        let sp = self.ram(SP);
+        self.set_ram(ARG, sp.wrapping_sub(5 + args_count));
+        self.set_ram(LCL, sp);
+        self.call_stack.push(function);
+        Ok(())
+    }
+
+    // OS の実装から関数を呼び、戻り値を返す
+    // VM コードの関数であれば、戻るまでここで実行する（停止したら 0 を返す）
+    pub(crate) fn call_function(&mut self, name: &str, args: &[u16]) -> anyhow::Result<u16> {
+        let function = self.program.function_ids.get(name).copied();
+        let address = function.and_then(|id| self.program.functions[id].address);
+        let (Some(function), Some(address)) = (function, address) else {
+            let Some(native) = self.program.native(name) else {
+                anyhow::bail!("undefined function {name}");
+            };
+            return match native(self, args)? {
+                NativeResult::Return(value) => Ok(value),
+                NativeResult::Wait => {
+                    anyhow::bail!("{name} cannot wait for the keyboard when called from the OS")
+                }
+            };
+        };
+
+        let pc = self.pc;
+        for arg in args {
+            self.push(*arg);
+        }
+        self.enter(function, args.len() as u16, pc)?;
+        self.pc = address;
+        let depth = self.call_stack.len() - 1;
+        while self.call_stack.len() > depth {
+            if self.is_halted() {
+                self.halted = true;
+                return Ok(0);
+            }
+            if self.steps >= self.max_steps {
+                anyhow::bail!("step limit reached in {name}");
+            }
+            self.step()?;
+        }
+        self.pc = pc;
+        Ok(self.pop())
+    }
+
+    fn arithmetic(&mut self, command: vm::ArithmeticCommand) {
+        use vm::ArithmeticCommand::*;
+        let boolean = |condition: bool| if condition { 0xffff } else { 0 };
+        let y = self.pop();
+        let result = match command {
+            Neg => y.wrapping_neg(),
+            Not => !y,
+            _ => {
+                let x = self.pop();
+                match command {
+                    Add => x.wrapping_add(y),
+                    Sub => x.wrapping_sub(y),
+                    Eq => boolean(x == y),
+                    Gt => boolean((x as i16) > (y as i16)),
+                    Lt => boolean((x as i16) < (y as i16)),
+                    And => x & y,
+                    Or => x | y,
+                    Neg | Not => unreachable!(),
+                }
+            }
+        };
+        self.push(result);
+    }
+
+    // セグメントの RAM アドレス
+    fn address(&self, operand: &Operand) -> u16 {
+        match operand {
+            Operand::Constant(_) => unreachable!("constant segment has no address"),
+            Operand::Static(address) => *address,
+            Operand::Segment(segment, index) => {
+                let base = match segment {
+                    vm::Segment::Argument => self.ram(ARG),
+                    vm::Segment::Local => self.ram(LCL),
+                    vm::Segment::This => self.ram(THIS),
+                    vm::Segment::That => self.ram(THAT),
+                    vm::Segment::Pointer => THIS,
+                    vm::Segment::Temp => TEMP,
+                    vm::Segment::Constant | vm::Segment::Static => unreachable!(),
+                };
+                base.wrapping_add(*index)
+            }
+        }
+    }
+
+    // キーボードのメモリマップは読み出し専用
+    fn write(&mut self, address: u16, value: u16) {
+        if address != KBD_ADDRESS {
+            self.set_ram(address, value);
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.ram(SP);
+        self.set_ram(sp, value);
+        self.set_ram(SP, sp.wrapping_add(1));
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.ram(SP).wrapping_sub(1);
+        self.set_ram(SP, sp);
+        self.ram(sp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Module;
+
+    #[test]
+    fn test_call_and_return() {
+        let main = Module::parse(
+            "Main",
+            [
+                // Main.double(x) = x + x をローカル変数を使って計算する
+                "function Main.double 1",
+                "push argument 0",
+                "push argument 0",
+                "add",
+                "pop local 0",
+                "push local 0",
+                "return",
+                "function Main.main 0",
+                "push constant 21",
+                "call Main.double 1",
+                "pop static 0",
+                "push constant 3",
+                "push constant 5",
+                "lt",
+                "if-goto NEGATE",
+                "push constant 0",
+                "return",
+                "label NEGATE",
+                "push static 0",
+                "neg",
+                "return",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let sys = Module::parse(
+            "Sys",
+            [
+                "function Sys.init 0",
+                "call Main.main 0",
+                "pop temp 0",
+                "label HALT",
+                "goto HALT",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let mut vm_emulator = VmEmulator::new(Program::new(&[main, sys]).unwrap());
+        assert_eq!(vm_emulator.run(1000).unwrap(), RunResult::Halted);
+
+        assert_eq!(vm_emulator.ram(16), 42);
+        assert_eq!(vm_emulator.ram(TEMP) as i16, -42);
+        assert_eq!(vm_emulator.ram(SP), 256);
+        assert!(vm_emulator.backtrace().is_empty());
+        assert_eq!(vm_emulator.steps(), 21);
+        assert_eq!(
+            vm_emulator.current_command().unwrap().to_string(),
+            "goto HALT"
+        );
+
+        // 定義されていない関数の呼び出しは位置を付けてエラーにする
+        let main =
+            Module::parse("Main", "function Main.main 0\ncall Math.sqrt 1".to_string()).unwrap();
+        let mut vm_emulator = VmEmulator::new(Program::new(&[main]).unwrap());
+        assert_eq!(
+            vm_emulator.run(10).unwrap_err().to_string(),
+            "Main.main+1 (call Math.sqrt 1): undefined function Math.sqrt"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use emulator::{Framebuffer, RunResult};
+use std::path::{Path, PathBuf};
+use vm_emulator::{Module, Program, VmEmulator};
+
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+// .vm ファイル、又はディレクトリ内の全ての .vm ファイル（ファイル名順）
+fn vm_files(input_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !input_path.is_dir() {
+        return Ok(vec![input_path.to_path_buf()]);
+    }
+    let mut input_files = std::fs::read_dir(input_path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    input_files.retain(|path| path.is_file() && path.extension() == Some("vm".as_ref()));
+    input_files.sort();
+    if input_files.is_empty() {
+        anyhow::bail!("{}: .vm files could not be found", input_path.display());
+    }
+    Ok(input_files)
+}
+
+fn run() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    // 実行する .vm ファイル、又は .vm ファイルを含むディレクトリのパス
+    let input_path: &Path = Path::new(args.get(1).ok_or_else(|| {
+        anyhow::anyhow!(
+            "usage: vm_emulator <file.vm|directory> [max_steps] [--ram <address>=<value>] [--screen <file.pbm|file.png>] [--print-screen <ascii|braille>] [--no-os]"
+        )
+    })?);
+    // --ram <address>=<value>: 実行前に RAM に値を書き込む（複数指定できる）
+    // --screen <file>: 実行後のスクリーンを画像 (.pbm, .png) として出力する
+    // --print-screen <ascii|braille>: 実行後のスクリーンを文字で標準出力に表示する
+    // --no-os: .vm ファイルに無い OS の関数を Rust の実装で実行しない
+    let mut max_steps = DEFAULT_MAX_STEPS;
+    let mut initial_ram = Vec::new();
+    let mut screen_path = None;
+    let mut print_screen = None;
+    let mut os = true;
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{option} requires a value"))
+        };
+        match option.as_str() {
+            "--ram" => {
+                let assignment = value()?;
+                let parsed = assignment.split_once('=').and_then(|(address, value)| {
+                    Some((address.parse::<u16>().ok()?, value.parse::<i32>().ok()?))
+                });
+                let Some((address, value)) = parsed else {
+                    anyhow::bail!("invalid --ram value: {assignment} (expected <address>=<value>)");
+                };
+                initial_ram.push((address, value as u16));
+            }
+            "--screen" => screen_path = Some(Path::new(value()?)),
+            "--print-screen" => print_screen = Some(value()?.as_str()),
+            "--no-os" => os = false,
+            _ => {
+                max_steps = option
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("unknown option: {option}"))?
+            }
+        }
+    }
+
+    let modules = vm_files(input_path)?
+        .into_iter()
+        .map(|path| {
+            // ファイル名をモジュール名とする
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("");
+            Module::parse(name, std::fs::read_to_string(&path)?)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let program = if os {
+        Program::with_os(&modules)?
+    } else {
+        Program::new(&modules)?
+    };
+    let mut vm_emulator = VmEmulator::new(program);
+    for (address, value) in initial_ram {
+        vm_emulator.set_ram(address, value);
+    }
+
+    let result = vm_emulator.run(max_steps).map_err(|e| {
+        anyhow::anyhow!(
+            "{e}\nbacktrace: {}\nstack: {:?}",
+            vm_emulator.backtrace().join(" -> "),
+            vm_emulator.stack()
+        )
+    })?;
+    println!(
+        "{}: {} steps={}",
+        match result {
+            RunResult::Halted => "halted",
+            RunResult::CycleLimit => "step limit reached",
+        },
+        vm_emulator.program().location(vm_emulator.pc()),
+        vm_emulator.steps()
+    );
+    for (address, value) in vm_emulator.ram_slice(0, 16).iter().enumerate() {
+        println!("RAM[{address}] = {}", *value as i16);
+    }
+
+    let framebuffer = Framebuffer::from_screen(vm_emulator.screen());
+    if let Some(screen_path) = screen_path {
+        framebuffer.write_file(screen_path)?;
+    }
+    match print_screen {
+        Some("ascii") => println!("{}", framebuffer.to_ascii()),
+        Some("braille") => println!("{}", framebuffer.to_braille()),
+        Some(style) => anyhow::bail!("unknown screen style: {style} (ascii or braille)"),
+        None => {}
+    }
+    Ok(())
+}
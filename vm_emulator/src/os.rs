@@ -0,0 +1,156 @@
+//! Jack OS (Math, Memory, Screen, Output, Keyboard, String, Array, Sys) の Rust による実装
+//!
+//! VM コードで定義されていないクラスの関数の呼び出しは、ここの実装で実行する。
+//! クラスの関数を1つでも VM コードで定義すれば、そのクラスは全て VM コードで実行する。
+//! OS の関数から他のクラスの関数を呼ぶときも同じ規則で呼び分けるので、
+//! 例えば自作の Memory.vm を Rust の Math や Output と組み合わせて試せる。
+
+mod array;
+mod keyboard;
+mod math;
+mod memory;
+mod output;
+mod screen;
+mod string;
+mod sys;
+
+use crate::machine::VmEmulator;
+use crate::program::Module;
+
+// 関数の引数（スタックに積まれた順）を受け取り、戻り値を返す
+pub(crate) type NativeFunction = fn(&mut VmEmulator, &[u16]) -> anyhow::Result<NativeResult>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NativeResult {
+    Return(u16),
+    // キーボードの入力を待つ（次のステップで同じ 'call' をもう一度実行する）
+    Wait,
+}
+
+// OS のクラスが持つ状態
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OsState {
+    pub(crate) memory: memory::Heap,
+    pub(crate) screen: screen::Pen,
+    pub(crate) output: output::Cursor,
+    pub(crate) keyboard: keyboard::Input,
+}
+
+pub(crate) fn native(name: &str) -> Option<NativeFunction> {
+    let (class_name, function_name) = name.split_once('.')?;
+    match class_name {
+        "Array" => array::native(function_name),
+        "Keyboard" => keyboard::native(function_name),
+        "Math" => math::native(function_name),
+        "Memory" => memory::native(function_name),
+        "Output" => output::native(function_name),
+        "Screen" => screen::native(function_name),
+        "String" => string::native(function_name),
+        "Sys" => sys::native(function_name),
+        _ => None,
+    }
+}
+
+// Sys.init が無いときに使う、Sys.init と同じく OS を初期化して Main.main を呼ぶブートストラップ
+pub(crate) fn bootstrap_module() -> Module {
+    let commands = [
+        "Memory.init",
+        "Math.init",
+        "Keyboard.init",
+        "Output.init",
+        "Screen.init",
+        "Main.main",
+        "Sys.halt",
+    ]
+    .iter()
+    .flat_map(|name| [format!("call {name} 0"), "pop temp 0".to_string()])
+    .collect::<Vec<_>>()
+    .join("\n");
+    Module::parse("<bootstrap>", commands).expect("bootstrap code should be valid")
+}
+
+// Sys.error を呼んで戻り値 0 を返す
+fn error(vm_emulator: &mut VmEmulator, error_code: u16) -> anyhow::Result<NativeResult> {
+    vm_emulator.call_function("Sys.error", &[error_code])?;
+    Ok(NativeResult::Return(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use emulator::{Framebuffer, RunResult};
+
+    fn run(sources: &[(&str, &str)]) -> VmEmulator {
+        let modules = sources
+            .iter()
+            .map(|(name, source)| Module::parse(name, source.to_string()).unwrap())
+            .collect::<Vec<_>>();
+        let mut vm_emulator = VmEmulator::new(Program::with_os(&modules).unwrap());
+        assert_eq!(vm_emulator.run(100_000).unwrap(), RunResult::Halted);
+        vm_emulator
+    }
+
+    #[test]
+    fn test_native_os() {
+        // "Hi" を表示し、Math.multiply の結果と String の長さを static に保存する
+        let main = [
+            "function Main.main 1",
+            "push constant 2",
+            "call String.new 1",
+            "push constant 72",
+            "call String.appendChar 2",
+            "push constant 105",
+            "call String.appendChar 2",
+            "pop local 0",
+            "push local 0",
+            "call Output.printString 1",
+            "pop temp 0",
+            "push constant 123",
+            "push constant 3",
+            "neg",
+            "call Math.multiply 2",
+            "pop static 0",
+            "push local 0",
+            "call String.length 1",
+            "pop static 1",
+            "push constant 0",
+            "return",
+        ]
+        .join("\n");
+        let vm_emulator = run(&[("Main", &main)]);
+        assert_eq!(vm_emulator.ram(16) as i16, -369);
+        assert_eq!(vm_emulator.ram(17), 2);
+        // 'H' の1行目 (0b110011) と 'i' の1行目 (0b1100) が先頭のワードに描かれる
+        assert_eq!(vm_emulator.ram(emulator::SCREEN_ADDRESS), 51 | (12 << 8));
+        assert!(Framebuffer::from_screen(vm_emulator.screen()).get(0, 0));
+        // String のオブジェクトとその文字の配列はヒープの先頭から順に確保される
+        assert_eq!(vm_emulator.ram(2048), 2051);
+
+        // VM コードで定義したクラスは、OS の関数から呼ばれるときもその定義を使う
+        let string = [
+            "function String.new 0",
+            "push constant 0",
+            "return",
+            "function String.length 0",
+            "push constant 1",
+            "return",
+            "function String.charAt 0",
+            "push constant 33",
+            "return",
+        ]
+        .join("\n");
+        let main = [
+            "function Main.main 0",
+            "push constant 0",
+            "call Output.printString 1",
+            "pop temp 0",
+            "push constant 0",
+            "return",
+        ]
+        .join("\n");
+        let vm_emulator = run(&[("Main", &main), ("String", &string)]);
+        // '!' の1行目
+        assert_eq!(vm_emulator.ram(emulator::SCREEN_ADDRESS), 12);
+    }
+}
@@ -0,0 +1,19 @@
+use super::{error, NativeFunction, NativeResult::Return};
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "new" => |vm_emulator, args| match args[0] as i16 {
+            // 2: 配列の大きさが正でない
+            size if size <= 0 => error(vm_emulator, 2),
+            size => Ok(Return(
+                vm_emulator.call_function("Memory.alloc", &[size as u16])?,
+            )),
+        },
+        "dispose" => |vm_emulator, args| {
+            vm_emulator.call_function("Memory.deAlloc", &[args[0]])?;
+            Ok(Return(0))
+        },
+        _ => return None,
+    };
+    Some(native)
+}
@@ -0,0 +1,135 @@
+use super::{
+    NativeFunction,
+    NativeResult::{Return, Wait},
+};
+use crate::machine::VmEmulator;
+use emulator::KBD_ADDRESS;
+
+const NEW_LINE: u16 = 128;
+const BACK_SPACE: u16 = 129;
+
+// 入力を待つ間 'call' を繰り返し実行するので、途中の状態を持っておく
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Input {
+    // 押されていて、まだ離されていないキー
+    pressed: Option<u16>,
+    // Keyboard.readLine で入力中の行（メッセージを表示したら Some）
+    line: Option<Vec<u16>>,
+}
+
+// キーが押されて離されたら、そのキーを返す
+fn read_key(vm_emulator: &mut VmEmulator) -> Option<u16> {
+    let key = vm_emulator.ram(KBD_ADDRESS);
+    match vm_emulator.os.keyboard.pressed {
+        None => {
+            if key != 0 {
+                vm_emulator.os.keyboard.pressed = Some(key);
+            }
+            None
+        }
+        Some(pressed) if key == 0 => {
+            vm_emulator.os.keyboard.pressed = None;
+            Some(pressed)
+        }
+        Some(_) => None,
+    }
+}
+
+// 改行が入力されたら、入力された行を String として返す
+fn read_line(vm_emulator: &mut VmEmulator, message: u16) -> anyhow::Result<Option<u16>> {
+    if vm_emulator.os.keyboard.line.is_none() {
+        vm_emulator.call_function("Output.printString", &[message])?;
+        vm_emulator.os.keyboard.line = Some(Vec::new());
+    }
+    match read_key(vm_emulator) {
+        Some(NEW_LINE) => {
+            vm_emulator.call_function("Output.println", &[])?;
+            let line = vm_emulator.os.keyboard.line.take().unwrap_or_default();
+            let string = vm_emulator.call_function("String.new", &[line.len().max(64) as u16])?;
+            for c in line {
+                vm_emulator.call_function("String.appendChar", &[string, c])?;
+            }
+            return Ok(Some(string));
+        }
+        Some(BACK_SPACE) => {
+            if let Some(line) = &mut vm_emulator.os.keyboard.line {
+                if line.pop().is_some() {
+                    vm_emulator.call_function("Output.backSpace", &[])?;
+                }
+            }
+        }
+        // 表示できる文字のみを入力する
+        Some(c) if c < NEW_LINE => {
+            vm_emulator.call_function("Output.printChar", &[c])?;
+            if let Some(line) = &mut vm_emulator.os.keyboard.line {
+                line.push(c);
+            }
+        }
+        _ => {}
+    }
+    Ok(None)
+}
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "init" => |vm_emulator, _| {
+            vm_emulator.os.keyboard = Input::default();
+            Ok(Return(0))
+        },
+        "keyPressed" => |vm_emulator, _| Ok(Return(vm_emulator.ram(KBD_ADDRESS))),
+        "readChar" => |vm_emulator, _| match read_key(vm_emulator) {
+            Some(c) => {
+                if c < NEW_LINE {
+                    vm_emulator.call_function("Output.printChar", &[c])?;
+                }
+                Ok(Return(c))
+            }
+            None => Ok(Wait),
+        },
+        "readLine" => |vm_emulator, args| Ok(read_line(vm_emulator, args[0])?.map_or(Wait, Return)),
+        "readInt" => |vm_emulator, args| {
+            let Some(string) = read_line(vm_emulator, args[0])? else {
+                return Ok(Wait);
+            };
+            let value = vm_emulator.call_function("String.intValue", &[string])?;
+            vm_emulator.call_function("String.dispose", &[string])?;
+            Ok(Return(value))
+        },
+        _ => return None,
+    };
+    Some(native)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::VmEmulator;
+    use crate::program::{Module, Program};
+    use emulator::{RunResult, KBD_ADDRESS};
+
+    #[test]
+    fn test_read_int() {
+        let main = [
+            "function Main.main 0",
+            "push constant 0",
+            "call String.new 1",
+            "call Keyboard.readInt 1",
+            "pop static 0",
+            "push constant 0",
+            "return",
+        ]
+        .join("\n");
+        let program = Program::with_os(&[Module::parse("Main", main).unwrap()]).unwrap();
+        let mut vm_emulator = VmEmulator::new(program);
+        // 入力を待つ間は同じ 'call' を繰り返す
+        assert_eq!(vm_emulator.run(100).unwrap(), RunResult::CycleLimit);
+        // キーを押して離すごとに1文字入力する ('9' は BackSpace で消す)
+        for key in [b'-' as u16, b'4' as u16, b'9' as u16, 129, b'2' as u16, 128] {
+            vm_emulator.set_ram(KBD_ADDRESS, key);
+            vm_emulator.run(vm_emulator.steps() + 10).unwrap();
+            vm_emulator.set_ram(KBD_ADDRESS, 0);
+            vm_emulator.run(vm_emulator.steps() + 10).unwrap();
+        }
+        assert!(vm_emulator.is_halted());
+        assert_eq!(vm_emulator.ram(16) as i16, -42);
+    }
+}
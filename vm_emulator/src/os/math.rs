@@ -0,0 +1,49 @@
+use super::{error, NativeFunction, NativeResult::Return};
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "init" => |_, _| Ok(Return(0)),
+        "abs" => |_, args| Ok(Return((args[0] as i16).wrapping_abs() as u16)),
+        "multiply" => |_, args| Ok(Return(args[0].wrapping_mul(args[1]))),
+        "divide" => |vm_emulator, args| match args[1] {
+            // 3: ゼロ除算
+            0 => error(vm_emulator, 3),
+            _ => Ok(Return((args[0] as i16).wrapping_div(args[1] as i16) as u16)),
+        },
+        "min" => |_, args| Ok(Return((args[0] as i16).min(args[1] as i16) as u16)),
+        "max" => |_, args| Ok(Return((args[0] as i16).max(args[1] as i16) as u16)),
+        "sqrt" => |vm_emulator, args| match args[0] as i16 {
+            // 4: 負の数の平方根
+            x if x < 0 => error(vm_emulator, 4),
+            x => Ok(Return(sqrt(x as u16))),
+        },
+        // 以下は JackOS/Math.jack 独自の関数（JackOS の Output.jack と Screen.jack から呼ばれる）
+        "pow" => |_, args| {
+            Ok(Return(
+                (0..args[1] as i16).fold(1u16, |result, _| result.wrapping_mul(args[0])),
+            ))
+        },
+        "bit" => |_, args| {
+            Ok(Return(if args[0] & (1 << (args[1] & 15)) != 0 {
+                0xffff
+            } else {
+                0
+            }))
+        },
+        "getBits" => |_, args| Ok(Return(1 << (args[0] & 15))),
+        _ => return None,
+    };
+    Some(native)
+}
+
+// 平方根の整数部分
+fn sqrt(x: u16) -> u16 {
+    let mut y: u16 = 0;
+    for bit in (0..8).rev() {
+        let candidate = y | (1 << bit);
+        if (candidate as u32) * (candidate as u32) <= x as u32 {
+            y = candidate;
+        }
+    }
+    y
+}
@@ -0,0 +1,111 @@
+use super::{error, NativeFunction, NativeResult::Return};
+use std::collections::BTreeMap;
+
+// ヒープは RAM[2048..16384]
+const HEAP_START_RAM_ADDRESS: u16 = 2048;
+const HEAP_END_RAM_ADDRESS: u16 = 16384;
+
+// 空き領域と確保した領域（先頭アドレス → 大きさ）は RAM の外で管理する
+#[derive(Debug, Clone)]
+pub(crate) struct Heap {
+    free: BTreeMap<u16, u16>,
+    allocated: BTreeMap<u16, u16>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            free: BTreeMap::from([(
+                HEAP_START_RAM_ADDRESS,
+                HEAP_END_RAM_ADDRESS - HEAP_START_RAM_ADDRESS,
+            )]),
+            allocated: BTreeMap::new(),
+        }
+    }
+}
+
+impl Heap {
+    // 先頭に近い空き領域から確保する
+    fn alloc(&mut self, size: u16) -> Option<u16> {
+        let (&address, &free_size) = self
+            .free
+            .iter()
+            .find(|(_, free_size)| **free_size >= size)?;
+        self.free.remove(&address);
+        if free_size > size {
+            self.free.insert(address + size, free_size - size);
+        }
+        self.allocated.insert(address, size);
+        Some(address)
+    }
+
+    // 前後の空き領域と結合して戻す（確保していないアドレスは無視する）
+    fn de_alloc(&mut self, address: u16) {
+        let Some(mut size) = self.allocated.remove(&address) else {
+            return;
+        };
+        let mut address = address;
+        if let Some(next_size) = self.free.remove(&(address + size)) {
+            size += next_size;
+        }
+        if let Some((&previous, &previous_size)) = self.free.range(..address).next_back() {
+            if previous + previous_size == address {
+                self.free.remove(&previous);
+                address = previous;
+                size += previous_size;
+            }
+        }
+        self.free.insert(address, size);
+    }
+}
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "init" => |vm_emulator, _| {
+            vm_emulator.os.memory = Heap::default();
+            Ok(Return(0))
+        },
+        "peek" => |vm_emulator, args| Ok(Return(vm_emulator.ram(args[0]))),
+        "poke" => |vm_emulator, args| {
+            vm_emulator.set_ram(args[0], args[1]);
+            Ok(Return(0))
+        },
+        "alloc" => |vm_emulator, args| match args[0] as i16 {
+            // 5: 確保する大きさが正でない
+            size if size <= 0 => error(vm_emulator, 5),
+            size => match vm_emulator.os.memory.alloc(size as u16) {
+                Some(address) => Ok(Return(address)),
+                // 6: ヒープの空きが足りない
+                None => error(vm_emulator, 6),
+            },
+        },
+        "deAlloc" => |vm_emulator, args| {
+            vm_emulator.os.memory.de_alloc(args[0]);
+            Ok(Return(0))
+        },
+        _ => return None,
+    };
+    Some(native)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap() {
+        let mut heap = Heap::default();
+        let a = heap.alloc(3).unwrap();
+        let b = heap.alloc(5).unwrap();
+        let c = heap.alloc(2).unwrap();
+        assert_eq!((a, b, c), (2048, 2051, 2056));
+        heap.de_alloc(a);
+        heap.de_alloc(b);
+        // 解放した a と b は1つの空き領域になる
+        assert_eq!(heap.alloc(8), Some(2048));
+        heap.de_alloc(2048);
+        heap.de_alloc(c);
+        assert_eq!(heap.free, BTreeMap::from([(2048, 14336)]));
+        assert_eq!(heap.alloc(14337), None);
+    }
+}
@@ -0,0 +1,132 @@
+use super::{error, NativeFunction, NativeResult::Return};
+use crate::machine::VmEmulator;
+use emulator::{SCREEN_ADDRESS, SCREEN_HEIGHT, SCREEN_SIZE, SCREEN_WIDTH};
+
+// 描画する色（true が黒）
+#[derive(Debug, Clone)]
+pub(crate) struct Pen {
+    color: bool,
+}
+
+impl Default for Pen {
+    fn default() -> Self {
+        Self { color: true }
+    }
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..SCREEN_WIDTH as i16).contains(&x) && (0..SCREEN_HEIGHT as i16).contains(&y)
+}
+
+// 画面外の点は描かない
+fn draw_pixel(vm_emulator: &mut VmEmulator, x: i16, y: i16) {
+    if !on_screen(x, y) {
+        return;
+    }
+    let address = SCREEN_ADDRESS + y as u16 * 32 + x as u16 / 16;
+    let mask = 1 << (x & 15);
+    let word = vm_emulator.ram(address);
+    vm_emulator.set_ram(
+        address,
+        if vm_emulator.os.screen.color {
+            word | mask
+        } else {
+            word & !mask
+        },
+    );
+}
+
+// ブレゼンハムのアルゴリズムで両端を含む線分を描く
+fn draw_line(vm_emulator: &mut VmEmulator, (x1, y1): (i16, i16), (x2, y2): (i16, i16)) {
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let (mut x, mut y, mut error) = (x1, y1, dx + dy);
+    loop {
+        draw_pixel(vm_emulator, x, y);
+        if (x, y) == (x2, y2) {
+            return;
+        }
+        if 2 * error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if 2 * error <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn draw_horizontal_line(vm_emulator: &mut VmEmulator, x1: i16, x2: i16, y: i16) {
+    for x in x1..=x2 {
+        draw_pixel(vm_emulator, x, y);
+    }
+}
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "init" => |vm_emulator, _| {
+            vm_emulator.os.screen = Pen::default();
+            Ok(Return(0))
+        },
+        "clearScreen" => |vm_emulator, _| {
+            for offset in 0..SCREEN_SIZE as u16 {
+                vm_emulator.set_ram(SCREEN_ADDRESS + offset, 0);
+            }
+            Ok(Return(0))
+        },
+        "setColor" => |vm_emulator, args| {
+            vm_emulator.os.screen.color = args[0] != 0;
+            Ok(Return(0))
+        },
+        "drawPixel" => |vm_emulator, args| {
+            let (x, y) = (args[0] as i16, args[1] as i16);
+            if !on_screen(x, y) {
+                // 7: 画面外の点
+                return error(vm_emulator, 7);
+            }
+            draw_pixel(vm_emulator, x, y);
+            Ok(Return(0))
+        },
+        "drawLine" => |vm_emulator, args| {
+            let [x1, y1, x2, y2] = [args[0], args[1], args[2], args[3]].map(|arg| arg as i16);
+            if !on_screen(x1, y1) || !on_screen(x2, y2) {
+                // 8: 画面外の線分
+                return error(vm_emulator, 8);
+            }
+            draw_line(vm_emulator, (x1, y1), (x2, y2));
+            Ok(Return(0))
+        },
+        "drawRectangle" => |vm_emulator, args| {
+            let [x1, y1, x2, y2] = [args[0], args[1], args[2], args[3]].map(|arg| arg as i16);
+            if !on_screen(x1, y1) || !on_screen(x2, y2) || x1 > x2 || y1 > y2 {
+                // 9: 画面外、又は左上と右下が逆の長方形
+                return error(vm_emulator, 9);
+            }
+            for y in y1..=y2 {
+                draw_horizontal_line(vm_emulator, x1, x2, y);
+            }
+            Ok(Return(0))
+        },
+        "drawCircle" => |vm_emulator, args| {
+            let [x, y, r] = [args[0], args[1], args[2]].map(|arg| arg as i16);
+            if !on_screen(x, y) {
+                // 12: 画面外の中心
+                return error(vm_emulator, 12);
+            }
+            if !(0..=181).contains(&r) {
+                // 13: 半径が負、又は大きすぎる
+                return error(vm_emulator, 13);
+            }
+            // 各行に、中心からの距離に応じた幅の水平線を描く
+            for dy in -r..=r {
+                let half_width =
+                    ((r as i32 * r as i32 - dy as i32 * dy as i32) as f64).sqrt() as i16;
+                draw_horizontal_line(vm_emulator, x - half_width, x + half_width, y + dy);
+            }
+            Ok(Return(0))
+        },
+        _ => return None,
+    };
+    Some(native)
+}
@@ -0,0 +1,129 @@
+use super::{error, NativeFunction, NativeResult::Return};
+use crate::machine::VmEmulator;
+
+// オブジェクトのフィールドは JackOS/String.jack と同じく、文字の配列・最大の長さ・現在の長さの順
+const CHARS: u16 = 0;
+const MAX_LENGTH: u16 = 1;
+const LENGTH: u16 = 2;
+
+fn field(vm_emulator: &VmEmulator, this: u16, field: u16) -> u16 {
+    vm_emulator.ram(this.wrapping_add(field))
+}
+
+fn set_field(vm_emulator: &mut VmEmulator, this: u16, field: u16, value: u16) {
+    vm_emulator.set_ram(this.wrapping_add(field), value)
+}
+
+fn int_value(vm_emulator: &VmEmulator, this: u16) -> u16 {
+    let chars = field(vm_emulator, this, CHARS);
+    let digits = (0..field(vm_emulator, this, LENGTH))
+        .map(|index| vm_emulator.ram(chars.wrapping_add(index)))
+        .collect::<Vec<_>>();
+    let (negative, digits) = match digits.split_first() {
+        Some((&c, rest)) if c == b'-' as u16 => (true, rest),
+        _ => (false, &digits[..]),
+    };
+    // 数字でない文字が現れるまでを数として読む
+    let value = digits
+        .iter()
+        .map_while(|c| char::from_u32(*c as u32)?.to_digit(10))
+        .fold(0u16, |value, digit| {
+            value.wrapping_mul(10).wrapping_add(digit as u16)
+        });
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "new" => |vm_emulator, args| {
+            let max_length = args[0];
+            if (max_length as i16) < 0 {
+                // 14: 最大の長さが負
+                return error(vm_emulator, 14);
+            }
+            let this = vm_emulator.call_function("Memory.alloc", &[3])?;
+            // 空の文字列には文字の配列を確保しない
+            let chars = match max_length {
+                0 => 0,
+                _ => vm_emulator.call_function("Array.new", &[max_length])?,
+            };
+            set_field(vm_emulator, this, CHARS, chars);
+            set_field(vm_emulator, this, MAX_LENGTH, max_length);
+            set_field(vm_emulator, this, LENGTH, 0);
+            Ok(Return(this))
+        },
+        "dispose" => |vm_emulator, args| {
+            let chars = field(vm_emulator, args[0], CHARS);
+            if chars != 0 {
+                vm_emulator.call_function("Array.dispose", &[chars])?;
+            }
+            vm_emulator.call_function("Memory.deAlloc", &[args[0]])?;
+            Ok(Return(0))
+        },
+        "length" => |vm_emulator, args| Ok(Return(field(vm_emulator, args[0], LENGTH))),
+        "charAt" => |vm_emulator, args| {
+            let (this, index) = (args[0], args[1]);
+            if index >= field(vm_emulator, this, LENGTH) {
+                // 15: 範囲外の位置
+                return error(vm_emulator, 15);
+            }
+            let chars = field(vm_emulator, this, CHARS);
+            Ok(Return(vm_emulator.ram(chars.wrapping_add(index))))
+        },
+        "setCharAt" => |vm_emulator, args| {
+            let (this, index, c) = (args[0], args[1], args[2]);
+            if index >= field(vm_emulator, this, LENGTH) {
+                // 16: 範囲外の位置
+                return error(vm_emulator, 16);
+            }
+            let chars = field(vm_emulator, this, CHARS);
+            vm_emulator.set_ram(chars.wrapping_add(index), c);
+            Ok(Return(0))
+        },
+        "appendChar" => |vm_emulator, args| {
+            let (this, c) = (args[0], args[1]);
+            let length = field(vm_emulator, this, LENGTH);
+            if length >= field(vm_emulator, this, MAX_LENGTH) {
+                // 17: 文字列がいっぱい
+                return error(vm_emulator, 17);
+            }
+            let chars = field(vm_emulator, this, CHARS);
+            vm_emulator.set_ram(chars.wrapping_add(length), c);
+            set_field(vm_emulator, this, LENGTH, length + 1);
+            Ok(Return(this))
+        },
+        "eraseLastChar" => |vm_emulator, args| {
+            let length = field(vm_emulator, args[0], LENGTH);
+            if length == 0 {
+                // 18: 文字列が空
+                return error(vm_emulator, 18);
+            }
+            set_field(vm_emulator, args[0], LENGTH, length - 1);
+            Ok(Return(0))
+        },
+        "intValue" => |vm_emulator, args| Ok(Return(int_value(vm_emulator, args[0]))),
+        "setInt" => |vm_emulator, args| {
+            let (this, value) = (args[0], args[1] as i16);
+            let text = value.to_string();
+            if text.len() as u16 > field(vm_emulator, this, MAX_LENGTH) {
+                // 19: 文字列に数が収まらない
+                return error(vm_emulator, 19);
+            }
+            let chars = field(vm_emulator, this, CHARS);
+            for (index, c) in text.bytes().enumerate() {
+                vm_emulator.set_ram(chars.wrapping_add(index as u16), c as u16);
+            }
+            set_field(vm_emulator, this, LENGTH, text.len() as u16);
+            Ok(Return(0))
+        },
+        "newLine" => |_, _| Ok(Return(128)),
+        "backSpace" => |_, _| Ok(Return(129)),
+        "doubleQuote" => |_, _| Ok(Return(b'"' as u16)),
+        _ => return None,
+    };
+    Some(native)
+}
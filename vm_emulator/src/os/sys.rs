@@ -0,0 +1,26 @@
+use super::{output::print_str, NativeFunction, NativeResult::Return};
+
+pub(super) fn native(function_name: &str) -> Option<NativeFunction> {
+    let native: NativeFunction = match function_name {
+        "halt" => |vm_emulator, _| {
+            vm_emulator.halt();
+            Ok(Return(0))
+        },
+        // JackOS/Sys.jack と同じく 'Err<コード>' を表示して停止する
+        "error" => |vm_emulator, args| {
+            print_str(vm_emulator, "Err<")?;
+            vm_emulator.call_function("Output.printInt", &[args[0]])?;
+            print_str(vm_emulator, ">")?;
+            vm_emulator.call_function("Sys.halt", &[])?;
+            Ok(Return(0))
+        },
+        // VM の実行には実時間が無いので待たずに戻る
+        "wait" => |vm_emulator, args| match args[0] as i16 {
+            // 1: 待つ時間が負
+            duration if duration < 0 => super::error(vm_emulator, 1),
+            _ => Ok(Return(0)),
+        },
+        _ => return None,
+    };
+    Some(native)
+}
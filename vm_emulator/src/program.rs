@@ -0,0 +1,328 @@
+//! VM コマンド列を実行しやすい形に変換する
+//!
+//! ラベルは関数ごとに、スタティック変数は 'モジュール名.番号' ごとに RAM アドレスへ解決する。
+//! スタティック変数は vm_translator と assembler を通した場合と同じく、出現順に RAM[16] から割り当てる。
+
+use crate::os::{self, NativeFunction};
+use schema::vm;
+use std::collections::{HashMap, HashSet};
+
+// スタティック変数を割り当てる RAM の範囲
+const STATIC_START_RAM_ADDRESS: u16 = 16;
+const STATIC_END_RAM_ADDRESS: u16 = 256;
+
+// 1つの .vm ファイル（ファイル名をモジュール名とする）
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub commands: Vec<vm::Command>,
+}
+
+impl Module {
+    pub fn parse(name: &str, source: String) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            commands: vm::parse(source).map_err(|e| anyhow::anyhow!("{name}.vm: {e}"))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Operand {
+    Constant(u16),
+    // 解決済みのスタティック変数の RAM アドレス
+    Static(u16),
+    Segment(vm::Segment, u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Instruction {
+    Arithmetic(vm::ArithmeticCommand),
+    Push(Operand),
+    Pop(Operand),
+    Function { local_variable_count: u16 },
+    // 呼び出す関数の番号 (Program::functions)
+    Call { function: usize, args_count: u16 },
+    Return,
+    // ラベルは直後の命令の位置に解決する
+    Goto(usize),
+    IfGoto(usize),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FunctionEntry {
+    pub(crate) name: String,
+    // 'function' 命令の位置（定義されていなければ None）
+    pub(crate) address: Option<usize>,
+    // VM コードで定義されていないときに使う OS の実装
+    pub(crate) native: Option<NativeFunction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub(crate) instructions: Vec<Instruction>,
+    // 各命令の元のコマンドと、それを含む関数（関数の外であればモジュール）の名前
+    pub(crate) sources: Vec<(vm::Command, String)>,
+    pub(crate) functions: Vec<FunctionEntry>,
+    pub(crate) function_ids: HashMap<String, usize>,
+    // 実行を始める位置
+    pub(crate) entry: usize,
+    // OS の実装を使うか
+    os: bool,
+    // 関数を VM コードで定義しているクラス
+    classes: HashSet<String>,
+}
+
+impl Program {
+    pub fn new(modules: &[Module]) -> anyhow::Result<Self> {
+        let mut functions: Vec<FunctionEntry> = Vec::new();
+        let mut function_ids: HashMap<String, usize> = HashMap::new();
+        let mut function_id = |name: &str, functions: &mut Vec<FunctionEntry>| {
+            *function_ids.entry(name.to_string()).or_insert_with(|| {
+                functions.push(FunctionEntry {
+                    name: name.to_string(),
+                    address: None,
+                    native: None,
+                });
+                functions.len() - 1
+            })
+        };
+
+        // ラベルの位置（ラベル、goto の解決は全ての命令を並べてから行う）
+        let mut labels: HashMap<(String, String), usize> = HashMap::new();
+        let mut statics: HashMap<String, u16> = HashMap::new();
+        let mut sources = Vec::new();
+        let mut instructions = Vec::new();
+        for module in modules {
+            let mut scope = module.name.clone();
+            for command in &module.commands {
+                if let vm::Command::Function { name, .. } = command {
+                    scope = name.get_string();
+                    let id = function_id(name.get(), &mut functions);
+                    if functions[id].address.replace(instructions.len()).is_some() {
+                        anyhow::bail!(
+                            "{}.vm: function {} is defined twice",
+                            module.name,
+                            name.get()
+                        );
+                    }
+                }
+                let instruction = match command {
+                    vm::Command::Label(label) => {
+                        labels.insert((scope.clone(), label.get_string()), instructions.len());
+                        continue;
+                    }
+                    vm::Command::Arithmetic(command) => Instruction::Arithmetic(command.clone()),
+                    vm::Command::MemoryAccess(command) => {
+                        let index = command.index.get();
+                        let operand = match command.segment {
+                            vm::Segment::Constant => Operand::Constant(index),
+                            vm::Segment::Static => {
+                                let next = STATIC_START_RAM_ADDRESS + statics.len() as u16;
+                                let address = *statics
+                                    .entry(format!("{}.{index}", module.name))
+                                    .or_insert(next);
+                                if address >= STATIC_END_RAM_ADDRESS {
+                                    anyhow::bail!(
+                                        "{}.vm: too many static variables (RAM[{STATIC_START_RAM_ADDRESS}..{STATIC_END_RAM_ADDRESS}])",
+                                        module.name
+                                    );
+                                }
+                                Operand::Static(address)
+                            }
+                            ref segment => Operand::Segment(segment.clone(), index),
+                        };
+                        match command.access_type {
+                            vm::AccessType::Push => Instruction::Push(operand),
+                            vm::AccessType::Pop => match operand {
+                                Operand::Constant(_) => anyhow::bail!(
+                                    "{}.vm: cannot pop to constant segment",
+                                    module.name
+                                ),
+                                operand => Instruction::Pop(operand),
+                            },
+                        }
+                    }
+                    vm::Command::Function {
+                        local_variable_count,
+                        ..
+                    } => Instruction::Function {
+                        local_variable_count: *local_variable_count,
+                    },
+                    vm::Command::Call { name, args_count } => Instruction::Call {
+                        function: function_id(name.get(), &mut functions),
+                        args_count: *args_count,
+                    },
+                    vm::Command::Return => Instruction::Return,
+                    // 解決前はラベルの番号を仮に入れておく
+                    vm::Command::Goto(_) => Instruction::Goto(usize::MAX),
+                    vm::Command::IfGoto(_) => Instruction::IfGoto(usize::MAX),
+                };
+                instructions.push(instruction);
+                sources.push((command.clone(), scope.clone()));
+            }
+        }
+
+        for (instruction, (command, scope)) in instructions.iter_mut().zip(&sources) {
+            if let (
+                Instruction::Goto(target) | Instruction::IfGoto(target),
+                vm::Command::Goto(label) | vm::Command::IfGoto(label),
+            ) = (instruction, command)
+            {
+                *target = *labels
+                    .get(&(scope.clone(), label.get_string()))
+                    .ok_or_else(|| anyhow::anyhow!("{scope}: undefined label {}", label.get()))?;
+            }
+        }
+
+        let classes = functions
+            .iter()
+            .filter(|function| function.address.is_some())
+            .filter_map(|function| Some(function.name.split_once('.')?.0.to_string()))
+            .collect();
+        Ok(Self {
+            entry: function_ids
+                .get("Sys.init")
+                .and_then(|id| functions[*id].address)
+                .unwrap_or(0),
+            instructions,
+            sources,
+            functions,
+            function_ids,
+            os: false,
+            classes,
+        })
+    }
+
+    // VM コードで定義されていない OS のクラスの関数は Rust の実装 (os) で実行する
+    // Sys.init が無く Main.main があれば、Sys.init と同じく OS を初期化して Main.main を呼ぶ
+    pub fn with_os(modules: &[Module]) -> anyhow::Result<Self> {
+        let defines = |function_name: &str| {
+            modules
+                .iter()
+                .flat_map(|module| &module.commands)
+                .any(|command| matches!(command, vm::Command::Function { name, .. } if name.get() == function_name))
+        };
+        let mut program = if !defines("Sys.init") && defines("Main.main") {
+            let bootstrap = os::bootstrap_module();
+            let mut modules = modules.to_vec();
+            modules.push(bootstrap.clone());
+            let mut program = Self::new(&modules)?;
+            program.entry = program
+                .sources
+                .iter()
+                .position(|(_, scope)| *scope == bootstrap.name)
+                .unwrap_or(0);
+            program
+        } else {
+            Self::new(modules)?
+        };
+        program.os = true;
+        let natives = program
+            .functions
+            .iter()
+            .map(|function| program.native(&function.name))
+            .collect::<Vec<_>>();
+        for (function, native) in program.functions.iter_mut().zip(natives) {
+            function.native = native;
+        }
+        Ok(program)
+    }
+
+    // 関数の OS の実装（そのクラスを VM コードで定義していれば None）
+    pub(crate) fn native(&self, name: &str) -> Option<NativeFunction> {
+        let (class_name, _) = name.split_once('.')?;
+        if !self.os || self.classes.contains(class_name) {
+            return None;
+        }
+        os::native(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    // 関数の 'function' 命令の位置
+    pub fn function_address(&self, name: &str) -> Option<usize> {
+        self.function_ids
+            .get(name)
+            .and_then(|id| self.functions[*id].address)
+    }
+
+    // 指定した位置の元のコマンド
+    pub fn command(&self, address: usize) -> Option<&vm::Command> {
+        self.sources.get(address).map(|(command, _)| command)
+    }
+
+    // 指定した位置を '関数名+関数の先頭からの位置' で表す
+    pub fn location(&self, address: usize) -> String {
+        let Some((_, scope)) = self.sources.get(address) else {
+            return format!("<end of program ({address})>");
+        };
+        // 関数の外のコマンドはモジュール内の位置で表す
+        let start = self.function_address(scope).unwrap_or_else(|| {
+            self.sources[..address]
+                .iter()
+                .rposition(|(_, other)| other != scope)
+                .map_or(0, |position| position + 1)
+        });
+        format!("{scope}+{}", address - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let main = Module::parse(
+            "Main",
+            [
+                "function Main.main 0",
+                "label LOOP",
+                "push static 1",
+                "pop static 0",
+                "goto LOOP",
+                "function Main.sub 0",
+                "label LOOP",
+                "call Main.main 0",
+                "if-goto LOOP",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+        let sys = Module::parse("Sys", "function Sys.init 0\npush static 0".to_string()).unwrap();
+        let program = Program::new(&[main, sys]).unwrap();
+
+        // ラベルは関数ごと、スタティック変数はモジュールごとに出現順で割り当てる
+        assert_eq!(program.instructions[3], Instruction::Goto(1));
+        assert_eq!(program.instructions[6], Instruction::IfGoto(5));
+        assert_eq!(
+            program.instructions[1],
+            Instruction::Push(Operand::Static(16))
+        );
+        assert_eq!(
+            program.instructions[2],
+            Instruction::Pop(Operand::Static(17))
+        );
+        assert_eq!(
+            program.instructions[8],
+            Instruction::Push(Operand::Static(18))
+        );
+        assert_eq!(program.function_address("Sys.init"), Some(7));
+        assert_eq!(program.location(6), "Main.sub+2");
+        assert_eq!(program.command(6).unwrap().to_string(), "if-goto LOOP");
+
+        let undefined =
+            Module::parse("Main", "function Main.main 0\ngoto END".to_string()).unwrap();
+        assert_eq!(
+            Program::new(&[undefined]).unwrap_err().to_string(),
+            "Main.main: undefined label END"
+        );
+    }
+}
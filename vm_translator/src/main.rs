@@ -1,11 +1,13 @@
 use core::panic;
 use schema::vm;
-use semantics::{bootstrap_code, genarate_assembler_code, AssemblerCodeBlock, Module};
 use std::path::{Path, PathBuf};
+use vm_translator::semantics::{
+    bootstrap_code, eliminate_dead_functions, genarate_assembler_code, optimize_code_blocks,
+    AssemblerCodeBlock, Bootstrap, Module,
+};
 
-mod semantics;
-
-fn construct_assembler_code_blocks(input_path: impl AsRef<Path>) -> Vec<AssemblerCodeBlock> {
+// optimize が真であれば、意味解析の前に VM コマンドを最適化する
+fn construct_module(input_path: impl AsRef<Path>, optimize: bool) -> Module {
     let input = std::fs::read_to_string(input_path.as_ref()).unwrap();
 
     // ファイル名をモジュール名とする
@@ -13,35 +15,154 @@ fn construct_assembler_code_blocks(input_path: impl AsRef<Path>) -> Vec<Assemble
 
     // 構文解析
     let vm_commands: Vec<vm::Command> = vm::parse(input).unwrap();
+    let vm_commands = if optimize {
+        let optimized = vm::optimizer::optimize(&vm_commands);
+        println!("{}: {}", input_path.as_ref().display(), optimized.report);
+        optimized.commands
+    } else {
+        vm_commands
+    };
 
     // 意味解析（コード生成処理のアルゴリズムが使いやすい形にしておく）
-    let module = Module::try_from_commands(module_name, vm_commands).unwrap();
+    Module::try_from_commands(module_name, vm_commands).unwrap()
+}
 
+// compact が真であれば、call, return, 比較演算子を共通ルーチンへのジャンプにする
+fn construct_assembler_code_blocks(
+    input_path: impl AsRef<Path>,
+    optimize: bool,
+    compact: bool,
+) -> Vec<AssemblerCodeBlock> {
     // アセンブラコード塊への変換
-    module.into_code_blocks()
+    construct_module(input_path, optimize).into_code_blocks(compact)
+}
+
+// --optimize が指定されていれば最適化し、削減した命令数を表示して .asm を出力する
+fn write_assembler_code(output_path: &Path, blocks: Vec<AssemblerCodeBlock>, optimize: bool) {
+    let blocks = if optimize {
+        let (blocks, report) = optimize_code_blocks(blocks);
+        println!("{}: {report}", output_path.display());
+        blocks
+    } else {
+        blocks
+    };
+    std::fs::write(output_path, genarate_assembler_code(blocks)).unwrap();
+}
+
+// ディレクトリ内の .vm ファイル
+fn vm_files(directory_path: &Path) -> Vec<PathBuf> {
+    let input_files: Vec<PathBuf> = std::fs::read_dir(directory_path)
+        .unwrap()
+        .map(|p| p.unwrap().path())
+        .filter(|p| p.is_file())
+        .filter(|p| p.extension() == Some(std::ffi::OsStr::new("vm")))
+        .collect();
+    // .vm ファイルが見つからなければエラーにする
+    if input_files.is_empty() {
+        panic!(".vm files could not be found in the input path");
+    }
+    input_files
+}
+
+// オプションの直後の引数
+fn option_value<'a>(args: &'a [String], option: &str) -> Option<&'a str> {
+    let position = args.iter().skip(2).position(|arg| arg == option)? + 2;
+    let value = args
+        .get(position + 1)
+        .unwrap_or_else(|| panic!("{option} requires a value"));
+    Some(value.as_str())
+}
+
+// --bootstrap <none|jump|call> と --sp <n> からブートストラップコードの種類を決める
+fn bootstrap_option(args: &[String]) -> Bootstrap {
+    let sp: Option<u16> = option_value(args, "--sp").map(|value| {
+        let sp: u16 = value
+            .parse()
+            .unwrap_or_else(|_| panic!("--sp requires a number: {value}"));
+        // A命令で読み込める 15 ビットの値に限る
+        if sp > 0x7fff {
+            panic!("--sp must be at most 32767: {value}");
+        }
+        sp
+    });
+    match (option_value(args, "--bootstrap"), sp) {
+        (None | Some("jump"), None) => Bootstrap::default(),
+        (None | Some("jump"), Some(sp)) => Bootstrap::Jump { sp },
+        (Some("call"), sp) => Bootstrap::Call {
+            sp: sp.unwrap_or(256),
+        },
+        (Some("none"), None) => Bootstrap::None,
+        (Some("none"), Some(_)) => panic!("--sp cannot be used with --bootstrap none."),
+        (Some(other), _) => panic!("unknown bootstrap: {other} (none, jump or call)"),
+    }
 }
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     let input_arg_path: &Path = Path::new(args.get(1).unwrap());
+    // --separate: ディレクトリ内の .vm ファイルごとに .asm を出力する
+    // (ブートストラップコードは Bootstrap.asm に出力し、linker で結合する)
+    let separate = args.iter().skip(2).any(|arg| arg == "--separate");
+    // --optimize: VM コマンドとアセンブラコードの覗き穴最適化で冗長な命令を取り除く
+    let optimize = args.iter().skip(2).any(|arg| arg == "--optimize");
+    // --compact: call, return, eq, gt, lt の命令列を共通ルーチンにまとめ、各箇所からはジャンプする
+    let compact = args.iter().skip(2).any(|arg| arg == "--compact");
+    // --eliminate-dead-functions: Sys.init から call で到達できない関数を出力しない
+    let eliminate_dead = args
+        .iter()
+        .skip(2)
+        .any(|arg| arg == "--eliminate-dead-functions");
+    // --bootstrap <none|jump|call>: ブートストラップコードを出力しない、Sys.init へ直接ジャンプする (既定)、
+    // call Sys.init 0 で呼び出す
+    // --sp <n>: ブートストラップコードで設定する SP の初期値 (既定は 256)
+    let bootstrap = bootstrap_option(&args);
+    if compact && bootstrap == Bootstrap::None {
+        panic!("--compact requires a bootstrap code to place the shared routines.");
+    }
+    if eliminate_dead && (separate || !input_arg_path.is_dir()) {
+        panic!("--eliminate-dead-functions requires a directory path without --separate.");
+    }
+
+    if separate {
+        if !input_arg_path.is_dir() {
+            panic!("--separate requires a directory path.");
+        }
+        for input_path in vm_files(input_arg_path) {
+            let output_path = input_path.with_extension("asm");
+            let assembler_code_blocks =
+                construct_assembler_code_blocks(&input_path, optimize, compact);
+            write_assembler_code(&output_path, assembler_code_blocks, optimize);
+        }
+        if bootstrap != Bootstrap::None {
+            write_assembler_code(
+                &input_arg_path.join("Bootstrap.asm"),
+                bootstrap_code(bootstrap, compact),
+                optimize,
+            );
+        }
+        return;
+    }
 
     let (output_path, assembler_code_blocks) = if input_arg_path.is_dir() {
-        let input_files: Vec<PathBuf> = std::fs::read_dir(input_arg_path)
-            .unwrap()
+        let input_files = vm_files(input_arg_path);
+
+        let modules: Vec<Module> = input_files
             .into_iter()
-            .map(|p| p.unwrap().path())
-            .filter(|p| p.is_file())
-            .filter(|p| p.extension().unwrap() == std::ffi::OsStr::new("vm"))
+            .map(|input_path| construct_module(input_path, optimize))
             .collect();
-        // .vm ファイルが見つからなければエラーにする
-        if input_files.is_empty() {
-            panic!(".vm files could not be found in the input path");
-        }
+        // 全モジュールが揃ってから呼び出しグラフをたどる
+        let modules = if eliminate_dead {
+            let (modules, report) = eliminate_dead_functions(modules, compact).unwrap();
+            println!("{}: {report}", input_arg_path.display());
+            modules
+        } else {
+            modules
+        };
 
-        let assembler_code_blocks = input_files
+        let assembler_code_blocks = modules
             .into_iter()
-            .flat_map(construct_assembler_code_blocks)
+            .flat_map(|module| module.into_code_blocks(compact))
             .collect();
 
         // vm言語から生成されたアセンブリ言語を出力するパス
@@ -55,7 +176,8 @@ fn main() {
         if input_arg_path.extension().unwrap() != std::ffi::OsStr::new("vm") {
             panic!("input file has to be .vm file");
         }
-        let assembler_code_blocks = construct_assembler_code_blocks(input_arg_path);
+        let assembler_code_blocks =
+            construct_assembler_code_blocks(input_arg_path, optimize, compact);
 
         // vm言語から生成されたアセンブリ言語を出力するパス
         let output_path: PathBuf = {
@@ -72,12 +194,42 @@ fn main() {
         panic!("First argument has to be file path or directory path.")
     };
 
-    let assembler_code: String = genarate_assembler_code(
-        bootstrap_code()
+    write_assembler_code(
+        &output_path,
+        bootstrap_code(bootstrap, compact)
             .into_iter()
-            .chain(assembler_code_blocks.into_iter())
+            .chain(assembler_code_blocks)
             .collect(),
+        optimize,
     );
+}
+
+#[test]
+fn test_bootstrap_option() {
+    let args = |options: &[&str]| -> Vec<String> {
+        ["vm_translator", "Prog"]
+            .iter()
+            .chain(options)
+            .map(|arg| arg.to_string())
+            .collect()
+    };
+    assert_eq!(bootstrap_option(&args(&[])), Bootstrap::Jump { sp: 256 });
+    assert_eq!(
+        bootstrap_option(&args(&["--bootstrap", "call", "--sp", "300"])),
+        Bootstrap::Call { sp: 300 }
+    );
+    assert_eq!(
+        bootstrap_option(&args(&["--sp", "32767"])),
+        Bootstrap::Jump { sp: 32767 }
+    );
+    assert_eq!(
+        bootstrap_option(&args(&["--bootstrap", "none"])),
+        Bootstrap::None
+    );
+}
 
-    std::fs::write(output_path, assembler_code).unwrap();
+#[test]
+#[should_panic(expected = "--sp must be at most 32767")]
+fn test_bootstrap_option_rejects_large_sp() {
+    bootstrap_option(&["vm_translator", "Prog", "--sp", "32768"].map(String::from));
 }
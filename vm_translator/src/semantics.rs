@@ -1,13 +1,18 @@
+mod dead_function;
 mod from_schema;
 mod to_assembler;
 
-pub(crate) use to_assembler::assembler_code::genarate_assembler_code;
-pub(crate) use to_assembler::assembler_code::AssemblerCodeBlock;
-pub(crate) use to_assembler::bootstrap_code;
+pub use dead_function::{eliminate_dead_functions, DeadFunctionReport};
+pub use to_assembler::assembler_code::count_rom_words;
+pub use to_assembler::assembler_code::genarate_assembler_code;
+pub use to_assembler::assembler_code::optimize_code_blocks;
+pub use to_assembler::assembler_code::AssemblerCodeBlock;
+pub use to_assembler::{bootstrap_code, Bootstrap};
 
 // ファイルはモジュールと仮定する
 pub struct Module {
     name: String,
+    commands: Vec<Command>, // 最初の function より前のコマンド (7章のテスト用)
     functions: Vec<Function>,
 }
 
@@ -0,0 +1,138 @@
+use super::{count_rom_words, Command, Function, Module};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// ブートストラップコードから呼ばれる関数
+const ENTRY_FUNCTION_NAME: &str = "Sys.init";
+
+// 取り除いた関数と、それによって削減した ROM の命令数
+pub struct DeadFunctionReport {
+    pub removed: Vec<String>,
+    pub saved_words: usize,
+}
+
+impl fmt::Display for DeadFunctionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} unreachable functions (saved {} rom words)",
+            self.removed.len(),
+            self.saved_words
+        )?;
+        for name in &self.removed {
+            write!(f, "\n  {name}")?;
+        }
+        Ok(())
+    }
+}
+
+// 全モジュールの call から呼び出しグラフを作り、Sys.init から到達できない関数を取り除く
+// (削減した命令数は compact に応じたコード生成をして数える)
+pub fn eliminate_dead_functions(
+    modules: Vec<Module>,
+    compact: bool,
+) -> anyhow::Result<(Vec<Module>, DeadFunctionReport)> {
+    let reachable = reachable_functions(&modules, ENTRY_FUNCTION_NAME)?;
+    let mut report = DeadFunctionReport {
+        removed: Vec::new(),
+        saved_words: 0,
+    };
+    let modules = modules
+        .into_iter()
+        .map(|module| {
+            let (functions, removed): (Vec<Function>, Vec<Function>) = module
+                .functions
+                .into_iter()
+                .partition(|function| reachable.contains(&function.name));
+            report
+                .removed
+                .extend(removed.iter().map(|function| function.name.clone()));
+            let removed_module = Module {
+                name: module.name.clone(),
+                commands: Vec::new(),
+                functions: removed,
+            };
+            report.saved_words += count_rom_words(&removed_module.into_code_blocks(compact));
+            Module {
+                name: module.name,
+                commands: module.commands,
+                functions,
+            }
+        })
+        .collect();
+    Ok((modules, report))
+}
+
+// entry と関数の外のコマンドから call をたどって到達できる関数名
+// (どのモジュールにも定義されていない関数の呼び出しはそのまま含める)
+fn reachable_functions(modules: &[Module], entry: &str) -> anyhow::Result<HashSet<String>> {
+    let call_graph: HashMap<&str, Vec<&str>> = modules
+        .iter()
+        .flat_map(|module| &module.functions)
+        .map(|function| (function.name.as_str(), called_functions(&function.commands)))
+        .collect();
+    let mut stack: Vec<&str> = modules
+        .iter()
+        .flat_map(|module| called_functions(&module.commands))
+        .collect();
+    if call_graph.contains_key(entry) {
+        stack.push(entry);
+    } else if modules.iter().all(|module| module.commands.is_empty()) {
+        anyhow::bail!("entry function '{entry}' could not be found");
+    }
+
+    let mut reachable: HashSet<String> = HashSet::new();
+    while let Some(name) = stack.pop() {
+        if !reachable.insert(name.to_string()) {
+            continue;
+        }
+        stack.extend(call_graph.get(name).into_iter().flatten().copied());
+    }
+    Ok(reachable)
+}
+
+fn called_functions(commands: &[Command]) -> Vec<&str> {
+    commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Call { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn parse_module(name: &str, source: &str) -> Module {
+    Module::try_from_commands(name, schema::vm::parse(source.to_string()).unwrap()).unwrap()
+}
+
+#[test]
+fn test_eliminate_dead_functions() {
+    let modules = vec![
+        parse_module(
+            "Sys",
+            "function Sys.init 0\ncall Main.main 0\nreturn\nfunction Sys.unused 0\ncall Main.helper 0\nreturn\n",
+        ),
+        parse_module(
+            "Main",
+            "function Main.main 0\ncall Main.run 0\nreturn\nfunction Main.run 0\nreturn\nfunction Main.helper 0\nreturn\n",
+        ),
+    ];
+    let (modules, report) = eliminate_dead_functions(modules, true).unwrap();
+    let names: Vec<&str> = modules
+        .iter()
+        .flat_map(|module| &module.functions)
+        .map(|function| function.name.as_str())
+        .collect();
+    // Main.run は Main.main を経由して到達でき、Main.helper は取り除いた Sys.unused からしか呼ばれない
+    assert_eq!(names, ["Sys.init", "Main.main", "Main.run"]);
+    assert_eq!(report.removed, ["Sys.unused", "Main.helper"]);
+    // --compact の return は '@VM$RETURN', '0;JMP' の 2 命令、call は 12 命令
+    assert_eq!(report.saved_words, (12 + 2) + 2);
+}
+
+#[test]
+fn test_eliminate_dead_functions_without_entry() {
+    let modules = vec![parse_module("Main", "function Main.main 0\nreturn\n")];
+    assert!(eliminate_dead_functions(modules, false).is_err());
+}
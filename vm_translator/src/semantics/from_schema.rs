@@ -2,13 +2,22 @@ use super::*;
 use schema::vm;
 
 impl Module {
-    pub(crate) fn try_from_commands(
+    pub fn try_from_commands(
         module_name: &str,
-        vm_commands: Vec<vm::Command>,
+        mut vm_commands: Vec<vm::Command>,
     ) -> anyhow::Result<Self> {
+        let first_function = vm_commands
+            .iter()
+            .position(|vm_command| matches!(vm_command, vm::Command::Function { .. }))
+            .unwrap_or(vm_commands.len());
+        let function_vm_commands = vm_commands.split_off(first_function);
         Ok(Self {
             name: module_name.to_string(),
-            functions: Function::try_from_commands(vm_commands)?,
+            commands: vm_commands
+                .into_iter()
+                .map(Command::try_from_command)
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            functions: Function::try_from_commands(function_vm_commands)?,
         })
     }
 }
@@ -22,9 +31,13 @@ impl Function {
             .into_iter()
             .map(|commands| {
                 let Some((
-                    vm::Command::Function { name, local_variable_count },
-                    rest_commands
-                )) = commands.split_first() else {
+                    vm::Command::Function {
+                        name,
+                        local_variable_count,
+                    },
+                    rest_commands,
+                )) = commands.split_first()
+                else {
                     anyhow::bail!("all commands should be written in function!");
                 };
                 Ok(Self {
@@ -196,3 +209,15 @@ fn test_separete() {
         vec![vec![1, 2], vec![3, 4, 5], vec![6, 7],]
     )
 }
+
+#[test]
+fn test_commands_outside_function() {
+    let vm_commands = vm::parse(
+        "push constant 7\npush constant 8\nadd\nfunction Main.main 0\nreturn\n".to_string(),
+    )
+    .unwrap();
+    let module = Module::try_from_commands("Main", vm_commands).unwrap();
+    assert_eq!(module.commands.len(), 3);
+    assert_eq!(module.functions.len(), 1);
+    assert_eq!(module.functions[0].commands, vec![Command::Return]);
+}
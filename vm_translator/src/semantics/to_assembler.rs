@@ -11,17 +11,95 @@ use schema::hack;
 
 pub(super) mod assembler_code;
 
-pub(crate) fn bootstrap_code() -> Vec<AssemblerCodeBlock> {
-    use memory_access::load_constant_to_d;
+// Bootstrap::Call で Sys.init から戻った後の無限ループのラベル
+const HALT_LABEL: &str = "VM$HALT";
+
+// ブートストラップコードの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bootstrap {
+    // ブートストラップコードを出力しない (7章の単一ファイルのテスト用)
+    None,
+    // SP を設定し、Sys.init へ直接ジャンプする
+    Jump { sp: u16 },
+    // SP を設定し、call Sys.init 0 で呼び出しフレームを積んでから Sys.init へジャンプする
+    // (8章の FibonacciElement 及び StaticsTest は SP=256 でこちらにした時の SP=261 を期待している)
+    Call { sp: u16 },
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self::Jump { sp: 256 }
+    }
+}
+
+// compact が真であれば、Sys.init の呼び出しの後に call, return, 比較演算子の共通ルーチンを置く
+// (Bootstrap::None では共通ルーチンも出力しない)
+pub fn bootstrap_code(bootstrap: Bootstrap, compact: bool) -> Vec<AssemblerCodeBlock> {
+    let bootstrap = match bootstrap {
+        Bootstrap::None => return Vec::new(),
+        Bootstrap::Jump { sp } => [
+            initialize_sp(sp),
+            vec![AssemblerCodeBlock::new(
+                "call Sys.init (simply Jump to 'Sys.init symbol')",
+                &[
+                    hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("Sys.init"))),
+                    hack::Command::C(hack::CCommand {
+                        dest: None,
+                        comp: hack::CompMnemonic::Zero,
+                        jump: Some(hack::JumpMnemonic::JMP),
+                    }),
+                ],
+            )],
+        ]
+        .concat(),
+        Bootstrap::Call { sp } => {
+            let call = if compact {
+                function_call::construct_compact
+            } else {
+                function_call::construct
+            };
+            [
+                initialize_sp(sp),
+                call("Sys.init".to_string(), 0, "Bootstrap", "bootstrap", &mut 0),
+                vec![halt()],
+            ]
+            .concat()
+        }
+    };
+    if !compact {
+        return bootstrap;
+    }
+    [
+        bootstrap,
+        function_call::routine(),
+        function_return::routine(),
+        arithmetic::comparison_routines(),
+    ]
+    .concat()
+}
+
+// Sys.init から戻った時に後ろの共通ルーチンや関数へ進まないよう、ここで止まる
+fn halt() -> AssemblerCodeBlock {
+    AssemblerCodeBlock::new(
+        "halt after returning from Sys.init",
+        &[
+            hack::Command::L(hack::Symbol::new(HALT_LABEL)),
+            hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new(HALT_LABEL))),
+            hack::Command::C(hack::CCommand {
+                dest: None,
+                comp: hack::CompMnemonic::Zero,
+                jump: Some(hack::JumpMnemonic::JMP),
+            }),
+        ],
+    )
+}
+
+fn initialize_sp(sp: u16) -> Vec<AssemblerCodeBlock> {
     vec![
         AssemblerCodeBlock::new_header_comment("bootstrap"),
-        load_constant_to_d(256),
-        // load_constant_to_d(261), // FibonatchElement 及び StaticTest をパスするためにはこちらにする
-        // 8章の.tst ファイルに一部間違いがある気がする。
-        // VMEmulatorを動作させた結果が、テストの.cmpと一致しない。
-        // 現時点でのコードはひとまずVMEmulatorと同じ挙動をしているのでテストOKとする
+        memory_access::load_constant_to_d(sp),
         AssemblerCodeBlock::new(
-            "write 256 to SP",
+            &format!("write {sp} to SP"),
             &[
                 hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("SP"))),
                 hack::Command::C(hack::CCommand {
@@ -31,31 +109,37 @@ pub(crate) fn bootstrap_code() -> Vec<AssemblerCodeBlock> {
                 }),
             ],
         ),
-        AssemblerCodeBlock::new(
-            "call Sys.init (simply Jump to 'Sys.init symbol')",
-            &[
-                hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("Sys.init"))),
-                hack::Command::C(hack::CCommand {
-                    dest: None,
-                    comp: hack::CompMnemonic::Zero,
-                    jump: Some(hack::JumpMnemonic::JMP),
-                }),
-            ],
-        ),
     ]
 }
 
 impl Module {
-    pub(crate) fn into_code_blocks(self) -> Vec<AssemblerCodeBlock> {
-        self.functions
+    // compact が真であれば、call, return, 比較演算子を共通ルーチンへのジャンプにする
+    // 関数の外のコマンドは、モジュール名を関数名として関数の前に置く
+    pub fn into_code_blocks(self, compact: bool) -> Vec<AssemblerCodeBlock> {
+        let mut comp_operator_counter: u32 = 0;
+        let mut return_command_counter: u32 = 0;
+        self.commands
             .into_iter()
-            .flat_map(|f| f.into_code_blocks(&self.name))
+            .flat_map(|command| {
+                command.into_code_blocks(
+                    &self.name,
+                    &self.name,
+                    &mut comp_operator_counter,
+                    &mut return_command_counter,
+                    compact,
+                )
+            })
+            .chain(
+                self.functions
+                    .into_iter()
+                    .flat_map(|f| f.into_code_blocks(&self.name, compact)),
+            )
             .collect()
     }
 }
 
 impl Function {
-    fn into_code_blocks(self, module_name: &str) -> Vec<AssemblerCodeBlock> {
+    fn into_code_blocks(self, module_name: &str, compact: bool) -> Vec<AssemblerCodeBlock> {
         let mut comp_operator_counter: u32 = 0;
         let mut return_command_counter: u32 = 0;
         [
@@ -72,17 +156,13 @@ impl Function {
         .into_iter()
         .chain(
             // ローカル変数の初期化
-            (0..self.local_variable_count as usize)
-                .into_iter()
-                .flat_map(|i| {
-                    [
-                        AssemblerCodeBlock::new_comment(&format!(
-                            "initialize local variable ({i})"
-                        )),
-                        memory_access::load_constant_to_d(0),
-                        memory_access::write_d_to_stack(),
-                    ]
-                }),
+            (0..self.local_variable_count as usize).flat_map(|i| {
+                [
+                    AssemblerCodeBlock::new_comment(&format!("initialize local variable ({i})")),
+                    memory_access::load_constant_to_d(0),
+                    memory_access::write_d_to_stack(),
+                ]
+            }),
         )
         .chain(
             // 関数内のコマンド群
@@ -92,6 +172,7 @@ impl Function {
                     &self.name,
                     &mut comp_operator_counter,
                     &mut return_command_counter,
+                    compact,
                 )
             }),
         )
@@ -106,8 +187,17 @@ impl Command {
         function_name: &str,
         comp_operator_counter: &mut u32,
         return_command_counter: &mut u32,
+        compact: bool,
     ) -> Vec<AssemblerCodeBlock> {
         match self {
+            semantics::Command::Arithmetic(semantics::ArithmeticCommand::BinaryOperator(
+                semantics::BinaryOperator::Comparison(comp_op),
+            )) if compact => arithmetic::construct_compact_comparison(
+                comp_op,
+                module_name,
+                function_name,
+                return_command_counter,
+            ),
             semantics::Command::Arithmetic(arithmetic_command) => arithmetic::construct(
                 arithmetic_command,
                 module_name,
@@ -120,6 +210,15 @@ impl Command {
             semantics::Command::Label(label) => vec![program_flow::construct_label(label)],
             semantics::Command::Goto(label) => vec![program_flow::construct_goto(label)],
             semantics::Command::IfGoto(label) => program_flow::construct_if_goto(label),
+            semantics::Command::Call { name, args_count } if compact => {
+                function_call::construct_compact(
+                    name,
+                    args_count,
+                    module_name,
+                    function_name,
+                    return_command_counter,
+                )
+            }
             semantics::Command::Call { name, args_count } => function_call::construct(
                 name,
                 args_count,
@@ -127,7 +226,95 @@ impl Command {
                 function_name,
                 return_command_counter,
             ),
+            semantics::Command::Return if compact => function_return::construct_compact(),
             semantics::Command::Return => function_return::construct(),
         }
     }
 }
+
+// 変換したプログラムをエミュレータで停止するまで実行した RAM
+#[cfg(test)]
+fn run_translated(source: &str, bootstrap: Bootstrap, compact: bool) -> emulator::Computer {
+    let module =
+        Module::try_from_commands("Main", schema::vm::parse(source.to_string()).unwrap()).unwrap();
+    let code = assembler_code::genarate_assembler_code(
+        bootstrap_code(bootstrap, compact)
+            .into_iter()
+            .chain(module.into_code_blocks(compact))
+            .collect(),
+    );
+    let mut computer = emulator::Computer::from_commands(hack::parse(code).unwrap()).unwrap();
+    assert_eq!(computer.run(100_000), emulator::RunResult::Halted);
+    computer
+}
+
+#[test]
+fn test_compact_code_is_equivalent() {
+    // 比較結果を 1, 2, 4 のビットにまとめて static に入れる
+    let source = "
+        function Sys.init 0
+        push constant 3
+        push constant 5
+        call Main.compare 2
+        pop static 0
+        push constant 7
+        push constant 7
+        call Main.compare 2
+        pop static 1
+        push constant 9
+        push constant 2
+        call Main.compare 2
+        pop static 2
+        label END
+        goto END
+        function Main.compare 0
+        push argument 0
+        push argument 1
+        lt
+        push constant 1
+        and
+        push argument 0
+        push argument 1
+        eq
+        push constant 2
+        and
+        add
+        push argument 0
+        push argument 1
+        gt
+        push constant 4
+        and
+        add
+        return
+    ";
+    let plain = run_translated(source, Bootstrap::default(), false);
+    let compact = run_translated(source, Bootstrap::default(), true);
+    assert_eq!(plain.ram_slice(16, 3), [1, 2, 4]);
+    // SP, LCL, ARG, THIS, THAT と static
+    assert_eq!(plain.ram_slice(0, 5), compact.ram_slice(0, 5));
+    assert_eq!(plain.ram_slice(16, 3), compact.ram_slice(16, 3));
+}
+
+#[test]
+fn test_bootstrap() {
+    let source = "function Sys.init 0\nlabel END\ngoto END\n";
+    for compact in [false, true] {
+        // SP, LCL, ARG
+        let computer = run_translated(source, Bootstrap::Call { sp: 256 }, compact);
+        assert_eq!(computer.ram_slice(0, 3), [261, 261, 256]);
+        let computer = run_translated(source, Bootstrap::Call { sp: 300 }, compact);
+        assert_eq!(computer.ram_slice(0, 3), [305, 305, 300]);
+        let computer = run_translated(source, Bootstrap::Jump { sp: 300 }, compact);
+        assert_eq!(computer.ram(0), 300);
+        assert!(bootstrap_code(Bootstrap::None, compact).is_empty());
+    }
+
+    // Sys.init から戻っても後ろの共通ルーチンや関数へ進まずに止まる
+    let source =
+        "function Sys.init 0\npush constant 5\nreturn\nfunction Main.main 0\ncall Main.main 0\n";
+    for compact in [false, true] {
+        let computer = run_translated(source, Bootstrap::Call { sp: 256 }, compact);
+        assert_eq!(computer.ram(0), 257);
+        assert_eq!(computer.ram(256), 5);
+    }
+}
@@ -1,4 +1,7 @@
 use super::assembler_code::AssemblerCodeBlock;
+use super::function_call::return_label;
+use super::memory_access::{load_symbol_value_to_d, write_d_to_symbol};
+use super::program_flow::{construct_goto, jump_to_address_in_symbol};
 use crate::semantics;
 use schema::hack;
 
@@ -29,6 +32,62 @@ pub(super) fn construct(
     }
 }
 
+// リターンアドレスを D に入れて比較演算子の共通ルーチンへジャンプする (--compact)
+// リターンアドレスのラベルは call と同じ名前の付け方にする
+pub(super) fn construct_compact_comparison(
+    comp_op: semantics::BinaryComparisonOperator,
+    module_name: &str,
+    function_name: &str,
+    return_command_counter: &mut u32,
+) -> Vec<AssemblerCodeBlock> {
+    let return_label = return_label(module_name, function_name, return_command_counter);
+    vec![
+        AssemblerCodeBlock::new_header_comment("Arithmetic command (Binary Operator)"),
+        load_symbol_value_to_d(return_label.clone()),
+        construct_goto(comparison_routine_label(&comp_op).to_string()),
+        AssemblerCodeBlock::new(
+            "return_address_label",
+            &[hack::Command::L(hack::Symbol::new(&return_label))],
+        ),
+    ]
+}
+
+// --compact で共通化した比較演算子のルーチンのラベル
+fn comparison_routine_label(operator: &semantics::BinaryComparisonOperator) -> &'static str {
+    match operator {
+        semantics::BinaryComparisonOperator::Equal => "VM$EQ",
+        semantics::BinaryComparisonOperator::GreaterThan => "VM$GT",
+        semantics::BinaryComparisonOperator::LessThan => "VM$LT",
+    }
+}
+
+// 比較演算子 (eq, gt, lt) ごとの共通ルーチン
+// D のリターンアドレスを R15 に退避し、スタックの x, y を比較した結果を書き込んで戻る
+pub(super) fn comparison_routines() -> Vec<AssemblerCodeBlock> {
+    [
+        semantics::BinaryComparisonOperator::Equal,
+        semantics::BinaryComparisonOperator::GreaterThan,
+        semantics::BinaryComparisonOperator::LessThan,
+    ]
+    .into_iter()
+    .flat_map(|operator| {
+        let label = comparison_routine_label(&operator);
+        vec![
+            AssemblerCodeBlock::new_header_comment(&format!("{label} routine")),
+            AssemblerCodeBlock::new(
+                "define comparison routine label",
+                &[hack::Command::L(hack::Symbol::new(label))],
+            ),
+            write_d_to_symbol("R15"),
+            load_argxy_to_d_and_a(),
+            exec_binary_comparison_operator(operator, "VM", label, &mut 0),
+            write_binary_result_to_stack(),
+            jump_to_address_in_symbol("R15"),
+        ]
+    })
+    .collect()
+}
+
 // スタックにある1変数関数の引数 x をDレジスタにロードする
 fn load_argx_to_d() -> AssemblerCodeBlock {
     AssemblerCodeBlock::new(
@@ -144,7 +203,7 @@ fn exec_binary_operator(
     operator: semantics::BinaryOperator,
     module_name: &str,
     function_name: &str,
-    comp_operator_counter: &u32,
+    comp_operator_counter: &mut u32,
 ) -> AssemblerCodeBlock {
     match operator {
         semantics::BinaryOperator::Mathmatical(math_op) => {
@@ -180,9 +239,10 @@ fn exec_binary_comparison_operator(
     operator: semantics::BinaryComparisonOperator,
     module_name: &str,
     function_name: &str,
-    comp_operator_counter: &u32,
+    comp_operator_counter: &mut u32,
 ) -> AssemblerCodeBlock {
     let unique_path = format!("{module_name}.{function_name}.{comp_operator_counter}");
+    *comp_operator_counter += 1;
     let true_label = format!("RETURN_TRUE_{}", unique_path);
     let false_label = format!("RETURN_FALSE_{}", unique_path);
 
@@ -287,3 +347,32 @@ fn write_binary_result_to_stack() -> AssemblerCodeBlock {
         ],
     )
 }
+
+#[test]
+fn test_comparison_labels_are_unique() {
+    let mut comp_operator_counter = 0;
+    let labels: Vec<String> = [
+        semantics::BinaryComparisonOperator::Equal,
+        semantics::BinaryComparisonOperator::LessThan,
+    ]
+    .into_iter()
+    .flat_map(|operator| {
+        exec_binary_comparison_operator(operator, "Main", "Main.main", &mut comp_operator_counter)
+            .commands
+    })
+    .filter_map(|command| match command {
+        hack::Command::L(label) => Some(label.get().to_string()),
+        _ => None,
+    })
+    .collect();
+    assert_eq!(
+        labels,
+        vec![
+            "RETURN_TRUE_Main.Main.main.0",
+            "RETURN_FALSE_Main.Main.main.0",
+            "RETURN_TRUE_Main.Main.main.1",
+            "RETURN_FALSE_Main.Main.main.1",
+        ]
+    );
+    assert_eq!(comp_operator_counter, 2);
+}
@@ -1,7 +1,8 @@
+use assembler::optimizer::{self, OptimizeReport};
 use schema::hack;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct AssemblerCodeBlock {
+pub struct AssemblerCodeBlock {
     pub comment: Option<AssemblerCodeComment>,
     pub commands: Vec<hack::Command>,
 }
@@ -49,7 +50,7 @@ impl AssemblerCodeLine {
     pub fn into_code_str(self) -> String {
         match self {
             Self::Command(command, comment) => {
-                command_to_code(command)
+                command.to_string()
                     + &comment
                         .map(|comment| format!(" {}", comment.to_str()))
                         .unwrap_or_default()
@@ -59,7 +60,55 @@ impl AssemblerCodeLine {
     }
 }
 
-pub(crate) fn genarate_assembler_code(blocks: Vec<AssemblerCodeBlock>) -> String {
+// 全ブロックのコマンドをまとめて最適化し、各コマンドを元のブロックに戻す
+// (コマンドが全て取り除かれたブロックはコメントごと取り除く)
+pub fn optimize_code_blocks(
+    blocks: Vec<AssemblerCodeBlock>,
+) -> (Vec<AssemblerCodeBlock>, OptimizeReport) {
+    let block_indices: Vec<usize> = blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(index, block)| std::iter::repeat_n(index, block.commands.len()))
+        .collect();
+    let commands: Vec<hack::Command> = blocks
+        .iter()
+        .flat_map(|block| block.commands.iter().cloned())
+        .collect();
+    let optimized = optimizer::optimize(&commands);
+
+    let mut optimized_blocks: Vec<AssemblerCodeBlock> = blocks
+        .iter()
+        .map(|block| AssemblerCodeBlock {
+            comment: block.comment.clone(),
+            commands: Vec::new(),
+        })
+        .collect();
+    for (origin, command) in optimized.origins.into_iter().zip(optimized.commands) {
+        optimized_blocks[block_indices[origin]]
+            .commands
+            .push(command);
+    }
+    let optimized_blocks = optimized_blocks
+        .into_iter()
+        .zip(blocks)
+        .filter(|(optimized_block, block)| {
+            block.commands.is_empty() || !optimized_block.commands.is_empty()
+        })
+        .map(|(optimized_block, _)| optimized_block)
+        .collect();
+    (optimized_blocks, optimized.report)
+}
+
+// ROM に置かれる命令 (A命令と C命令) の数
+pub fn count_rom_words(blocks: &[AssemblerCodeBlock]) -> usize {
+    blocks
+        .iter()
+        .flat_map(|block| &block.commands)
+        .filter(|command| matches!(command, hack::Command::A(_) | hack::Command::C(_)))
+        .count()
+}
+
+pub fn genarate_assembler_code(blocks: Vec<AssemblerCodeBlock>) -> String {
     construct_code_lines(blocks)
         .into_iter()
         .map(AssemblerCodeLine::into_code_str)
@@ -91,84 +140,3 @@ fn construct_code_lines(blocks: Vec<AssemblerCodeBlock>) -> Vec<AssemblerCodeLin
     }
     lines
 }
-
-fn command_to_code(command: hack::Command) -> String {
-    match command {
-        hack::Command::A(a_command) => match a_command {
-            hack::ACommand::Address(value) => format!("@{}", value),
-            hack::ACommand::Symbol(symbol) => format!("@{}", symbol.get()),
-        },
-        hack::Command::C(c_command) => [
-            c_command
-                .dest
-                .map(|d| format!("{}=", dest(d)))
-                .unwrap_or_default(),
-            comp(c_command.comp).to_string(),
-            c_command
-                .jump
-                .map(|j| format!(";{}", jump(j)))
-                .unwrap_or_default(),
-        ]
-        .concat(),
-        hack::Command::L(symbol) => format!("({})", symbol.get()),
-    }
-}
-
-fn dest(mnemonic: hack::DestMnemonic) -> &'static str {
-    match mnemonic {
-        hack::DestMnemonic::Null => "null",
-        hack::DestMnemonic::M => "M",
-        hack::DestMnemonic::D => "D",
-        hack::DestMnemonic::MD => "MD",
-        hack::DestMnemonic::A => "A",
-        hack::DestMnemonic::AM => "AM",
-        hack::DestMnemonic::AD => "AD",
-        hack::DestMnemonic::AMD => "AMD",
-    }
-}
-
-fn comp(mnemonic: hack::CompMnemonic) -> &'static str {
-    match mnemonic {
-        hack::CompMnemonic::Zero => "0",
-        hack::CompMnemonic::One => "1",
-        hack::CompMnemonic::MinusOne => "-1",
-        hack::CompMnemonic::D => "D",
-        hack::CompMnemonic::A => "A",
-        hack::CompMnemonic::NegateD => "!D",
-        hack::CompMnemonic::NegateA => "!A",
-        hack::CompMnemonic::MinusD => "-D",
-        hack::CompMnemonic::MinusA => "-A",
-        hack::CompMnemonic::DPlusOne => "D+1",
-        hack::CompMnemonic::APlusOne => "A+1",
-        hack::CompMnemonic::DMinusOne => "D-1",
-        hack::CompMnemonic::AMinusOne => "A-1",
-        hack::CompMnemonic::DPlusA => "D+A",
-        hack::CompMnemonic::DMinusA => "D-A",
-        hack::CompMnemonic::AMinusD => "A-D",
-        hack::CompMnemonic::DAndA => "D&A",
-        hack::CompMnemonic::DOrA => "D|A",
-        hack::CompMnemonic::M => "M",
-        hack::CompMnemonic::NegateM => "!M",
-        hack::CompMnemonic::MinusM => "-M",
-        hack::CompMnemonic::MPlusOne => "M+1",
-        hack::CompMnemonic::MMinusOne => "M-1",
-        hack::CompMnemonic::DPlusM => "D+M",
-        hack::CompMnemonic::DMinusM => "D-M",
-        hack::CompMnemonic::MMinusD => "M-D",
-        hack::CompMnemonic::DAndM => "D&M",
-        hack::CompMnemonic::DOrM => "D|M",
-    }
-}
-
-fn jump(mnemonic: hack::JumpMnemonic) -> &'static str {
-    match mnemonic {
-        hack::JumpMnemonic::Null => "null",
-        hack::JumpMnemonic::JGT => "JGT",
-        hack::JumpMnemonic::JEQ => "JEQ",
-        hack::JumpMnemonic::JGE => "JGE",
-        hack::JumpMnemonic::JLT => "JLT",
-        hack::JumpMnemonic::JNE => "JNE",
-        hack::JumpMnemonic::JLE => "JLE",
-        hack::JumpMnemonic::JMP => "JMP",
-    }
-}
@@ -1,9 +1,14 @@
 use super::assembler_code::AssemblerCodeBlock;
 use super::memory_access::{
-    load_symbol_value_to_d, load_value_to_d_by_symbol_address, write_d_to_stack,
+    load_constant_to_d, load_symbol_value_to_d, load_value_to_d_by_symbol_address,
+    write_d_to_stack, write_d_to_symbol,
 };
+use super::program_flow::{construct_goto, jump_to_address_in_symbol};
 use schema::hack;
 
+// --compact で共通化した呼び出しルーチンのラベル
+pub(super) const CALL_ROUTINE_LABEL: &str = "VM$CALL";
+
 pub(super) fn construct(
     called_function_name: String,
     args_count: u16,
@@ -11,10 +16,11 @@ pub(super) fn construct(
     current_function_name: &str,
     return_command_counter: &mut u32,
 ) -> Vec<AssemblerCodeBlock> {
-    let return_label = format!(
-        "return_address_{current_module_name}.{current_function_name}.{return_command_counter}"
+    let return_label = return_label(
+        current_module_name,
+        current_function_name,
+        return_command_counter,
     );
-    *return_command_counter += 1;
 
     [
         vec![AssemblerCodeBlock::new_header_comment(&format!(
@@ -28,21 +34,114 @@ pub(super) fn construct(
     ]
     .into_iter()
     .flatten()
-    .chain(
-        [
-            move_arg_for_called_function(args_count),
-            move_lcl_for_called_function(),
-            super::program_flow::construct_goto(called_function_name),
+    .chain([
+        move_arg_for_called_function(args_count),
+        move_lcl_for_called_function(),
+        construct_goto(called_function_name),
+        AssemblerCodeBlock::new(
+            "return_address_label",
+            &[hack::Command::L(hack::Symbol::new(&return_label))],
+        ),
+    ])
+    .collect()
+}
+
+// 引数の数 + 5 を R13 に、呼び出す関数のアドレスを R14 に、リターンアドレスを D に入れて
+// 共通の呼び出しルーチンへジャンプする
+pub(super) fn construct_compact(
+    called_function_name: String,
+    args_count: u16,
+    current_module_name: &str,
+    current_function_name: &str,
+    return_command_counter: &mut u32,
+) -> Vec<AssemblerCodeBlock> {
+    let return_label = return_label(
+        current_module_name,
+        current_function_name,
+        return_command_counter,
+    );
+    vec![
+        AssemblerCodeBlock::new_header_comment(&format!("call {called_function_name}")),
+        load_constant_to_d(args_count + 5),
+        write_d_to_symbol("R13"),
+        load_symbol_value_to_d(called_function_name),
+        write_d_to_symbol("R14"),
+        load_symbol_value_to_d(return_label.clone()),
+        construct_goto(CALL_ROUTINE_LABEL.to_string()),
+        AssemblerCodeBlock::new(
+            "return_address_label",
+            &[hack::Command::L(hack::Symbol::new(&return_label))],
+        ),
+    ]
+}
+
+// 共通の呼び出しルーチン
+// リターンアドレスと呼び出し元の LCL, ARG, THIS, THAT を積み、ARG と LCL を設定して関数へジャンプする
+pub(super) fn routine() -> Vec<AssemblerCodeBlock> {
+    [
+        vec![
+            AssemblerCodeBlock::new_header_comment("call routine"),
             AssemblerCodeBlock::new(
-                "return_address_label",
-                &[hack::Command::L(hack::Symbol::new(&return_label))],
+                "define call routine label",
+                &[hack::Command::L(hack::Symbol::new(CALL_ROUTINE_LABEL))],
             ),
-        ]
-        .into_iter(),
-    )
+            write_d_to_stack(),
+        ],
+        push_symbol_referencing_value_to_stack("LCL"),
+        push_symbol_referencing_value_to_stack("ARG"),
+        push_symbol_referencing_value_to_stack("THIS"),
+        push_symbol_referencing_value_to_stack("THAT"),
+    ]
+    .into_iter()
+    .flatten()
+    .chain([
+        AssemblerCodeBlock::new(
+            "set ARG = SP-R13",
+            &[
+                // @SP
+                // D=M
+                // @R13
+                // D=D-M
+                // @ARG
+                // M=D
+                hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("SP"))),
+                hack::Command::C(hack::CCommand {
+                    dest: Some(hack::DestMnemonic::D),
+                    comp: hack::CompMnemonic::M,
+                    jump: None,
+                }),
+                hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("R13"))),
+                hack::Command::C(hack::CCommand {
+                    dest: Some(hack::DestMnemonic::D),
+                    comp: hack::CompMnemonic::DMinusM,
+                    jump: None,
+                }),
+                hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("ARG"))),
+                hack::Command::C(hack::CCommand {
+                    dest: Some(hack::DestMnemonic::M),
+                    comp: hack::CompMnemonic::D,
+                    jump: None,
+                }),
+            ],
+        ),
+        move_lcl_for_called_function(),
+        jump_to_address_in_symbol("R14"),
+    ])
     .collect()
 }
 
+pub(super) fn return_label(
+    current_module_name: &str,
+    current_function_name: &str,
+    return_command_counter: &mut u32,
+) -> String {
+    let return_label = format!(
+        "return_address_{current_module_name}.{current_function_name}.{return_command_counter}"
+    );
+    *return_command_counter += 1;
+    return_label
+}
+
 fn push_symbol_referencing_value_to_stack(symbol_name: &str) -> Vec<AssemblerCodeBlock> {
     vec![
         load_value_to_d_by_symbol_address(symbol_name.to_string()),
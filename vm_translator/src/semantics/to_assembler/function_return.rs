@@ -1,7 +1,33 @@
 use super::assembler_code::AssemblerCodeBlock;
 use super::memory_access::{load_value_to_d_by_symbol_address, pop_to_address_written_in_d};
+use super::program_flow::construct_goto;
 use schema::hack;
 
+// --compact で共通化したリターンルーチンのラベル
+const RETURN_ROUTINE_LABEL: &str = "VM$RETURN";
+
+// 共通のリターンルーチンへジャンプする
+pub(super) fn construct_compact() -> Vec<AssemblerCodeBlock> {
+    vec![
+        AssemblerCodeBlock::new_header_comment("return"),
+        construct_goto(RETURN_ROUTINE_LABEL.to_string()),
+    ]
+}
+
+// 共通のリターンルーチン（各 return の命令列と同じ）
+pub(super) fn routine() -> Vec<AssemblerCodeBlock> {
+    [
+        AssemblerCodeBlock::new_header_comment("return routine"),
+        AssemblerCodeBlock::new(
+            "define return routine label",
+            &[hack::Command::L(hack::Symbol::new(RETURN_ROUTINE_LABEL))],
+        ),
+    ]
+    .into_iter()
+    .chain(construct())
+    .collect()
+}
+
 pub(super) fn construct() -> Vec<AssemblerCodeBlock> {
     std::iter::once(AssemblerCodeBlock::new(
         "return from current function",
@@ -48,39 +74,35 @@ pub(super) fn construct() -> Vec<AssemblerCodeBlock> {
             }),
         ],
     ))
-    .into_iter()
-    .chain(
-        [
-            // ARG のアドレスにこの関数の戻り値（Stackの末尾にある）をいれる
-            load_value_to_d_by_symbol_address("ARG".to_string()),
-            pop_to_address_written_in_d(),
-            set_sp_arg_plus_one(),
-            restore_caller_value("THAT", "R14", -1),
-            restore_caller_value("THIS", "R14", -2),
-            restore_caller_value("ARG", "R14", -3),
-            restore_caller_value("LCL", "R14", -4),
-            AssemblerCodeBlock::new(
-                "jump to caller",
-                &[
-                    // @R15
-                    // A=M
-                    // 0;JMP
-                    hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("R15"))),
-                    hack::Command::C(hack::CCommand {
-                        dest: Some(hack::DestMnemonic::A),
-                        comp: hack::CompMnemonic::M,
-                        jump: None,
-                    }),
-                    hack::Command::C(hack::CCommand {
-                        dest: None,
-                        comp: hack::CompMnemonic::Zero,
-                        jump: Some(hack::JumpMnemonic::JMP),
-                    }),
-                ],
-            ),
-        ]
-        .into_iter(),
-    )
+    .chain([
+        // ARG のアドレスにこの関数の戻り値（Stackの末尾にある）をいれる
+        load_value_to_d_by_symbol_address("ARG".to_string()),
+        pop_to_address_written_in_d(),
+        set_sp_arg_plus_one(),
+        restore_caller_value("THAT", "R14", -1),
+        restore_caller_value("THIS", "R14", -2),
+        restore_caller_value("ARG", "R14", -3),
+        restore_caller_value("LCL", "R14", -4),
+        AssemblerCodeBlock::new(
+            "jump to caller",
+            &[
+                // @R15
+                // A=M
+                // 0;JMP
+                hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new("R15"))),
+                hack::Command::C(hack::CCommand {
+                    dest: Some(hack::DestMnemonic::A),
+                    comp: hack::CompMnemonic::M,
+                    jump: None,
+                }),
+                hack::Command::C(hack::CCommand {
+                    dest: None,
+                    comp: hack::CompMnemonic::Zero,
+                    jump: Some(hack::JumpMnemonic::JMP),
+                }),
+            ],
+        ),
+    ])
     .collect()
 }
 
@@ -284,6 +284,23 @@ fn load_indirect_address_to_d(
     )
 }
 
+// Dレジスタの値をシンボルのアドレスに書き込む
+pub(crate) fn write_d_to_symbol(symbol_name: &str) -> AssemblerCodeBlock {
+    AssemblerCodeBlock::new(
+        &format!("write D to {symbol_name}"),
+        &[
+            // @symbol_name
+            // M=D
+            hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new(symbol_name))),
+            hack::Command::C(hack::CCommand {
+                dest: Some(hack::DestMnemonic::M),
+                comp: hack::CompMnemonic::D,
+                jump: None,
+            }),
+        ],
+    )
+}
+
 // Dレジスタに保存されたアドレスのメモリ位置にStackから値をPopする
 pub(crate) fn pop_to_address_written_in_d() -> AssemblerCodeBlock {
     AssemblerCodeBlock::new(
@@ -27,6 +27,29 @@ pub(super) fn construct_goto(label: String) -> AssemblerCodeBlock {
     )
 }
 
+// シンボルのアドレスに書き込まれたアドレスへジャンプする
+pub(super) fn jump_to_address_in_symbol(symbol_name: &str) -> AssemblerCodeBlock {
+    AssemblerCodeBlock::new(
+        &format!("jump to address in {symbol_name}"),
+        &[
+            // @symbol_name
+            // A=M
+            // 0;JMP
+            hack::Command::A(hack::ACommand::Symbol(hack::Symbol::new(symbol_name))),
+            hack::Command::C(hack::CCommand {
+                dest: Some(hack::DestMnemonic::A),
+                comp: hack::CompMnemonic::M,
+                jump: None,
+            }),
+            hack::Command::C(hack::CCommand {
+                dest: None,
+                comp: hack::CompMnemonic::Zero,
+                jump: Some(hack::JumpMnemonic::JMP),
+            }),
+        ],
+    )
+}
+
 pub(super) fn construct_if_goto(label: String) -> Vec<AssemblerCodeBlock> {
     use super::memory_access::pop_to_address_written_in_d;
     vec![